@@ -3,9 +3,17 @@ use std::collections::HashMap;
 pub(crate) mod llm;
 pub(crate) mod chat;
 pub(crate) mod ollama;
+pub(crate) mod openai;
 pub(crate) mod reader;
+pub(crate) mod structured_output;
+pub(crate) mod tools;
+pub(crate) mod agent;
+pub(crate) mod context;
+pub(crate) mod title;
+pub(crate) mod modelfile;
 
 use crate::backend::ollama::SharedOllamaBackend;
+use crate::backend::openai::SharedOpenAiBackend;
 use crate::backend::llm::SharedBackend;
 use crate::settings::Settings;
 
@@ -25,5 +33,14 @@ pub fn build_backend_store(settings: &Settings) -> BackendStore {
     }
     backends.insert(backend_name, ollama);
 
+    // OpenAI compatible backend
+    let openai = SharedOpenAiBackend::new(settings.openai_url(), settings.openai_api_key());
+    let backend_name: String;
+    {
+        let backend = openai.blocking_read();
+        backend_name = backend.name().to_owned();
+    }
+    backends.insert(backend_name, openai);
+
     return backends;
 }
@@ -0,0 +1,220 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::{info, warn};
+use tokio::sync::{mpsc::{channel, Receiver, Sender}, Mutex};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    backend::{
+        chat::{ChatMessage, ChatResponse, ToolCall},
+        context::{fit_context, ContextReport},
+        llm::{GenerationOptions, PromptResponse, SharedModel},
+        tools::SharedToolRegistry,
+    },
+    errors::{self, ErrorKind},
+};
+
+/// Default for the maximum number of completions of a single prompt.
+pub const DEFAULT_MAX_TOOL_STEPS: u32 = 8;
+
+/// Wraps the [PromptResponse] of a prompt offering tools and
+/// runs the tool calls requested by the model.
+/// The results are passed back to the model, which is prompted
+/// again until it answers without calling tools or `max_steps`
+/// completions have been made.
+///
+/// Every step is streamed: Tool results are send as responses with a
+/// [crate::backend::chat::Role::Tool] message and only the last
+/// response of the final step is done.
+pub struct AgentPromptResponse {
+    first: Option<Box<dyn PromptResponse>>,
+    model: SharedModel,
+    registry: SharedToolRegistry,
+    messages: Vec<ChatMessage>,
+    think: Option<bool>,
+    options: GenerationOptions,
+    max_steps: u32,
+    // Response of the ongoing step
+    current: Arc<Mutex<Option<Box<dyn PromptResponse>>>>,
+    cancel: CancellationToken,
+}
+
+impl AgentPromptResponse {
+    /// `first` is the response of the initial prompt made with the
+    /// tools of `options`. `messages` are the history of that prompt
+    /// followed by the prompted message.
+    pub fn new(
+        first: Box<dyn PromptResponse>,
+        model: SharedModel,
+        registry: SharedToolRegistry,
+        messages: Vec<ChatMessage>,
+        think: Option<bool>,
+        options: GenerationOptions,
+        max_steps: u32,
+    ) -> Self {
+        Self {
+            first: Some(first),
+            model,
+            registry,
+            messages,
+            think,
+            options,
+            max_steps: max_steps.max(1),
+            current: Arc::new(Mutex::new(None)),
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+
+/// State of the loop, moved into the task running it.
+struct AgentLoop {
+    model: SharedModel,
+    registry: SharedToolRegistry,
+    messages: Vec<ChatMessage>,
+    think: Option<bool>,
+    options: GenerationOptions,
+    max_steps: u32,
+    current: Arc<Mutex<Option<Box<dyn PromptResponse>>>>,
+    cancel: CancellationToken,
+    sender: Sender<ChatResponse>,
+    // Shortening of the history for the ongoing step
+    context: Option<ContextReport>,
+}
+
+impl AgentLoop {
+    async fn run(mut self, mut response: Box<dyn PromptResponse>) {
+        for step in 1..=self.max_steps {
+            let prompts = match response.get_prompts() {
+                Ok(prompts) => prompts,
+                Err(e) => {
+                    let _ = self.sender.send(ChatResponse::error(ErrorKind::from(&e))).await;
+                    return;
+                }
+            };
+            {
+                let mut current = self.current.lock().await;
+                if self.cancel.is_cancelled() {
+                    response.abort().await;
+                    return;
+                }
+                *current = Some(response);
+            }
+
+            let Some(tool_calls) = self.forward_step(prompts).await else {
+                return;
+            };
+            if tool_calls.is_empty() {
+                return;
+            }
+            if step == self.max_steps {
+                warn!("Model still calls tools after {} steps", self.max_steps);
+                let _ = self.sender.send(ChatResponse::error(ErrorKind::ToolStepLimit(self.max_steps))).await;
+                return;
+            }
+
+            for call in tool_calls {
+                info!("Calling tool '{}'", call.function.name);
+                let result = tokio::select! {
+                    _ = self.cancel.cancelled() => return,
+                    result = self.registry.call(&call.function.name, call.function.arguments.clone()) => result
+                };
+                let message = ChatMessage::tool_result(&call, result);
+                self.messages.push(message.clone());
+                let tool_response = ChatResponse { done: false, message, error: None, stats: None, context: None };
+                if self.sender.send(tool_response).await.is_err() {
+                    return;
+                }
+            }
+
+            response = match self.prompt_again().await {
+                Ok(response) => response,
+                Err(e) => {
+                    let _ = self.sender.send(ChatResponse::error(ErrorKind::from(&e))).await;
+                    return;
+                }
+            };
+        }
+    }
+
+    /// Forwards the responses of a single step and records the answer.
+    /// Returns the tool calls of the model or [None] if the loop must end.
+    async fn forward_step(&mut self, mut prompts: Receiver<ChatResponse>) -> Option<Vec<ToolCall>> {
+        let mut content = String::new();
+        let mut thoughts = String::new();
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+
+        while let Some(mut prompt) = prompts.recv().await {
+            prompt.context = self.context.take().or(prompt.context);
+            if prompt.error.is_some() {
+                let _ = self.sender.send(prompt).await;
+                return None;
+            }
+
+            content.push_str(prompt.message.content());
+            thoughts.push_str(prompt.message.thoughts().unwrap_or_default());
+            tool_calls.extend_from_slice(prompt.message.tool_calls());
+
+            let done = prompt.done;
+            // The generation continues with the results of the tools
+            prompt.done = done && tool_calls.is_empty();
+            if self.sender.send(prompt).await.is_err() {
+                return None;
+            }
+            if done {
+                break;
+            }
+        }
+
+        if !tool_calls.is_empty() {
+            let thoughts = (!thoughts.is_empty()).then_some(thoughts);
+            self.messages.push(ChatMessage::with_tool_calls(content, thoughts, tool_calls.clone()));
+        }
+        Some(tool_calls)
+    }
+
+    async fn prompt_again(&mut self) -> Result<Box<dyn PromptResponse>, errors::Error> {
+        let content = self.messages.pop().ok_or(errors::internal("No message to prompt"))?;
+        let model = self.model.read().await;
+        // Results of tools may exceed the context window
+        let response = match fit_context(&*model, &content, self.messages.clone(), &self.options).await {
+            Ok((history, context)) => {
+                self.context = context;
+                model.prompt(content.clone(), &history, self.think, &self.options).await
+            },
+            Err(e) => Err(e)
+        };
+        self.messages.push(content);
+        response
+    }
+}
+
+#[async_trait]
+impl PromptResponse for AgentPromptResponse {
+    fn get_prompts(&mut self) -> Result<Receiver<ChatResponse>, errors::Error> {
+        let first = self.first.take().ok_or(errors::internal("Prompts have already been taken"))?;
+        let (sender, receiver) = channel(64);
+
+        let agent = AgentLoop {
+            model: self.model.clone(),
+            registry: self.registry.clone(),
+            messages: std::mem::take(&mut self.messages),
+            think: self.think,
+            options: self.options.clone(),
+            max_steps: self.max_steps,
+            current: self.current.clone(),
+            cancel: self.cancel.clone(),
+            sender,
+            context: None,
+        };
+        tokio::spawn(agent.run(first));
+        Ok(receiver)
+    }
+
+    async fn abort(&self) {
+        self.cancel.cancel();
+        if let Some(response) = self.current.lock().await.as_ref() {
+            response.abort().await;
+        }
+    }
+}
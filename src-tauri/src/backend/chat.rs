@@ -1,12 +1,15 @@
 use std::sync::Arc;
 
 use uuid::Uuid;
-use time::{format_description::well_known::Rfc3339, UtcDateTime};
+use time::{
+    format_description::{self, well_known::{Iso8601, Rfc2822, Rfc3339}},
+    Date, OffsetDateTime, PrimitiveDateTime, UtcDateTime,
+};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::errors;
+use crate::{backend::context::ContextReport, errors::{self, ErrorKind}};
 
-#[derive(Serialize, Clone, Deserialize, Debug, Default)]
+#[derive(Serialize, Clone, Deserialize, Debug, Default, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum Role {
     #[default]
@@ -18,12 +21,28 @@ pub enum Role {
 
 #[derive(Serialize, Clone, Deserialize, Debug, Default)]
 pub struct ChatMessageInner {
+    /// Identifies the message inside the tree of a [Chat].
+    /// Assigned when the message is added to a chat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<Uuid>,
+    /// Message this one answers, [None] for the first message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<Uuid>,
     role: Role,
     content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     images: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    thoughts: Option<String>
+    thoughts: Option<String>,
+    /// Tools the assistant wants to call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<ToolCall>>,
+    /// Name of the called tool of a [Role::Tool] message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_name: Option<String>,
+    /// ID of the answered [ToolCall] of a [Role::Tool] message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>
 }
 
 #[derive(Serialize, Clone, Deserialize, Debug, Default)]
@@ -32,27 +51,407 @@ pub struct ChatMessage {
     inner: Arc<ChatMessageInner>
 }
 
-#[derive(Serialize, Deserialize)]
+/// Call of a tool requested by a model.
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct ToolCall {
+    /// Only some backends identify their tool calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub function: ToolCallFunction
+}
+
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct ToolCallFunction {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: String) -> Self {
+        Self {
+            inner: Arc::new(ChatMessageInner {
+                role,
+                content,
+                ..Default::default()
+            })
+        }
+    }
+
+    /// Creates a message containing the thoughts
+    /// of a model besides its regular content.
+    pub fn with_thoughts(role: Role, content: String, thoughts: Option<String>) -> Self {
+        Self {
+            inner: Arc::new(ChatMessageInner {
+                role,
+                content,
+                thoughts,
+                ..Default::default()
+            })
+        }
+    }
+
+    /// Creates an assistant message requesting the given tool calls.
+    pub fn with_tool_calls(content: String, thoughts: Option<String>, tool_calls: Vec<ToolCall>) -> Self {
+        Self {
+            inner: Arc::new(ChatMessageInner {
+                role: Role::Assistant,
+                content,
+                thoughts,
+                tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                ..Default::default()
+            })
+        }
+    }
+
+    /// Creates a message containing the result of a tool call.
+    pub fn tool_result(call: &ToolCall, content: String) -> Self {
+        Self {
+            inner: Arc::new(ChatMessageInner {
+                role: Role::Tool,
+                content,
+                tool_name: Some(call.function.name.clone()),
+                tool_call_id: call.id.clone(),
+                ..Default::default()
+            })
+        }
+    }
+
+    /// Attaches base64 encoded images to the message.
+    pub fn with_images(mut self, images: Vec<String>) -> Self {
+        Arc::make_mut(&mut self.inner).images = (!images.is_empty()).then_some(images);
+        self
+    }
+
+    /// Places the message in the tree of a chat below `parent`.
+    /// A new ID is assigned if the message has none yet.
+    pub fn with_parent(mut self, parent: Option<Uuid>) -> Self {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.id.get_or_insert_with(Uuid::new_v4);
+        inner.parent = parent;
+        self
+    }
+
+    pub fn id(&self) -> Option<Uuid> {
+        self.inner.id
+    }
+
+    pub fn parent(&self) -> Option<Uuid> {
+        self.inner.parent
+    }
+
+    pub fn role(&self) -> &Role {
+        &self.inner.role
+    }
+
+    pub fn content(&self) -> &str {
+        &self.inner.content
+    }
+
+    /// Base64 encoded images attached to the message.
+    pub fn images(&self) -> &[String] {
+        self.inner.images.as_deref().unwrap_or_default()
+    }
+
+    pub fn thoughts(&self) -> Option<&str> {
+        self.inner.thoughts.as_deref()
+    }
+
+    pub fn tool_calls(&self) -> &[ToolCall] {
+        self.inner.tool_calls.as_deref().unwrap_or_default()
+    }
+
+    pub fn tool_name(&self) -> Option<&str> {
+        self.inner.tool_name.as_deref()
+    }
+
+    pub fn tool_call_id(&self) -> Option<&str> {
+        self.inner.tool_call_id.as_deref()
+    }
+}
+
+#[derive(Serialize)]
 pub struct ChatResponse {
     pub done: bool,
-    pub message: ChatMessage
+    pub message: ChatMessage,
+    /// Set if the generation failed.
+    /// The response is the last one in this case.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorKind>,
+    /// Statistics of the generation.
+    /// Only set on the last response if the backend reports them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<GenerationStats>,
+    /// What has been removed from the history to fit the context window.
+    /// Only set on the first response if anything has been removed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<ContextReport>
+}
+
+impl ChatResponse {
+    /// Last response of a failed generation.
+    pub fn error(kind: ErrorKind) -> Self {
+        Self {
+            done: true,
+            message: ChatMessage::new(Role::Assistant, String::new()),
+            error: Some(kind),
+            stats: None,
+            context: None
+        }
+    }
+}
+
+/// Backend-neutral statistics of a finished generation.
+/// Durations are in nanoseconds. Backends may not report every value.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct GenerationStats {
+    /// Time spent for the whole request
+    pub total_duration: Option<u64>,
+    /// Time spent loading the model
+    pub load_duration: Option<u64>,
+    /// Number of tokens in the prompt
+    pub prompt_eval_count: Option<u64>,
+    /// Time spent processing the prompt
+    pub prompt_eval_duration: Option<u64>,
+    /// Number of generated tokens
+    pub eval_count: Option<u64>,
+    /// Time spent generating the response
+    pub eval_duration: Option<u64>,
+    /// Prompt processing speed
+    pub prompt_tokens_per_second: Option<f64>,
+    /// Generation speed
+    pub tokens_per_second: Option<f64>
+}
+
+impl GenerationStats {
+    /// Calculates the token rates from the counts and durations.
+    pub fn with_rates(mut self) -> Self {
+        self.prompt_tokens_per_second = tokens_per_second(self.prompt_eval_count, self.prompt_eval_duration)
+            .or(self.prompt_tokens_per_second);
+        self.tokens_per_second = tokens_per_second(self.eval_count, self.eval_duration)
+            .or(self.tokens_per_second);
+        self
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+fn tokens_per_second(count: Option<u64>, duration_ns: Option<u64>) -> Option<f64> {
+    match (count, duration_ns) {
+        (Some(count), Some(duration)) if duration > 0 => {
+            Some(count as f64 / (duration as f64 / 1_000_000_000.0))
+        },
+        _ => None
+    }
+}
+
+/// A conversation with a model.
+/// Regenerating a response or editing a message creates a new branch, so
+/// the history is a tree. Only the active branch is shown and sent to the model.
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Chat {
     pub uuid: Uuid,
     pub title: String,
+    /// Messages of all branches. Parents come before their children.
+    #[serde(default)]
     pub history: Vec<ChatMessage>,
+    /// Last message of the active branch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current: Option<Uuid>,
+    #[serde(alias = "created_at")]
     #[serde(deserialize_with = "parse_utc_datetime", serialize_with = "serialize_utc_datetime")]
-    pub created_at: UtcDateTime
+    pub created_at: UtcDateTime,
+    /// Knowledge bases attached to the chat
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub knowledge_bases: Vec<Uuid>
 }
 
+impl Chat {
+    /// Assigns IDs to messages without one. These come from older versions and
+    /// other tools with a flat history, so each one answers the message before it.
+    /// Without a current message, the last message becomes the current one.
+    pub fn normalize(&mut self) {
+        let mut previous: Option<Uuid> = None;
+        for message in &mut self.history {
+            if message.id().is_none() {
+                *message = message.clone().with_parent(previous);
+            }
+            previous = message.id();
+        }
+        if self.current.is_none() {
+            self.current = previous;
+        }
+    }
+
+    pub fn message(&self, id: &Uuid) -> Option<&ChatMessage> {
+        self.history.iter().find(|message| message.id().as_ref() == Some(id))
+    }
+
+    /// Messages from the first one to the current one.
+    pub fn active_path(&self) -> Vec<&ChatMessage> {
+        let mut path = Vec::new();
+        let mut id = self.current;
+        while let Some(message) = id.and_then(|id| self.message(&id)) {
+            path.push(message);
+            // Malformed trees could contain cycles
+            if path.len() > self.history.len() {
+                break;
+            }
+            id = message.parent();
+        }
+        path.reverse();
+        path
+    }
+
+    /// Adds `message` as an answer to `parent` and makes it the current message.
+    /// Returns the added message with its ID.
+    pub fn add_message(&mut self, parent: Option<Uuid>, message: ChatMessage) -> ChatMessage {
+        let message = message.with_parent(parent);
+        self.current = message.id();
+        self.history.push(message.clone());
+        message
+    }
+
+    /// Makes the branch containing `id` the active one.
+    /// The branch is followed to its latest message.
+    /// Returns false if the message doesn't exist.
+    pub fn switch_branch(&mut self, id: &Uuid) -> bool {
+        if self.message(id).is_none() {
+            return false;
+        }
+        let mut current = Some(*id);
+        // Bounded, as malformed trees could contain cycles
+        for _ in 0..self.history.len() {
+            let child = current.and_then(|id| self.history
+                .iter()
+                .rev()
+                .find(|message| message.parent() == Some(id)));
+            match child {
+                Some(child) => current = child.id(),
+                None => break
+            }
+        }
+        self.current = current;
+        true
+    }
+
+    /// The prompt which has been answered by the assistant message `id`:
+    /// the last user message before it and the history before that.
+    /// A regenerated answer becomes a sibling of the original one, so its parent is
+    /// the returned prompt.
+    pub fn prompt_of(&self, id: &Uuid) -> Option<(ChatMessage, Vec<ChatMessage>)> {
+        let mut path = Vec::new();
+        let mut current = self.message(id)?.parent();
+        while let Some(message) = current.and_then(|id| self.message(&id)) {
+            path.push(message.clone());
+            if path.len() > self.history.len() {
+                return None;
+            }
+            current = message.parent();
+        }
+        path.reverse();
+        let prompt_index = path.iter().rposition(|message| *message.role() == Role::User)?;
+        let prompt = path.remove(prompt_index);
+        path.truncate(prompt_index);
+        Some((prompt, path))
+    }
+}
+
+/// Images are stored as raw base64 without a MIME type.
+/// We guess the type using the magic number of the encoded data.
+pub fn image_data_url(image: &str) -> String {
+    let mime = if image.starts_with("/9j/") {
+        "image/jpeg"
+    } else if image.starts_with("R0lGOD") {
+        "image/gif"
+    } else if image.starts_with("UklGR") {
+        "image/webp"
+    } else {
+        "image/png"
+    };
+    format!("data:{mime};base64,{image}")
+}
+
+/// Timestamps are written as RFC 3339, but other tools and
+/// older files use all kinds of formats.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnyTimestamp {
+    Text(String),
+    Number(f64)
+}
+
+/// Deserializes RFC 3339 and most other common timestamps, see [parse_timestamp].
 pub fn parse_utc_datetime<'de, D>(deserializer: D) -> Result<UtcDateTime, D::Error>
 where D: Deserializer<'de>
 {
-    let utc_str = String::deserialize(deserializer)?;
-    UtcDateTime::parse(&utc_str, &Rfc3339).map_err(serde::de::Error::custom)
+    match AnyTimestamp::deserialize(deserializer)? {
+        AnyTimestamp::Text(text) => parse_timestamp(&text)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid timestamp '{text}'"))),
+        AnyTimestamp::Number(number) => timestamp_from_number(number)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid timestamp {number}")))
+    }
+}
+
+/// Parses RFC 3339, ISO 8601 and RFC 2822 timestamps as well as
+/// dates and times without an offset, which are assumed to be UTC.
+/// Numbers are taken as seconds or milliseconds since the epoch.
+pub fn parse_timestamp(text: &str) -> Option<UtcDateTime> {
+    let text = text.trim();
+    if let Ok(timestamp) = UtcDateTime::parse(text, &Rfc3339) {
+        return Some(timestamp);
+    }
+    if let Ok(timestamp) = OffsetDateTime::parse(text, &Iso8601::DEFAULT)
+        .or_else(|_| OffsetDateTime::parse(text, &Rfc2822)) {
+        return Some(timestamp.to_utc());
+    }
+    if let Ok(timestamp) = PrimitiveDateTime::parse(text, &Iso8601::DEFAULT) {
+        return Some(timestamp.as_utc());
+    }
+    const FORMATS: [&str; 4] = [
+        "[year]-[month]-[day] [hour]:[minute]:[second].[subsecond]",
+        "[year]-[month]-[day] [hour]:[minute]:[second]",
+        "[year]-[month]-[day] [hour]:[minute]",
+        "[year]-[month]-[day]"
+    ];
+    for format in FORMATS {
+        let Ok(format) = format_description::parse(format) else {
+            continue;
+        };
+        if let Ok(timestamp) = PrimitiveDateTime::parse(text, &format) {
+            return Some(timestamp.as_utc());
+        }
+        if let Ok(date) = Date::parse(text, &format) {
+            return Some(date.midnight().as_utc());
+        }
+    }
+    text.parse().ok().and_then(timestamp_from_number)
+}
+
+/// Timestamp from seconds or, for large numbers, milliseconds since the epoch.
+pub fn timestamp_from_number(number: f64) -> Option<UtcDateTime> {
+    // Seconds would be in the year 5138
+    let micros = if number.abs() >= 1e11 { number * 1e3 } else { number * 1e6 };
+    let nanos = (micros.round() as i128).checked_mul(1000)?;
+    UtcDateTime::from_unix_timestamp_nanos(nanos).ok()
+}
+
+/// Like [parse_utc_datetime] for optional timestamps. Needs `#[serde(default)]` for missing ones.
+pub fn parse_optional_utc_datetime<'de, D>(deserializer: D) -> Result<Option<UtcDateTime>, D::Error>
+where D: Deserializer<'de>
+{
+    #[derive(Deserialize)]
+    struct Timestamp(#[serde(deserialize_with = "parse_utc_datetime")] UtcDateTime);
+
+    Ok(Option::<Timestamp>::deserialize(deserializer)?.map(|timestamp| timestamp.0))
+}
+
+pub fn serialize_optional_utc_datetime<S>(time: &Option<UtcDateTime>, serializer: S) -> Result<S::Ok, S::Error>
+where S: Serializer
+{
+    match time {
+        Some(time) => serialize_utc_datetime(time, serializer),
+        None => serializer.serialize_none()
+    }
 }
 
 pub fn serialize_utc_datetime<S>(time: &UtcDateTime, serializer: S) -> Result<S::Ok, S::Error>
@@ -66,3 +465,55 @@ pub fn timestamp_to_string(timestamp: &UtcDateTime) -> Result<String, errors::Er
 {
     timestamp.format(&Rfc3339).map_err(|e| errors::internal(e.to_string()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_timestamp, timestamp_from_number};
+
+    fn unix(text: &str) -> Option<i64> {
+        parse_timestamp(text).map(|timestamp| timestamp.unix_timestamp())
+    }
+
+    #[test]
+    fn parses_timestamps_with_offset() {
+        // 2024-05-01T12:30:00Z
+        const EXPECTED: Option<i64> = Some(1_714_566_600);
+        assert_eq!(unix("2024-05-01T12:30:00Z"), EXPECTED);
+        assert_eq!(unix("2024-05-01T14:30:00+02:00"), EXPECTED);
+        assert_eq!(unix(" 2024-05-01T12:30:00.250Z "), EXPECTED);
+        // ISO 8601, but not RFC 3339
+        assert_eq!(unix("20240501T123000Z"), EXPECTED);
+        assert_eq!(unix("2024-05-01T14:30+02"), EXPECTED);
+        assert_eq!(unix("Wed, 01 May 2024 12:30:00 +0000"), EXPECTED);
+    }
+
+    #[test]
+    fn parses_timestamps_without_offset_as_utc() {
+        assert_eq!(unix("2024-05-01T12:30:00"), Some(1_714_566_600));
+        assert_eq!(unix("2024-05-01 12:30:00"), Some(1_714_566_600));
+        assert_eq!(unix("2024-05-01 12:30:00.5"), Some(1_714_566_600));
+        assert_eq!(unix("2024-05-01 12:30"), Some(1_714_566_600));
+        assert_eq!(unix("2024-05-01"), Some(1_714_521_600));
+    }
+
+    #[test]
+    fn parses_seconds_and_milliseconds_since_the_epoch() {
+        assert_eq!(unix("1714566600"), Some(1_714_566_600));
+        assert_eq!(unix("1714566600000"), Some(1_714_566_600));
+        assert_eq!(unix("1714566600.5"), Some(1_714_566_600));
+
+        let timestamp = timestamp_from_number(1_714_566_600_250.0).unwrap();
+        assert_eq!((timestamp.unix_timestamp(), timestamp.millisecond()), (1_714_566_600, 250));
+        let timestamp = timestamp_from_number(1_714_566_600.25).unwrap();
+        assert_eq!((timestamp.unix_timestamp(), timestamp.millisecond()), (1_714_566_600, 250));
+        assert_eq!(timestamp_from_number(0.0).map(|t| t.unix_timestamp()), Some(0));
+    }
+
+    #[test]
+    fn rejects_invalid_timestamps() {
+        assert_eq!(unix("yesterday"), None);
+        assert_eq!(unix("2024-13-01"), None);
+        assert_eq!(unix(""), None);
+        assert!(timestamp_from_number(f64::MAX).is_none());
+    }
+}
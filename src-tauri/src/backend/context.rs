@@ -0,0 +1,627 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use time::UtcDateTime;
+use uuid::Uuid;
+
+use crate::{
+    backend::{
+        chat::{parse_utc_datetime, serialize_utc_datetime, ChatMessage, Role},
+        llm::{complete, GenerationOptions, Model},
+    },
+    errors::{Error, ErrorKind},
+};
+
+/// What to do with a history exceeding the context window of a model.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ContextPolicy {
+    /// Send the whole history and let the backend cut it off
+    Off,
+    /// Drop the oldest turns including system messages
+    DropOldest,
+    /// Drop the oldest turns but keep system messages
+    #[default]
+    PinSystem,
+    /// Replace the oldest turns with a summary written by the model
+    /// and keep system messages
+    Summarize,
+    /// Like [ContextPolicy::Summarize], but the summary is stored with the chat
+    /// and extended by later prompts, so turns are only summarized once.
+    /// Needs a stored chat, otherwise the same as [ContextPolicy::Summarize].
+    RollingSummary
+}
+
+impl ContextPolicy {
+    fn summarizes(&self) -> bool {
+        matches!(self, ContextPolicy::Summarize | ContextPolicy::RollingSummary)
+    }
+}
+
+/// Summary of the oldest turns of a chat, kept by [ContextPolicy::RollingSummary].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RollingSummary {
+    pub content: String,
+    /// Last message covered by the summary. The summary is only used
+    /// for histories containing this message, not for other branches.
+    pub through: Uuid,
+    #[serde(deserialize_with = "parse_utc_datetime", serialize_with = "serialize_utc_datetime")]
+    pub updated_at: UtcDateTime
+}
+
+/// What has been removed from a history to fit the context window.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextReport {
+    pub policy: ContextPolicy,
+    /// Tokens the model considers
+    pub context_length: u32,
+    /// Estimated tokens of the prompt including the whole history
+    pub estimated_tokens: usize,
+    /// Estimated tokens of the prompt which has been sent
+    pub sent_tokens: usize,
+    /// Number of messages which have not been sent
+    pub dropped_messages: usize,
+    /// Summary sent in place of the dropped messages with [ContextPolicy::Summarize]
+    pub summary: Option<String>
+}
+
+/// Tokens of the structure around every message like its role.
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+/// Images are resized to a fixed number of patches by most vision models.
+/// This is about the maximum of common models.
+const IMAGE_TOKENS: usize = 768;
+/// Tokens kept free for the answer if [GenerationOptions::max_tokens] is not set.
+const MAX_RESPONSE_RESERVE: u32 = 2048;
+/// Length of summaries of older turns.
+const SUMMARY_TOKENS: u32 = 512;
+/// Share of the context window a rolling summary frees for later turns.
+/// Summarizing more than necessary means the summary is not extended on every prompt.
+const ROLLING_SUMMARY_SHARE: usize = 2;
+
+const SUMMARY_INSTRUCTION: &str = "You summarize conversations between a user and an assistant.
+Keep facts, decisions, names, numbers and open questions which may matter later in the conversation.
+A conversation starting with a summary of earlier turns continues it, so include the facts of that summary.
+Write a concise summary in the language of the conversation without any introduction.";
+
+/// Rough number of tokens of `text`. Tokenizers differ between models, so this errs on the
+/// high side: English text has about four characters per token, other scripts often one.
+pub fn estimate_text_tokens(text: &str) -> usize {
+    let ascii = text.bytes().filter(u8::is_ascii).count();
+    let other = text.chars().filter(|c| !c.is_ascii()).count();
+    ascii.div_ceil(4) + other
+}
+
+/// Rough number of tokens of `message` in a prompt.
+pub fn estimate_tokens(message: &ChatMessage) -> usize {
+    let tool_calls = match message.tool_calls() {
+        [] => 0,
+        calls => estimate_text_tokens(&serde_json::to_string(calls).unwrap_or_default())
+    };
+    MESSAGE_OVERHEAD_TOKENS
+        + estimate_text_tokens(message.content())
+        + message.images().len() * IMAGE_TOKENS
+        + tool_calls
+}
+
+fn estimate_all(messages: &[ChatMessage]) -> usize {
+    messages.iter().map(estimate_tokens).sum()
+}
+
+/// Token budget of a prompt.
+struct Budget {
+    context_length: u32,
+    /// Tokens kept free for the answer
+    reserve: usize,
+    /// Tokens of the prompt and the tools, which can't be shortened
+    fixed: usize,
+    /// Tokens available for the whole prompt
+    limit: usize
+}
+
+impl Budget {
+    /// [None] if the context window is unknown or the policy is [ContextPolicy::Off].
+    fn new(model: &dyn Model, content: &ChatMessage, options: &GenerationOptions) -> Option<Self> {
+        if options.context_policy == Some(ContextPolicy::Off) {
+            return None;
+        }
+        let context_length = model.context_window(options)?;
+        let reserve = options.max_tokens.unwrap_or(MAX_RESPONSE_RESERVE.min(context_length / 4));
+        let tools: usize = options.tools
+            .iter()
+            .map(|tool| estimate_text_tokens(&tool.to_function().to_string()))
+            .sum();
+        Some(Self {
+            context_length,
+            reserve: reserve as usize,
+            fixed: estimate_tokens(content) + tools,
+            limit: context_length.saturating_sub(reserve) as usize
+        })
+    }
+
+    /// Tokens left for the history besides `pinned_tokens` which are always sent.
+    /// Fails if there are none.
+    fn available(&self, pinned_tokens: usize) -> Result<usize, Error> {
+        if self.fixed + pinned_tokens > self.limit {
+            return Err(Error::Generation(ErrorKind::ContextTooLong(format!(
+                "The prompt needs about {} tokens, but the model considers only {}",
+                self.fixed + pinned_tokens + self.reserve, self.context_length
+            ))));
+        }
+        Ok(self.limit - self.fixed - pinned_tokens)
+    }
+}
+
+/// Shortens `history` according to [GenerationOptions::context_policy],
+/// so the history, `content` and the answer fit into the context window of `model`.
+/// Whole turns starting with a user message are dropped, so tool calls keep their results.
+/// Returns the history to send and a report if anything has been removed.
+/// Fails if `content` and the kept system messages alone exceed the context window.
+pub async fn fit_context(
+    model: &dyn Model, content: &ChatMessage, history: Vec<ChatMessage>, options: &GenerationOptions
+) -> Result<(Vec<ChatMessage>, Option<ContextReport>), Error>
+{
+    let policy = options.context_policy.unwrap_or_default();
+    let Some(budget) = Budget::new(model, content, options) else {
+        return Ok((history, None));
+    };
+    let estimated_tokens = budget.fixed + estimate_all(&history);
+    if estimated_tokens <= budget.limit {
+        return Ok((history, None));
+    }
+
+    let pinned = |message: &ChatMessage| policy != ContextPolicy::DropOldest && *message.role() == Role::System;
+    let pinned_tokens: usize = history.iter().filter(|m| pinned(m)).map(estimate_tokens).sum();
+    let summary_tokens = if policy.summarizes() { SUMMARY_TOKENS as usize } else { 0 };
+    let available = budget.available(pinned_tokens + summary_tokens)?;
+
+    let turns: Vec<usize> = (0..history.len()).filter(|i| !pinned(&history[*i])).collect();
+    let dropped = drop_turns(&history, &turns, available);
+    let dropped_messages: Vec<ChatMessage> = turns[..dropped].iter().map(|i| history[*i].clone()).collect();
+
+    let summary = if policy.summarizes() && dropped > 0 {
+        Some(summarize(model, &dropped_messages, None, options).await?)
+    } else {
+        None
+    };
+    let first_kept = turns.get(dropped).copied().unwrap_or(history.len());
+    let messages = replace_turns(history, first_kept, pinned, summary.as_deref());
+
+    let report = ContextReport {
+        policy,
+        context_length: budget.context_length,
+        estimated_tokens,
+        sent_tokens: budget.fixed + estimate_all(&messages),
+        dropped_messages: dropped,
+        summary
+    };
+    info!(
+        "Dropped {} messages to fit the context of {} tokens (about {} of {} tokens sent)",
+        report.dropped_messages, report.context_length, report.sent_tokens, report.estimated_tokens
+    );
+    Ok((messages, Some(report)))
+}
+
+/// Fits `history` into the context window like [fit_context] with [ContextPolicy::RollingSummary].
+/// If it doesn't fit, the turns covered by `summary` are replaced with it. If the rest still doesn't fit,
+/// the oldest remaining turns are added to the summary.
+/// Returns the history to send, a report if anything has been removed and
+/// the extended summary, which should be stored with the chat.
+/// A summary not covering a message of `history` is ignored, e.g. of another branch.
+pub async fn fit_rolling_context(
+    model: &dyn Model, content: &ChatMessage, history: Vec<ChatMessage>, options: &GenerationOptions,
+    summary: Option<RollingSummary>
+) -> Result<(Vec<ChatMessage>, Option<ContextReport>, Option<RollingSummary>), Error>
+{
+    let Some(budget) = Budget::new(model, content, options) else {
+        return Ok((history, None, None));
+    };
+    let estimated_tokens = budget.fixed + estimate_all(&history);
+    if estimated_tokens <= budget.limit {
+        return Ok((history, None, None));
+    }
+    let covered = summary
+        .as_ref()
+        .and_then(|summary| history.iter().position(|message| message.id() == Some(summary.through)));
+    let summary = summary.filter(|_| covered.is_some());
+
+    let pinned = |message: &ChatMessage| *message.role() == Role::System;
+    let pinned_tokens: usize = history.iter().filter(|m| pinned(m)).map(estimate_tokens).sum();
+    let available = budget.available(pinned_tokens + SUMMARY_TOKENS as usize)?;
+
+    // Turns after the summary
+    let start = covered.map_or(0, |i| i + 1);
+    let turns: Vec<usize> = (start..history.len()).filter(|i| !pinned(&history[*i])).collect();
+    let kept_tokens: usize = turns.iter().map(|i| estimate_tokens(&history[*i])).sum();
+    let condensed = if kept_tokens > available {
+        drop_turns(&history, &turns, available / ROLLING_SUMMARY_SHARE)
+    } else {
+        0
+    };
+
+    let mut updated = None;
+    let mut content = summary.map(|summary| summary.content);
+    if condensed > 0 {
+        let messages: Vec<ChatMessage> = turns[..condensed].iter().map(|i| history[*i].clone()).collect();
+        let extended = summarize(model, &messages, content.as_deref(), options).await?;
+        // Unsaved messages have no ID yet, so the summary can't refer to them
+        updated = messages.last().and_then(ChatMessage::id).map(|through| RollingSummary {
+            content: extended.clone(),
+            through,
+            updated_at: UtcDateTime::now()
+        });
+        content = Some(extended);
+    }
+
+    let dropped = history[..start].iter().filter(|m| !pinned(m)).count() + condensed;
+    let first_kept = turns.get(condensed).copied().unwrap_or(history.len());
+    let messages = replace_turns(history, first_kept, pinned, content.as_deref());
+
+    let report = ContextReport {
+        policy: ContextPolicy::RollingSummary,
+        context_length: budget.context_length,
+        estimated_tokens,
+        sent_tokens: budget.fixed + estimate_all(&messages),
+        dropped_messages: dropped,
+        summary: content
+    };
+    info!(
+        "Summarized {} messages ({} new) to fit the context of {} tokens (about {} of {} tokens sent)",
+        report.dropped_messages, condensed, report.context_length, report.sent_tokens, report.estimated_tokens
+    );
+    Ok((messages, Some(report), updated))
+}
+
+/// Number of `turns`, indices into `history`, to drop from the start,
+/// so the rest takes at most `available` tokens.
+/// Only whole turns starting with a user message are dropped.
+fn drop_turns(history: &[ChatMessage], turns: &[usize], available: usize) -> usize {
+    let mut kept_tokens: usize = turns.iter().map(|i| estimate_tokens(&history[*i])).sum();
+    let mut dropped = 0;
+    while kept_tokens > available && dropped < turns.len() {
+        let next_turn = turns[dropped + 1..]
+            .iter()
+            .position(|i| *history[*i].role() == Role::User)
+            .map_or(turns.len(), |i| dropped + 1 + i);
+        kept_tokens -= turns[dropped..next_turn].iter().map(|i| estimate_tokens(&history[*i])).sum::<usize>();
+        dropped = next_turn;
+    }
+    dropped
+}
+
+/// Removes the messages before `first_kept` which are not `pinned`
+/// and puts `summary` in their place.
+fn replace_turns(
+    history: Vec<ChatMessage>, first_kept: usize, pinned: impl Fn(&ChatMessage) -> bool, summary: Option<&str>
+) -> Vec<ChatMessage>
+{
+    let count = history.len();
+    let mut messages = Vec::with_capacity(count + 1);
+    for (i, message) in history.into_iter().enumerate() {
+        if i == first_kept {
+            messages.extend(summary.map(summary_message));
+        }
+        if pinned(&message) || i >= first_kept {
+            messages.push(message);
+        }
+    }
+    if first_kept == count {
+        messages.extend(summary.map(summary_message));
+    }
+    messages
+}
+
+/// Message passing a summary of earlier turns to the model.
+pub fn summary_message(summary: &str) -> ChatMessage {
+    ChatMessage::new(Role::System, format!("Summary of the earlier conversation:\n\n{summary}"))
+}
+
+/// Asks `model` to summarize `messages`, continuing the `previous` summary of the turns before.
+/// `options` are those of the chat, so the model is not loaded again with another context length.
+/// Messages which don't fit into the context window are left out, the newest are kept.
+pub async fn summarize(
+    model: &dyn Model, messages: &[ChatMessage], previous: Option<&str>, options: &GenerationOptions
+) -> Result<String, Error>
+{
+    let context_length = model.context_window(options).unwrap_or(u32::MAX);
+    let available = context_length.saturating_sub(2 * SUMMARY_TOKENS) as usize;
+    let mut transcript: Vec<String> = Vec::new();
+    let mut tokens = previous.map_or(0, estimate_text_tokens);
+    for message in messages.iter().rev() {
+        let speaker = match message.role() {
+            Role::User => "User",
+            Role::Assistant => "Assistant",
+            // Results of tools and earlier summaries
+            Role::Tool | Role::System => "Context"
+        };
+        if message.content().trim().is_empty() {
+            continue;
+        }
+        tokens += estimate_tokens(message);
+        if tokens > available {
+            break;
+        }
+        transcript.push(format!("{speaker}: {}", message.content().trim()));
+    }
+    transcript.extend(previous.map(|summary| format!("Summary of the earlier conversation: {}", summary.trim())));
+    transcript.reverse();
+
+    let instruction = ChatMessage::new(Role::System, SUMMARY_INSTRUCTION.to_owned());
+    let content = ChatMessage::new(Role::User, format!("Conversation:\n\n{}", transcript.join("\n\n")));
+    let options = GenerationOptions {
+        temperature: Some(0.2),
+        max_tokens: Some(SUMMARY_TOKENS),
+        context_length: options.context_length,
+        keep_alive: options.keep_alive.clone(),
+        ..Default::default()
+    };
+    Ok(complete(model, content, &[instruction], &options).await?.trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use time::UtcDateTime;
+    use tokio::sync::mpsc::{channel, Receiver};
+    use uuid::Uuid;
+
+    use crate::{
+        backend::{
+            chat::{ChatMessage, ChatResponse, Role},
+            llm::{Embeddings, GenerationOptions, Model, ModelInfo, PromptResponse, RuntimeInfo, SharedBackend}
+        },
+        errors::{self, Error, ErrorKind}
+    };
+
+    use super::{
+        fit_context, fit_rolling_context, replace_turns, summary_message, ContextPolicy, RollingSummary
+    };
+
+    /// Model answering every prompt with "Summary".
+    struct StubModel {
+        info: ModelInfo,
+        /// Contents of all prompts
+        prompts: Mutex<Vec<String>>
+    }
+
+    impl StubModel {
+        fn new(context_length: u32) -> Self {
+            let info = ModelInfo { name: "stub".into(), context_length: Some(context_length), ..Default::default() };
+            Self { info, prompts: Mutex::new(Vec::new()) }
+        }
+    }
+
+    struct StubResponse(Option<Receiver<ChatResponse>>);
+
+    #[async_trait]
+    impl PromptResponse for StubResponse {
+        fn get_prompts(&mut self) -> Result<Receiver<ChatResponse>, Error> {
+            self.0.take().ok_or(errors::internal("Prompts already taken"))
+        }
+
+        async fn abort(&self) {}
+    }
+
+    #[async_trait]
+    impl Model for StubModel {
+        fn info(&self) -> &ModelInfo {
+            &self.info
+        }
+
+        fn backend(&self) -> Option<SharedBackend> {
+            None
+        }
+
+        async fn loaded(&self) -> Result<bool, Error> {
+            Ok(true)
+        }
+
+        async fn get_loaded_size(&self) -> Result<i64, Error> {
+            Ok(0)
+        }
+
+        async fn get_runtime_info(&self) -> Result<Option<RuntimeInfo>, Error> {
+            Ok(None)
+        }
+
+        async fn load(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn unload(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn prompt(
+            &self, content: ChatMessage, _history: &[ChatMessage], _think: Option<bool>, _options: &GenerationOptions
+        ) -> Result<Box<dyn PromptResponse>, Error>
+        {
+            self.prompts.lock().unwrap().push(content.content().to_owned());
+            let (sender, receiver) = channel(1);
+            let response = ChatResponse {
+                done: true,
+                message: ChatMessage::new(Role::Assistant, "Summary".into()),
+                error: None,
+                stats: None,
+                context: None
+            };
+            sender.send(response).await.unwrap();
+            Ok(Box::new(StubResponse(Some(receiver))))
+        }
+
+        async fn embed(&self, _input: &[String]) -> Result<Embeddings, Error> {
+            Err(errors::internal("Not supported"))
+        }
+    }
+
+    /// Message of about `tokens` tokens, answering `parent`.
+    fn message(role: Role, name: &str, tokens: usize, parent: Option<&ChatMessage>) -> ChatMessage {
+        let content = format!("{name:<width$}", width = (tokens - super::MESSAGE_OVERHEAD_TOKENS) * 4);
+        ChatMessage::new(role, content).with_parent(parent.and_then(ChatMessage::id))
+    }
+
+    /// A system message and two turns, the first one calling a tool.
+    /// Every message takes 300 tokens.
+    fn history() -> Vec<ChatMessage> {
+        let roles = [
+            (Role::System, "system"),
+            (Role::User, "question 1"),
+            (Role::Assistant, "tool call"),
+            (Role::Tool, "tool result"),
+            (Role::Assistant, "answer 1"),
+            (Role::User, "question 2"),
+            (Role::Assistant, "answer 2")
+        ];
+        let mut history: Vec<ChatMessage> = Vec::new();
+        for (role, name) in roles {
+            let message = message(role, name, 300, history.last());
+            history.push(message);
+        }
+        history
+    }
+
+    /// 100 tokens
+    fn content() -> ChatMessage {
+        message(Role::User, "question 3", 100, None)
+    }
+
+    fn options(policy: ContextPolicy) -> GenerationOptions {
+        // Nothing reserved for the answer, so the whole context window is available
+        GenerationOptions { context_policy: Some(policy), max_tokens: Some(0), ..Default::default() }
+    }
+
+    fn names(messages: &[ChatMessage]) -> Vec<&str> {
+        messages.iter().map(|message| message.content().trim()).collect()
+    }
+
+    #[tokio::test]
+    async fn keeps_histories_which_fit() {
+        let model = StubModel::new(2200);
+        let (messages, report) = fit_context(&model, &content(), history(), &options(ContextPolicy::PinSystem)).await.unwrap();
+        assert_eq!(messages.len(), 7);
+        assert!(report.is_none());
+    }
+
+    #[tokio::test]
+    async fn drops_whole_turns() {
+        // 1100 tokens are left for the first six messages
+        let model = StubModel::new(1500);
+        let (messages, report) = fit_context(&model, &content(), history(), &options(ContextPolicy::PinSystem)).await.unwrap();
+        assert_eq!(names(&messages), ["system", "question 2", "answer 2"]);
+
+        let report = report.unwrap();
+        assert_eq!((report.dropped_messages, report.estimated_tokens, report.sent_tokens), (4, 2200, 1000));
+        assert!(report.summary.is_none());
+    }
+
+    #[tokio::test]
+    async fn drops_system_messages_only_with_drop_oldest() {
+        let model = StubModel::new(1500);
+        let (messages, report) = fit_context(&model, &content(), history(), &options(ContextPolicy::DropOldest)).await.unwrap();
+        assert_eq!(names(&messages), ["question 2", "answer 2"]);
+        assert_eq!(report.unwrap().dropped_messages, 5);
+    }
+
+    #[tokio::test]
+    async fn drops_the_last_turn_if_it_does_not_fit() {
+        let system = message(Role::System, "system", 300, None);
+        let question = message(Role::User, "question", 300, Some(&system));
+        let answer = message(Role::Assistant, "answer", 1200, Some(&question));
+        let model = StubModel::new(1500);
+        let history = vec![system, question, answer];
+        let (messages, report) = fit_context(&model, &content(), history, &options(ContextPolicy::PinSystem)).await.unwrap();
+        assert_eq!(names(&messages), ["system"]);
+        assert_eq!(report.unwrap().dropped_messages, 2);
+    }
+
+    #[tokio::test]
+    async fn fails_if_the_prompt_and_pinned_messages_do_not_fit() {
+        let model = StubModel::new(350);
+        let result = fit_context(&model, &content(), history(), &options(ContextPolicy::PinSystem)).await;
+        assert!(matches!(result, Err(Error::Generation(ErrorKind::ContextTooLong(_)))));
+
+        // Without pinned system messages, only the prompt has to fit
+        let result = fit_context(&model, &content(), history(), &options(ContextPolicy::DropOldest)).await;
+        assert!(result.unwrap().0.is_empty());
+
+        let content = message(Role::User, "long question", 400, None);
+        let result = fit_context(&model, &content, history(), &options(ContextPolicy::DropOldest)).await;
+        assert!(matches!(result, Err(Error::Generation(ErrorKind::ContextTooLong(_)))));
+    }
+
+    #[tokio::test]
+    async fn replaces_dropped_turns_with_a_summary() {
+        // 1188 tokens are left besides the system message and the summary
+        let model = StubModel::new(2100);
+        let (messages, report) = fit_context(&model, &content(), history(), &options(ContextPolicy::Summarize)).await.unwrap();
+        assert_eq!(names(&messages), ["system", summary_message("Summary").content(), "question 2", "answer 2"]);
+        assert_eq!(report.unwrap().summary.as_deref(), Some("Summary"));
+
+        let prompts = model.prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 1);
+        assert!(prompts[0].contains("tool result") && !prompts[0].contains("question 2"));
+    }
+
+    #[test]
+    fn puts_summaries_in_place_of_the_dropped_turns() {
+        let history = history();
+        let is_system = |message: &ChatMessage| *message.role() == Role::System;
+        let summary = summary_message("Summary");
+
+        let messages = replace_turns(history.clone(), 5, is_system, Some("Summary"));
+        assert_eq!(names(&messages), ["system", summary.content(), "question 2", "answer 2"]);
+
+        let messages = replace_turns(history.clone(), 7, is_system, Some("Summary"));
+        assert_eq!(names(&messages), ["system", summary.content()]);
+
+        let messages = replace_turns(history, 5, |_| false, None);
+        assert_eq!(names(&messages), ["question 2", "answer 2"]);
+    }
+
+    #[tokio::test]
+    async fn rolling_summaries_cover_the_condensed_turns() {
+        let history = history();
+        // 1238 tokens are left besides the system message and the summary,
+        // so turns are summarized until at most half of it is taken
+        let model = StubModel::new(2150);
+        let options = options(ContextPolicy::RollingSummary);
+        let (messages, report, summary) = fit_rolling_context(&model, &content(), history.clone(), &options, None).await.unwrap();
+        assert_eq!(names(&messages), ["system", summary_message("Summary").content(), "question 2", "answer 2"]);
+        assert_eq!(report.unwrap().dropped_messages, 4);
+
+        let summary = summary.unwrap();
+        assert_eq!(summary.content, "Summary");
+        assert_eq!(Some(summary.through), history[4].id());
+    }
+
+    #[tokio::test]
+    async fn rolling_summaries_are_reused() {
+        let history = history();
+        let model = StubModel::new(2150);
+        let options = options(ContextPolicy::RollingSummary);
+        let stored = RollingSummary {
+            content: "Stored".into(),
+            through: history[4].id().unwrap(),
+            updated_at: UtcDateTime::now()
+        };
+        let (messages, report, summary) = fit_rolling_context(&model, &content(), history, &options, Some(stored)).await.unwrap();
+        assert_eq!(names(&messages), ["system", summary_message("Stored").content(), "question 2", "answer 2"]);
+        assert_eq!(report.unwrap().dropped_messages, 4);
+        assert!(summary.is_none());
+        assert!(model.prompts.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rolling_summaries_of_other_branches_are_ignored() {
+        let history = history();
+        let model = StubModel::new(2150);
+        let options = options(ContextPolicy::RollingSummary);
+        let other = RollingSummary { content: "Other".into(), through: Uuid::new_v4(), updated_at: UtcDateTime::now() };
+        let (messages, _, summary) = fit_rolling_context(&model, &content(), history.clone(), &options, Some(other)).await.unwrap();
+        assert_eq!(names(&messages)[1], summary_message("Summary").content());
+        assert_eq!(summary.map(|summary| summary.through), history[4].id());
+        assert!(!model.prompts.lock().unwrap()[0].contains("Other"));
+    }
+}
@@ -1,14 +1,21 @@
 use std::any::Any;
+use std::future::Future;
 use std::sync::{Arc, Weak};
 use time::UtcDateTime;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 use tokio::sync::mpsc::Receiver;
+use tokio_util::sync::CancellationToken;
 use std::boxed::Box;
 use serde::{Deserialize, Serialize};
 use async_trait::async_trait;
 
-use crate::backend::chat::{ChatMessage, ChatResponse, parse_utc_datetime, serialize_utc_datetime};
-use crate::errors::Error;
+use crate::backend::context::ContextPolicy;
+use crate::backend::tools::ToolDefinition;
+use crate::backend::chat::{
+    ChatMessage, ChatResponse, parse_optional_utc_datetime, parse_utc_datetime, serialize_optional_utc_datetime,
+    serialize_utc_datetime
+};
+use crate::errors::{self, Error};
 
 pub type SharedModel = Arc<RwLock<dyn Model>>;
 pub type SharedBackend = Arc<RwLock<dyn Backend>>;
@@ -111,7 +118,106 @@ pub trait PromptResponse: Send + Sync {
     async fn abort(&self);
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+/// [PromptResponse] of a prompt which is only sent after a preparation
+/// taking a while, e.g. summarizing older messages of the history.
+/// Aborting stops the preparation as well as the prompt.
+/// Clones share their state, so one can be aborted while another prepares.
+#[derive(Clone, Default)]
+pub struct DeferredPromptResponse {
+    // Response of the prompt once it has been sent
+    current: Arc<Mutex<Option<Box<dyn PromptResponse>>>>,
+    cancel: CancellationToken
+}
+
+impl DeferredPromptResponse {
+    /// Runs `preparation` unless aborted before it has finished.
+    pub async fn prepare<T>(&self, preparation: impl Future<Output = T>) -> Option<T> {
+        tokio::select! {
+            _ = self.cancel.cancelled() => None,
+            result = preparation => Some(result)
+        }
+    }
+
+    /// Takes the prompts of the `response` to the prepared prompt.
+    /// [None] if aborted in the meantime, which aborts `response` as well.
+    pub async fn start(&self, mut response: Box<dyn PromptResponse>) -> Result<Option<Receiver<ChatResponse>>, Error> {
+        let prompts = response.get_prompts()?;
+        let mut current = self.current.lock().await;
+        if self.cancel.is_cancelled() {
+            response.abort().await;
+            return Ok(None);
+        }
+        *current = Some(response);
+        Ok(Some(prompts))
+    }
+
+    /// Whether the prompt has been aborted, possibly during the preparation.
+    pub fn is_aborted(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+}
+
+#[async_trait]
+impl PromptResponse for DeferredPromptResponse {
+    fn get_prompts(&mut self) -> Result<Receiver<ChatResponse>, Error> {
+        Err(errors::internal("Prompts of a deferred response are taken by DeferredPromptResponse::start"))
+    }
+
+    async fn abort(&self) {
+        self.cancel.cancel();
+        if let Some(response) = self.current.lock().await.as_ref() {
+            response.abort().await;
+        }
+    }
+}
+
+/// Backend-neutral options for a single chat completion.
+/// Options not set use the defaults of the backend or model.
+/// Backends ignore options they don't support.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationOptions {
+    pub temperature: Option<f32>,
+    pub top_k: Option<u32>,
+    pub top_p: Option<f32>,
+    pub min_p: Option<f32>,
+    pub repeat_penalty: Option<f32>,
+    pub seed: Option<i64>,
+    /// Size of the context window in tokens
+    pub context_length: Option<u32>,
+    /// Maximum number of tokens to generate
+    pub max_tokens: Option<u32>,
+    /// Sequences which stop the generation
+    #[serde(default)]
+    pub stop: Vec<String>,
+    /// How long the model stays loaded after the request,
+    /// e.g. "10m" or "1h". Only supported by Ollama.
+    pub keep_alive: Option<String>,
+    /// Constrains the response to JSON
+    pub format: Option<ResponseFormat>,
+    /// How to shorten histories exceeding the context window.
+    /// Defaults to [ContextPolicy::PinSystem].
+    pub context_policy: Option<ContextPolicy>,
+    /// Tools the model may call.
+    /// Set from the tool registry, not by the frontend.
+    #[serde(skip)]
+    pub tools: Vec<ToolDefinition>
+}
+
+/// Format the response of a model must adhere to.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", content = "schema", rename_all = "camelCase")]
+pub enum ResponseFormat {
+    /// Any valid JSON
+    Json,
+    /// JSON matching the given JSON Schema
+    JsonSchema(serde_json::Value)
+}
+
+/// General information about a model.
+/// Backends fill in what they know, the rest is [None].
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
 pub struct ModelInfo {
     /// Human readable name of the model
     /// and also identifier.
@@ -122,7 +228,52 @@ pub struct ModelInfo {
     /// Disk size of the model in bytes.
     pub size: u64,
     /// Additional functionality of the model.
-    pub capabilities: Vec<Capability>
+    pub capabilities: Vec<Capability>,
+    /// Maximum number of tokens the model has been trained for.
+    /// [None] if the backend doesn't tell.
+    pub context_length: Option<u32>,
+    /// Number of weights, e.g. 8030261248
+    pub parameter_count: Option<u64>,
+    /// Quantization of the weights, e.g. `Q4_K_M`
+    pub quantization: Option<String>,
+    /// Architecture the model belongs to, e.g. `llama`
+    pub family: Option<String>,
+    /// Template turning messages into the prompt,
+    /// in the syntax of the backend
+    pub template: Option<String>,
+    pub license: Option<String>,
+    /// When the model has been downloaded or changed
+    #[serde(default, deserialize_with = "parse_optional_utc_datetime", serialize_with = "serialize_optional_utc_datetime")]
+    pub modified_at: Option<UtcDateTime>
+}
+
+/// Everything the backend knows about a model, see [Model::card].
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelCard {
+    #[serde(flatten)]
+    pub info: ModelInfo,
+    /// Definition the model has been created from, e.g. an Ollama Modelfile
+    pub modelfile: Option<String>,
+    /// System prompt built into the model
+    pub system: Option<String>,
+    /// Default parameters of the model, one `name value` per line
+    pub parameters: Option<String>
+}
+
+impl From<ModelInfo> for ModelCard {
+    fn from(info: ModelInfo) -> Self {
+        Self { info, modelfile: None, system: None, parameters: None }
+    }
+}
+
+impl ModelInfo {
+    /// Whether the model only computes embeddings
+    /// and cannot be used for chat completions.
+    pub fn is_embedding_only(&self) -> bool {
+        self.capabilities.contains(&Capability::Embedding)
+            && !self.capabilities.contains(&Capability::Completion)
+    }
 }
 
 /// A large language model (LLM).
@@ -162,6 +313,59 @@ pub trait Model: Send + Sync {
         &self,
         content: ChatMessage,
         history: &[ChatMessage],
-        think: Option<bool>
+        think: Option<bool>,
+        options: &GenerationOptions
     ) -> Result<Box<dyn PromptResponse>, Error>;
+
+    /// Computes an embedding for every input.
+    /// Requires a model with [Capability::Embedding].
+    async fn embed(&self, input: &[String]) -> Result<Embeddings, Error>;
+
+    /// Everything the backend knows about the model.
+    /// Fetched on every call, since it is rarely needed.
+    async fn card(&self) -> Result<ModelCard, Error> {
+        Ok(self.info().clone().into())
+    }
+
+    /// Number of tokens the model considers in a prompt with `options`.
+    /// Backends cut off longer prompts. [None] if unknown.
+    fn context_window(&self, options: &GenerationOptions) -> Option<u32> {
+        options.context_length.or(self.info().context_length)
+    }
+}
+
+/// Prompts `model` without thinking and waits for the whole answer.
+/// Used for short internal tasks instead of chats shown to the user.
+pub async fn complete(
+    model: &dyn Model, content: ChatMessage, history: &[ChatMessage], options: &GenerationOptions
+) -> Result<String, Error>
+{
+    let mut res = model.prompt(content, history, Some(false), options).await?;
+    let mut prompts = res.get_prompts()?;
+    let mut answer = String::new();
+    while let Some(prompt) = prompts.recv().await {
+        if let Some(error) = prompt.error {
+            return Err(Error::Generation(error));
+        }
+        answer.push_str(prompt.message.content());
+        if prompt.done {
+            break;
+        }
+    }
+    Ok(answer)
+}
+
+/// Embeddings of a batch of inputs in the order of the inputs.
+#[derive(Serialize, Clone, Debug)]
+pub struct Embeddings {
+    pub embeddings: Vec<Vec<f32>>,
+    /// Number of dimensions of each embedding
+    pub dimension: usize
+}
+
+impl Embeddings {
+    pub fn new(embeddings: Vec<Vec<f32>>) -> Self {
+        let dimension = embeddings.first().map(Vec::len).unwrap_or_default();
+        Self { embeddings, dimension }
+    }
 }
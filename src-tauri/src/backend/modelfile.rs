@@ -0,0 +1,426 @@
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{backend::chat::Role, errors::Error};
+
+/// Definition of an Ollama model derived from another one.
+/// See https://github.com/ollama/ollama/blob/main/docs/modelfile.md
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Modelfile {
+    /// Base model, e.g. `llama3.2:3b`
+    pub from: String,
+    pub system: Option<String>,
+    /// Prompt template in Go template syntax
+    pub template: Option<String>,
+    /// Parameters in order of appearance.
+    /// Some like `stop` may occur multiple times.
+    #[serde(default)]
+    pub parameters: Vec<(String, ParameterValue)>,
+    /// Paths of LoRA adapters applied to the base model
+    #[serde(default)]
+    pub adapters: Vec<String>,
+    #[serde(default)]
+    pub licenses: Vec<String>,
+    /// Conversation the model continues, e.g. examples of answers
+    #[serde(default)]
+    pub messages: Vec<ModelfileMessage>,
+    /// Minimum version of Ollama
+    pub requires: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum ParameterValue {
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ModelfileMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl Modelfile {
+    pub fn new(from: impl Into<String>) -> Self {
+        Self { from: from.into(), ..Default::default() }
+    }
+}
+
+impl Modelfile {
+    /// Parses the text of a Modelfile.
+    /// Instructions are case-insensitive, arguments may be quoted with `"` or,
+    /// spanning multiple lines, with `"""`. In `"`, quotes and backslashes may be escaped.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut modelfile: Option<Modelfile> = None;
+        let mut lines = text.lines().enumerate();
+        while let Some((number, line)) = lines.next() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |reason: &str| Error::InvalidModelfile(format!("Line {}: {reason}", number + 1));
+            let mut argument = |first: &str| read_argument(first, &mut lines).map_err(|reason| error(&reason));
+            let (instruction, rest) = split_word(line);
+            let instruction = instruction.to_uppercase();
+
+            if instruction == "FROM" {
+                if modelfile.is_some() {
+                    return Err(error("FROM may only occur once"));
+                }
+                modelfile = Some(Modelfile::new(argument(rest)?));
+                continue;
+            }
+            let Some(modelfile) = modelfile.as_mut() else {
+                return Err(error("The Modelfile needs to start with FROM"));
+            };
+            match instruction.as_str() {
+                "SYSTEM" => modelfile.system = Some(argument(rest)?),
+                "TEMPLATE" => modelfile.template = Some(argument(rest)?),
+                "ADAPTER" => modelfile.adapters.push(argument(rest)?),
+                "LICENSE" => modelfile.licenses.push(argument(rest)?),
+                "REQUIRES" => modelfile.requires = Some(argument(rest)?),
+                "PARAMETER" => {
+                    let (name, value) = split_word(rest);
+                    if name.is_empty() {
+                        return Err(error("PARAMETER needs a name and a value"));
+                    }
+                    let quoted = value.trim_start().starts_with('"');
+                    let value = argument(value)?;
+                    let value = if quoted { ParameterValue::Text(value) } else { ParameterValue::parse(&value) };
+                    modelfile.parameters.push((name.to_lowercase(), value));
+                },
+                "MESSAGE" => {
+                    let (role, content) = split_word(rest);
+                    let role = match role.to_lowercase().as_str() {
+                        "system" => Role::System,
+                        "user" => Role::User,
+                        "assistant" => Role::Assistant,
+                        _ => return Err(error("The role of a MESSAGE must be system, user or assistant"))
+                    };
+                    let content = argument(content)?;
+                    modelfile.messages.push(ModelfileMessage { role, content });
+                },
+                _ => return Err(error(&format!("Unknown instruction {instruction}")))
+            }
+        }
+        modelfile.ok_or(Error::InvalidModelfile("The Modelfile has no FROM instruction".into()))
+    }
+}
+
+impl Display for Modelfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "FROM {}", quote(&self.from))?;
+        for (name, value) in &self.parameters {
+            writeln!(f, "PARAMETER {name} {value}")?;
+        }
+        if let Some(template) = &self.template {
+            writeln!(f, "TEMPLATE {}", quote(template))?;
+        }
+        if let Some(system) = &self.system {
+            writeln!(f, "SYSTEM {}", quote(system))?;
+        }
+        for adapter in &self.adapters {
+            writeln!(f, "ADAPTER {}", quote(adapter))?;
+        }
+        for license in &self.licenses {
+            writeln!(f, "LICENSE {}", quote(license))?;
+        }
+        for message in &self.messages {
+            let role = serde_json::to_value(&message.role).ok();
+            let role = role.as_ref().and_then(|role| role.as_str()).unwrap_or("user");
+            writeln!(f, "MESSAGE {role} {}", quote(&message.content))?;
+        }
+        if let Some(requires) = &self.requires {
+            writeln!(f, "REQUIRES {}", quote(requires))?;
+        }
+        Ok(())
+    }
+}
+
+impl ParameterValue {
+    /// Numbers and booleans become typed values, everything else text.
+    fn parse(value: &str) -> Self {
+        if let Ok(integer) = value.parse() {
+            ParameterValue::Integer(integer)
+        } else if let Ok(float) = value.parse() {
+            ParameterValue::Float(float)
+        } else if let Ok(bool) = value.parse() {
+            ParameterValue::Bool(bool)
+        } else {
+            ParameterValue::Text(value.to_owned())
+        }
+    }
+}
+
+impl Display for ParameterValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParameterValue::Integer(integer) => write!(f, "{integer}"),
+            // With a decimal point, so 1.0 stays a float
+            ParameterValue::Float(float) => write!(f, "{float:?}"),
+            ParameterValue::Bool(bool) => write!(f, "{bool}"),
+            // Always quoted, so "4" stays text
+            ParameterValue::Text(text) => write!(f, "{}", quote_always(text)),
+        }
+    }
+}
+
+impl From<i64> for ParameterValue {
+    fn from(value: i64) -> Self {
+        ParameterValue::Integer(value)
+    }
+}
+
+impl From<f64> for ParameterValue {
+    fn from(value: f64) -> Self {
+        ParameterValue::Float(value)
+    }
+}
+
+impl From<bool> for ParameterValue {
+    fn from(value: bool) -> Self {
+        ParameterValue::Bool(value)
+    }
+}
+
+impl From<&str> for ParameterValue {
+    fn from(value: &str) -> Self {
+        ParameterValue::Text(value.to_owned())
+    }
+}
+
+impl From<String> for ParameterValue {
+    fn from(value: String) -> Self {
+        ParameterValue::Text(value)
+    }
+}
+
+impl From<&ParameterValue> for serde_json::Value {
+    fn from(value: &ParameterValue) -> Self {
+        match value {
+            ParameterValue::Integer(integer) => (*integer).into(),
+            ParameterValue::Float(float) => (*float).into(),
+            ParameterValue::Bool(bool) => (*bool).into(),
+            ParameterValue::Text(text) => text.as_str().into(),
+        }
+    }
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    text.split_once(char::is_whitespace).unwrap_or((text, ""))
+}
+
+/// Reads the argument starting with `first`. Quoted arguments
+/// continue on the following `lines` until the closing quotes.
+fn read_argument<'a>(first: &str, lines: &mut impl Iterator<Item = (usize, &'a str)>) -> Result<String, String> {
+    let first = first.trim();
+    if first.is_empty() {
+        return Err("Missing argument".into());
+    }
+    if let Some(rest) = first.strip_prefix("\"\"\"") {
+        // The last quotes close, so the text may end with a quote
+        if let Some((argument, _)) = rest.rsplit_once("\"\"\"") {
+            return Ok(argument.to_owned());
+        }
+        let mut argument = rest.to_owned();
+        for (_, line) in lines.by_ref() {
+            argument.push('\n');
+            if let Some((end, _)) = line.rsplit_once("\"\"\"") {
+                argument.push_str(end);
+                return Ok(argument);
+            }
+            argument.push_str(line);
+        }
+        return Err("Missing closing \"\"\"".into());
+    }
+    let Some(rest) = first.strip_prefix('"') else {
+        return Ok(first.to_owned());
+    };
+    if let Some(argument) = strip_closing_quote(rest) {
+        return Ok(unescape(argument));
+    }
+    let mut argument = rest.to_owned();
+    for (_, line) in lines.by_ref() {
+        argument.push('\n');
+        if let Some(end) = strip_closing_quote(line.trim_end()) {
+            argument.push_str(end);
+            return Ok(unescape(&argument));
+        }
+        argument.push_str(line);
+    }
+    Err("Missing closing \"".into())
+}
+
+/// `text` without the `"` it ends with, unless the quote is escaped.
+fn strip_closing_quote(text: &str) -> Option<&str> {
+    let text = text.strip_suffix('"')?;
+    let backslashes = text.len() - text.trim_end_matches('\\').len();
+    (backslashes % 2 == 0).then_some(text)
+}
+
+/// Reverts [escape].
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&next @ ('"' | '\\'))) => {
+                unescaped.push(next);
+                chars.next();
+            },
+            _ => unescaped.push(c)
+        }
+    }
+    unescaped
+}
+
+/// Escapes quotes and the backslashes which would be read as escapes,
+/// so other sequences like `\n` stay as they are.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('"', _) => escaped.push_str("\\\""),
+            ('\\', None | Some('"' | '\\')) => escaped.push_str("\\\\"),
+            _ => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// Quotes `argument` if it would not be read back as it is.
+fn quote(argument: &str) -> String {
+    if argument.is_empty() || argument.contains(['\n', '"']) || argument.trim() != argument {
+        quote_always(argument)
+    } else {
+        argument.to_owned()
+    }
+}
+
+/// Quotes `argument` with `"""` if it contains quotes or line breaks, else with `"`.
+/// Text containing `"""` itself is quoted with `"` as well, escaping its quotes.
+fn quote_always(argument: &str) -> String {
+    if argument.contains(['\n', '"']) && !argument.contains("\"\"\"") {
+        format!("\"\"\"{argument}\"\"\"")
+    } else {
+        format!("\"{}\"", escape(argument))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODELFILE: &str = r#"# Assistant with examples
+FROM llama3.2:3b
+parameter temperature 0.7
+PARAMETER num_ctx 4096
+PARAMETER stop "<|eot_id|>"
+PARAMETER stop "4"
+TEMPLATE """{{ if .System }}{{ .System }}
+{{ end }}{{ .Prompt }}"""
+SYSTEM You are a "helpful" assistant.
+ADAPTER ./lora.gguf
+MESSAGE user Is the sky blue?
+MESSAGE assistant """Yes."""
+REQUIRES 0.5.0
+"#;
+
+    fn text(value: &str) -> ParameterValue {
+        ParameterValue::Text(value.to_owned())
+    }
+
+    fn assert_round_trip(modelfile: &Modelfile) {
+        let rendered = modelfile.to_string();
+        assert_eq!(&Modelfile::parse(&rendered).unwrap(), modelfile, "Rendered as:\n{rendered}");
+    }
+
+    #[test]
+    fn parses_instructions() {
+        let modelfile = Modelfile::parse(MODELFILE).unwrap();
+        assert_eq!(modelfile, Modelfile {
+            from: "llama3.2:3b".into(),
+            system: Some("You are a \"helpful\" assistant.".into()),
+            template: Some("{{ if .System }}{{ .System }}\n{{ end }}{{ .Prompt }}".into()),
+            parameters: vec![
+                ("temperature".into(), ParameterValue::Float(0.7)),
+                ("num_ctx".into(), ParameterValue::Integer(4096)),
+                ("stop".into(), text("<|eot_id|>")),
+                ("stop".into(), text("4")),
+            ],
+            adapters: vec!["./lora.gguf".into()],
+            licenses: vec![],
+            messages: vec![
+                ModelfileMessage { role: Role::User, content: "Is the sky blue?".into() },
+                ModelfileMessage { role: Role::Assistant, content: "Yes.".into() },
+            ],
+            requires: Some("0.5.0".into()),
+        });
+    }
+
+    #[test]
+    fn parsed_modelfile_round_trips() {
+        assert_round_trip(&Modelfile::parse(MODELFILE).unwrap());
+    }
+
+    #[test]
+    fn renders_floats_with_decimal_point() {
+        let modelfile = Modelfile {
+            parameters: vec![("temperature".into(), ParameterValue::Float(1.0))],
+            ..Modelfile::new("llama3.2")
+        };
+        assert!(modelfile.to_string().contains("PARAMETER temperature 1.0\n"));
+        assert_round_trip(&modelfile);
+    }
+
+    #[test]
+    fn text_round_trips() {
+        let texts = [
+            "",
+            " padded ",
+            "ends with a quote\"",
+            "\"\"\"",
+            "contains \"\"\" on one line",
+            "contains \"\"\"\non two lines\"",
+            "ends with a backslash\\",
+            "keeps \\n and \\\" as they are",
+            "first line\n\nthird line",
+        ];
+        for text in texts {
+            let modelfile = Modelfile {
+                system: Some(text.into()),
+                licenses: vec![text.into()],
+                parameters: vec![("stop".into(), ParameterValue::Text(text.into()))],
+                messages: vec![ModelfileMessage { role: Role::User, content: text.into() }],
+                ..Modelfile::new("llama3.2")
+            };
+            assert_round_trip(&modelfile);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_modelfiles() {
+        for text in [
+            "",
+            "SYSTEM Before FROM\nFROM llama3.2",
+            "FROM llama3.2\nFROM llama3.1",
+            "FROM llama3.2\nUNKNOWN instruction",
+            "FROM llama3.2\nSYSTEM",
+            "FROM llama3.2\nSYSTEM \"\"\"Never closed",
+            "FROM llama3.2\nSYSTEM \"Never closed",
+            "FROM llama3.2\nMESSAGE tool Hello",
+        ] {
+            assert!(
+                matches!(Modelfile::parse(text), Err(Error::InvalidModelfile(_))),
+                "{text:?} should be invalid"
+            );
+        }
+    }
+}
@@ -1,34 +1,57 @@
 use core::str;
 use std::{
-    ops::Deref, path::{Path, PathBuf}, process::{Child, Command}, sync::Arc, time::Duration
+    collections::HashMap, ops::Deref, path::{Path, PathBuf}, process::{Child, Command}, sync::Arc, time::Duration
 };
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
 use time::UtcDateTime;
-use tokio::sync::{mpsc::Receiver, RwLock};
+use tokio::{fs::File, sync::{mpsc::{channel, Receiver, Sender}, RwLock}};
+use tokio_util::io::ReaderStream;
 use log::{info, error};
 
 use crate::{
     backend::{
-        chat::ChatMessage,
+        chat::{parse_optional_utc_datetime, ChatMessage, ChatResponse, GenerationStats},
         llm::{
-            Backend, Capability, Model, ModelInfo, PromptResponse, RuntimeInfo, SharedBackend,
-            SharedBackendImpl, SharedModel, WeakBackend,
+            Backend, Capability, Embeddings, GenerationOptions, Model, ModelCard, ModelInfo, PromptResponse,
+            ResponseFormat, RuntimeInfo, SharedBackend, SharedBackendImpl, SharedModel, WeakBackend,
         },
+        modelfile::{Modelfile, ModelfileMessage},
         reader::ndjson_reader::NdJsonReader,
+        tools::ToolDefinition,
     },
-    errors::{self, Error},
+    errors::{self, Error, ErrorKind},
 };
 use async_trait::async_trait;
-use reqwest::{Client, IntoUrl, Method, RequestBuilder, Response};
+use reqwest::{Body, Client, IntoUrl, Method, RequestBuilder, Response};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
 pub(crate) static OLLAMA_NAME: &'static str = "Ollama";
 
+/// How long a model stays loaded after a chat completion
+/// if not given otherwise.
+const DEFAULT_KEEP_ALIVE: &str = "10m";
+
+/// Context length Ollama uses if a request doesn't set one.
+/// Can be changed with the environment variable `OLLAMA_CONTEXT_LENGTH`.
+const DEFAULT_CONTEXT_LENGTH: u32 = 4096;
+
+/// Size of the chunks local files are hashed and uploaded in
+const BLOB_CHUNK_SIZE: usize = 1 << 20;
+
+fn default_context_length() -> u32 {
+    std::env::var("OLLAMA_CONTEXT_LENGTH")
+        .ok()
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(DEFAULT_CONTEXT_LENGTH)
+}
+
 pub(crate) fn not_ollama() -> errors::Error {
     errors::internal("Backend is not Ollama")
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct OllamaPullProgress {
     #[serde(default)]
     pub status: String,
@@ -38,6 +61,80 @@ pub struct OllamaPullProgress {
     pub completed: Option<u64>
 }
 
+/// Request of `/api/create`, see [OllamaCreateRequest::new].
+#[derive(Serialize, Debug, Default)]
+pub struct OllamaCreateRequest {
+    /// Name of the new model
+    pub model: String,
+    /// Existing model to derive the new one from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// File names onto the digests of uploaded files, e.g. GGUF files
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub files: HashMap<String, String>,
+    /// Like `files` for LoRA adapters
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub adapters: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub license: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<ModelfileMessage>,
+    /// Quantization of the weights, e.g. `q4_K_M`.
+    /// Only for models with FP16 or FP32 weights.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quantize: Option<String>,
+}
+
+impl OllamaCreateRequest {
+    /// Request creating the model `name` as defined by `modelfile`.
+    /// Adapters need to be uploaded before, `blobs` maps their paths onto the digests.
+    pub fn new(name: &str, modelfile: &Modelfile, blobs: &HashMap<String, String>) -> Result<Self, Error> {
+        let mut parameters = serde_json::Map::new();
+        for (name, value) in &modelfile.parameters {
+            let value = serde_json::Value::from(value);
+            // Ollama expects lists for parameters occurring multiple times
+            match parameters.get_mut(name) {
+                Some(serde_json::Value::Array(values)) => values.push(value),
+                Some(first) => *first = serde_json::Value::Array(vec![first.take(), value]),
+                None if name == "stop" => {
+                    parameters.insert(name.clone(), serde_json::Value::Array(vec![value]));
+                },
+                None => {
+                    parameters.insert(name.clone(), value);
+                }
+            }
+        }
+        let adapters = modelfile.adapters
+            .iter()
+            .map(|path| {
+                let digest = blobs
+                    .get(path)
+                    .ok_or_else(|| Error::InvalidModelfile(format!("Adapter '{path}' has not been uploaded")))?;
+                let file_name = Path::new(path).file_name().map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+                Ok((file_name, digest.clone()))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Self {
+            model: name.to_owned(),
+            from: Some(modelfile.from.clone()),
+            adapters,
+            template: modelfile.template.clone(),
+            system: modelfile.system.clone(),
+            parameters,
+            license: modelfile.licenses.clone(),
+            messages: modelfile.messages.clone(),
+            ..Default::default()
+        })
+    }
+}
+
 pub struct OllamaBackend {
     http_client: Client,
     api_url: Url,
@@ -107,6 +204,20 @@ impl OllamaBackend {
         Ok(())
     }
 
+    /// Everything Ollama knows about a model.
+    async fn show(&self, model_name: &str) -> Result<ModelDetail, errors::Error> {
+        let name = model_name.to_owned();
+        let res = self
+            .call_backend("show", Method::POST, move |req| {
+                req.json(&serde_json::json!({"model": name}))
+            })
+            .await?;
+        if !res.status().is_success() {
+            return Err(errors::response_error(res, model_name, OLLAMA_NAME).await);
+        }
+        Ok(res.json().await?)
+    }
+
     /// Append trailing slash (/) if not already there
     pub fn prepare_api_url(url: &mut Url) {
         if !url.as_str().ends_with("/") {
@@ -132,6 +243,74 @@ impl OllamaBackend {
         reader.start_unwrapping_data(64)
     }
 
+    /// Creates the model `name` from `modelfile`, e.g. from an existing model with another system prompt.
+    /// Its adapters are uploaded like by [OllamaBackend::import_gguf] first.
+    /// The progress is reported like by [OllamaBackend::pull_model], errors as progress as well.
+    pub fn create_model(
+        &self, name: String, modelfile: Modelfile, quantize: Option<String>
+    ) -> Result<Receiver<OllamaPullProgress>, errors::Error> {
+        let adapters = modelfile.adapters
+            .iter()
+            .map(|path| Ok((path.clone(), resolve_adapter(path)?)))
+            .collect::<Result<Vec<_>, errors::Error>>()?;
+        let paths = adapters.iter().map(|(_, resolved)| resolved.clone()).collect();
+        Ok(self.upload_and_create(paths, move |digests| {
+            let blobs = adapters
+                .into_iter()
+                .filter_map(|(path, resolved)| Some((path, digests.get(&resolved)?.clone())))
+                .collect();
+            Ok(OllamaCreateRequest { quantize, ..OllamaCreateRequest::new(&name, &modelfile, &blobs)? })
+        }))
+    }
+
+    /// Creates a model from the GGUF file at `path`, which becomes the only file of `request`.
+    /// The file is hashed and uploaded unless Ollama has it already, reported with
+    /// the statuses `hashing` and `uploading`. Then, the progress is reported like by
+    /// [OllamaBackend::pull_model]. Errors are reported as progress as well.
+    pub fn import_gguf(&self, mut request: OllamaCreateRequest, path: PathBuf) -> Receiver<OllamaPullProgress> {
+        let file_name = path.file_name().map_or("model.gguf".into(), |name| name.to_string_lossy().into_owned());
+        self.upload_and_create(vec![path.clone()], move |mut digests| {
+            let digest = digests.remove(&path).ok_or(errors::internal("GGUF file has not been uploaded"))?;
+            request.files = HashMap::from([(file_name, digest)]);
+            Ok(request)
+        })
+    }
+
+    /// Uploads the files at `paths` and creates the model `build` requests with their digests.
+    /// The upload runs detached from the backend since large files take a while.
+    fn upload_and_create<F>(&self, paths: Vec<PathBuf>, build: F) -> Receiver<OllamaPullProgress>
+    where
+        F: FnOnce(HashMap<PathBuf, String>) -> Result<OllamaCreateRequest, errors::Error> + Send + 'static
+    {
+        let (tx_progress, rx_progress) = channel(64);
+        let http_client = self.http_client.clone();
+        let api_url = self.api_url.clone();
+        tokio::spawn(async move {
+            let upload = async {
+                let mut digests = HashMap::new();
+                for path in &paths {
+                    let digest = upload_blob(&http_client, &api_url, path, &tx_progress).await?;
+                    digests.insert(path.clone(), digest);
+                }
+                let request = build(digests)?;
+                let mut rx_create = create(&http_client, &api_url, &request).await?;
+                while let Some(progress) = rx_create.recv().await {
+                    tx_progress.send(progress).await.map_err(errors::internal)?;
+                }
+                Ok::<_, errors::Error>(())
+            };
+            if let Err(e) = upload.await {
+                error!("Creating a model from {paths:?} - {e}");
+                let _ = tx_progress.send(OllamaPullProgress {
+                    status: "error".into(),
+                    error: Some(e.to_string()),
+                    ..Default::default()
+                }).await;
+            }
+        });
+        rx_progress
+    }
+
     pub async fn delete_model(&mut self, model_tag: String) -> Result<(), errors::Error> {
         let mut model_idx = None;
         for (i, model) in self.models.iter().enumerate() {
@@ -176,6 +355,108 @@ impl OllamaBackend {
     }
 }
 
+/// Sends `request` to `/api/create` and reports the progress like [OllamaBackend::pull_model].
+/// Shared by imports, which run without access to the backend.
+async fn create(
+    http_client: &Client, api_url: &Url, request: &OllamaCreateRequest
+) -> Result<Receiver<OllamaPullProgress>, errors::Error> {
+    let url = api_url.join("create").map_err(errors::internal)?;
+    let res = http_client.post(url).json(request).send().await?;
+
+    let mut reader = NdJsonReader::<OllamaPullProgress>::new();
+    reader.start_reading_response(res);
+    reader.start_unwrapping_data(64)
+}
+
+/// Hashes the file at `path` and uploads it unless Ollama has it already.
+/// Returns the digest Ollama identifies the file by.
+async fn upload_blob(
+    http_client: &Client, api_url: &Url, path: &Path, tx_progress: &Sender<OllamaPullProgress>
+) -> Result<String, errors::Error> {
+    let digest = hash_file(path, tx_progress).await?;
+    let blob_url = api_url.join(&format!("blobs/{digest}")).map_err(errors::internal)?;
+    // Ollama knows the blob if it has been imported before
+    if !http_client.head(blob_url.clone()).send().await?.status().is_success() {
+        upload_file(http_client, blob_url, path, &digest, tx_progress).await?;
+    }
+    Ok(digest)
+}
+
+/// Path of the adapter file at `path` of a Modelfile.
+/// Relative paths are rejected since Modelfiles are not read from a folder.
+fn resolve_adapter(path: &str) -> Result<PathBuf, errors::Error> {
+    let resolved = Path::new(path);
+    if !resolved.is_absolute() {
+        return Err(Error::InvalidModelfile(format!("Adapter '{path}' needs an absolute path")));
+    }
+    resolved
+        .canonicalize()
+        .ok()
+        .filter(|resolved| resolved.is_file())
+        .ok_or_else(|| Error::InvalidModelfile(format!("Adapter '{path}' is not a file")))
+}
+
+/// Computes the digest Ollama identifies the file at `path` by, e.g. `sha256:4b2f...`.
+async fn hash_file(path: &Path, tx_progress: &Sender<OllamaPullProgress>) -> Result<String, errors::Error> {
+    let file = File::open(path).await?;
+    let total = file.metadata().await?.len();
+    let mut chunks = ReaderStream::with_capacity(file, BLOB_CHUNK_SIZE);
+    let mut hasher = Sha256::new();
+    let mut completed = 0;
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        hasher.update(&chunk);
+        completed += chunk.len() as u64;
+        let _ = tx_progress.send(OllamaPullProgress {
+            status: "hashing".into(),
+            total: Some(total),
+            completed: Some(completed),
+            ..Default::default()
+        }).await;
+    }
+    Ok(format!("sha256:{:x}", hasher.finalize()))
+}
+
+/// Streams the file at `path` to `blob_url`.
+/// Ollama rejects the upload if the file does not match `digest`.
+async fn upload_file(
+    http_client: &Client,
+    blob_url: Url,
+    path: &Path,
+    digest: &str,
+    tx_progress: &Sender<OllamaPullProgress>
+) -> Result<(), errors::Error> {
+    let file = File::open(path).await?;
+    let total = file.metadata().await?.len();
+    let tx_progress = tx_progress.clone();
+    let digest_ = digest.to_owned();
+    let mut completed = 0;
+    let chunks = ReaderStream::with_capacity(file, BLOB_CHUNK_SIZE).inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            completed += chunk.len() as u64;
+            // Skipped while the receiver lags behind
+            let _ = tx_progress.try_send(OllamaPullProgress {
+                status: "uploading".into(),
+                digest: Some(digest_.clone()),
+                total: Some(total),
+                completed: Some(completed),
+                ..Default::default()
+            });
+        }
+    });
+
+    let res = http_client.post(blob_url).body(Body::wrap_stream(chunks)).send().await?;
+    let status = res.status();
+    if !status.is_success() {
+        // Not a missing model like other failed requests
+        let reason = res.json::<serde_json::Value>().await.ok()
+            .and_then(|body| errors::backend_message(&body))
+            .unwrap_or(status.to_string());
+        return Err(errors::Error::Upload { file: path.display().to_string(), reason });
+    }
+    Ok(())
+}
+
 impl Deref for SharedOllamaBackend {
     type Target = SharedBackendImpl<OllamaBackend>;
 
@@ -189,6 +470,8 @@ struct ModelResInner {
     name: String,
     model: String,
     size: u64,
+    #[serde(default, deserialize_with = "parse_optional_utc_datetime")]
+    modified_at: Option<UtcDateTime>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -196,9 +479,171 @@ struct ModelResponse<T> {
     models: Vec<T>,
 }
 
+/// Response of `/api/show`.
 #[derive(Deserialize)]
 struct ModelDetail {
     capabilities: Vec<Capability>,
+    /// Metadata of the model file. Keys are prefixed by the architecture,
+    /// e.g. `llama.context_length`.
+    #[serde(default)]
+    model_info: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    details: ModelDetailSummary,
+    modelfile: Option<String>,
+    template: Option<String>,
+    system: Option<String>,
+    /// One `name value` per line
+    parameters: Option<String>,
+    license: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct ModelDetailSummary {
+    family: Option<String>,
+    /// e.g. `8.0B`
+    parameter_size: Option<String>,
+    quantization_level: Option<String>,
+}
+
+impl ModelDetail {
+    fn context_length(&self) -> Option<u32> {
+        let architecture = self.model_info.get("general.architecture")?.as_str()?;
+        let length = self.model_info.get(&format!("{architecture}.context_length"))?.as_u64()?;
+        Some(length.min(u32::MAX as u64) as u32)
+    }
+
+    /// Exact count from the model file, else the rounded size of the summary.
+    fn parameter_count(&self) -> Option<u64> {
+        self.model_info
+            .get("general.parameter_count")
+            .and_then(serde_json::Value::as_u64)
+            .or_else(|| self.details.parameter_size.as_deref().and_then(parse_parameter_size))
+    }
+
+    /// Completes `info` with the name, ID, size and date from the list of models.
+    fn into_card(self, info: ModelInfo) -> ModelCard {
+        let non_empty = |text: Option<String>| text.filter(|text| !text.trim().is_empty());
+        ModelCard {
+            info: ModelInfo {
+                context_length: self.context_length(),
+                parameter_count: self.parameter_count(),
+                quantization: non_empty(self.details.quantization_level),
+                family: non_empty(self.details.family),
+                template: non_empty(self.template),
+                license: non_empty(self.license),
+                capabilities: self.capabilities,
+                ..info
+            },
+            modelfile: non_empty(self.modelfile),
+            system: non_empty(self.system),
+            parameters: non_empty(self.parameters),
+        }
+    }
+}
+
+/// Parses sizes like `8.0B` or `567M` into a number of parameters.
+fn parse_parameter_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let (number, factor) = match size.char_indices().last()? {
+        (i, 'K' | 'k') => (&size[..i], 1e3),
+        (i, 'M' | 'm') => (&size[..i], 1e6),
+        (i, 'B' | 'b') => (&size[..i], 1e9),
+        (i, 'T' | 't') => (&size[..i], 1e12),
+        _ => (size, 1.0)
+    };
+    let number: f64 = number.trim().parse().ok()?;
+    Some((number * factor).round() as u64)
+}
+
+/// Model parameters of a request.
+/// See https://github.com/ollama/ollama/blob/main/docs/modelfile.md#valid-parameters-and-values
+#[derive(Serialize, Default)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repeat_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_ctx: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+}
+
+impl From<&GenerationOptions> for OllamaOptions {
+    fn from(options: &GenerationOptions) -> Self {
+        OllamaOptions {
+            temperature: options.temperature,
+            top_k: options.top_k,
+            top_p: options.top_p,
+            min_p: options.min_p,
+            repeat_penalty: options.repeat_penalty,
+            seed: options.seed,
+            num_ctx: options.context_length,
+            num_predict: options.max_tokens,
+            stop: options.stop.clone(),
+        }
+    }
+}
+
+/// Response of `/api/embed`.
+#[derive(Deserialize)]
+struct OllamaEmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+/// Streamed response of `/api/chat`.
+/// Errors occurring during generation are send as `{"error": "..."}`.
+#[derive(Deserialize)]
+struct OllamaChatChunk {
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    message: ChatMessage,
+    error: Option<String>,
+    // Statistics are only part of the last chunk
+    total_duration: Option<u64>,
+    load_duration: Option<u64>,
+    prompt_eval_count: Option<u64>,
+    prompt_eval_duration: Option<u64>,
+    eval_count: Option<u64>,
+    eval_duration: Option<u64>,
+}
+
+impl From<OllamaChatChunk> for ChatResponse {
+    fn from(chunk: OllamaChatChunk) -> Self {
+        if let Some(error) = chunk.error {
+            return ChatResponse::error(ErrorKind::from_backend_message(&error, &chunk.model, OLLAMA_NAME));
+        }
+        let stats = chunk.done.then(|| GenerationStats {
+            total_duration: chunk.total_duration,
+            load_duration: chunk.load_duration,
+            prompt_eval_count: chunk.prompt_eval_count,
+            prompt_eval_duration: chunk.prompt_eval_duration,
+            eval_count: chunk.eval_count,
+            eval_duration: chunk.eval_duration,
+            ..Default::default()
+        }.with_rates());
+
+        ChatResponse {
+            done: chunk.done,
+            message: chunk.message,
+            error: None,
+            stats,
+            context: None,
+        }
+    }
 }
 
 #[async_trait]
@@ -217,27 +662,17 @@ impl Backend for OllamaBackend {
         let mut models: Vec<SharedModel> = Vec::with_capacity(model_json.models.len());
 
         for m in model_json.models {
-            let model_name = m.name.clone();
-            let detail_res: ModelDetail = self
-                .call_backend("show", Method::POST, move |req| {
-                    req.json(&serde_json::json!({"model": model_name}))
-                })
-                .await?
-                .json()
-                .await?;
-
-            // A backend doesn't support embedding directly.
-            if detail_res.capabilities.contains(&Capability::Embedding) {
-                continue;
-            }
+            let detail_res = self.show(&m.name).await?;
+            let info = ModelInfo {
+                name: m.name,
+                id: m.model,
+                size: m.size,
+                modified_at: m.modified_at,
+                ..Default::default()
+            };
 
             models.push(Arc::new(RwLock::new(OllamaModel {
-                info: ModelInfo {
-                    name: m.name,
-                    id: m.model,
-                    size: m.size,
-                    capabilities: detail_res.capabilities,
-                },
+                info: detail_res.into_card(info).info,
                 backend: self.self_ref.clone(),
                 runtime_info: RwLock::new(None),
             })));
@@ -436,9 +871,18 @@ impl Model for OllamaModel {
         content: ChatMessage,
         history: &[ChatMessage],
         think: Option<bool>,
+        options: &GenerationOptions,
     ) -> Result<Box<dyn PromptResponse>, Error> {
         let res: Response = {
             let model_name = self.info.id.clone();
+            let keep_alive = options.keep_alive.clone().unwrap_or(DEFAULT_KEEP_ALIVE.to_owned());
+            let model_options = OllamaOptions::from(options);
+            let format = match &options.format {
+                Some(ResponseFormat::Json) => Some(serde_json::json!("json")),
+                Some(ResponseFormat::JsonSchema(schema)) => Some(schema.clone()),
+                None => None,
+            };
+            let tools: Vec<serde_json::Value> = options.tools.iter().map(ToolDefinition::to_function).collect();
             let mut messages: Vec<ChatMessage> = Vec::with_capacity(history.len() + 1);
             history.iter().for_each(|msg| messages.push(msg.clone()));
             messages.push(content);
@@ -450,17 +894,162 @@ impl Model for OllamaModel {
                 .call_backend("chat", Method::POST, move |req| {
                     req.json(&serde_json::json!({
                         "model": model_name,
-                        "keep_alive": "10m",
+                        "keep_alive": keep_alive,
                         "think": think.unwrap_or(false),
+                        "options": model_options,
+                        "format": format,
+                        "tools": tools,
                         "messages": messages
                     }))
                 })
                 .await?
         };
+        if !res.status().is_success() {
+            return Err(errors::response_error(res, &self.info.name, OLLAMA_NAME).await);
+        }
 
-        let reader = NdJsonReader::new();
+        let reader = NdJsonReader::<OllamaChatChunk>::new();
         reader.start_reading_response(res);
 
         Ok(Box::new(reader))
     }
+
+    async fn embed(&self, input: &[String]) -> Result<Embeddings, Error> {
+        let res: Response = {
+            let model_name = self.info.id.clone();
+            let input = input.to_vec();
+
+            let strong_backend = self.access_backend()?;
+            let backend = strong_backend.read().await;
+
+            backend
+                .call_backend("embed", Method::POST, move |req| {
+                    req.json(&serde_json::json!({
+                        "model": model_name,
+                        "keep_alive": DEFAULT_KEEP_ALIVE,
+                        "input": input
+                    }))
+                })
+                .await?
+        };
+        if !res.status().is_success() {
+            return Err(errors::response_error(res, &self.info.name, OLLAMA_NAME).await);
+        }
+
+        let embed_res: OllamaEmbedResponse = res.json().await?;
+        Ok(Embeddings::new(embed_res.embeddings))
+    }
+
+    /// Built from the details shown by Ollama.
+    async fn card(&self) -> Result<ModelCard, Error> {
+        let strong_backend = self.access_backend()?;
+        let detail = strong_backend.read().await.show(&self.info.name).await?;
+        Ok(detail.into_card(self.info.clone()))
+    }
+
+    /// Ollama doesn't use the whole context of a model unless requested.
+    fn context_window(&self, options: &GenerationOptions) -> Option<u32> {
+        let length = options.context_length.unwrap_or_else(default_context_length);
+        Some(self.info.context_length.map_or(length, |max| length.min(max)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::sync::mpsc::channel;
+    use uuid::Uuid;
+
+    use super::*;
+
+    /// Writes `content` to a new file in the temporary directory.
+    fn temp_file(name: &str, content: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ollama-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn adapters_need_absolute_paths_of_files() {
+        let path = temp_file("lora.gguf", b"adapter");
+        let resolved = resolve_adapter(path.to_str().unwrap()).unwrap();
+        assert_eq!(resolved, path.canonicalize().unwrap());
+
+        for invalid in ["lora.gguf", "./lora.gguf"] {
+            assert!(matches!(resolve_adapter(invalid), Err(Error::InvalidModelfile(_))));
+        }
+        let missing = path.with_file_name("missing.gguf");
+        assert!(matches!(resolve_adapter(missing.to_str().unwrap()), Err(Error::InvalidModelfile(_))));
+        let dir = path.parent().unwrap();
+        assert!(matches!(resolve_adapter(dir.to_str().unwrap()), Err(Error::InvalidModelfile(_))));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn create_request_refers_to_uploaded_adapters() {
+        let modelfile = Modelfile {
+            adapters: vec!["/models/lora.gguf".into()],
+            parameters: vec![
+                ("temperature".into(), 0.5.into()),
+                ("stop".into(), "<|eot_id|>".into()),
+            ],
+            ..Modelfile::new("llama3.2")
+        };
+        let blobs = HashMap::from([("/models/lora.gguf".to_owned(), "sha256:1234".to_owned())]);
+        let request = OllamaCreateRequest::new("assistant", &modelfile, &blobs).unwrap();
+        assert_eq!(request.adapters, HashMap::from([("lora.gguf".to_owned(), "sha256:1234".to_owned())]));
+        assert_eq!(request.from.as_deref(), Some("llama3.2"));
+        assert_eq!(request.parameters["temperature"], serde_json::json!(0.5));
+        assert_eq!(request.parameters["stop"], serde_json::json!(["<|eot_id|>"]));
+
+        let missing = OllamaCreateRequest::new("assistant", &modelfile, &HashMap::new());
+        assert!(matches!(missing, Err(Error::InvalidModelfile(_))));
+    }
+
+    #[tokio::test]
+    async fn hashes_files_like_ollama() {
+        let path = temp_file("model.gguf", b"hello");
+        let (tx_progress, mut rx_progress) = channel(64);
+        let digest = hash_file(&path, &tx_progress).await.unwrap();
+        assert_eq!(digest, "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
+
+        let progress = rx_progress.recv().await.unwrap();
+        assert_eq!(progress.status, "hashing");
+        assert_eq!((progress.completed, progress.total), (Some(5), Some(5)));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_uploads_name_the_file() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Rejects the upload like Ollama does for a wrong digest
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let blob_url = Url::parse(&format!("http://{}/api/blobs/sha256:0", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            let body = r#"{"error":"digest mismatch"}"#;
+            let response = format!(
+                "HTTP/1.1 400 Bad Request\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let path = temp_file("lora.gguf", b"adapter");
+        let (tx, _rx) = channel(16);
+        let result = upload_file(&Client::new(), blob_url, &path, "sha256:0", &tx).await;
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        match result {
+            Err(Error::Upload { file, reason }) => {
+                assert_eq!((file, reason.as_str()), (path.display().to_string(), "digest mismatch"));
+            },
+            other => panic!("Unexpected result {other:?}")
+        }
+    }
 }
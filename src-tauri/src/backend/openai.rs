@@ -0,0 +1,774 @@
+use std::{ops::Deref, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use log::info;
+use reqwest::{Client, IntoUrl, Method, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use time::UtcDateTime;
+use tokio::sync::{mpsc::{channel, Receiver}, RwLock};
+use url::Url;
+
+use crate::{
+    backend::{
+        chat::{image_data_url, ChatMessage, ChatResponse, GenerationStats, Role, ToolCall, ToolCallFunction},
+        llm::{
+            Backend, Capability, Embeddings, GenerationOptions, Model, ModelInfo, PromptResponse, ResponseFormat,
+            RuntimeInfo, SharedBackend, SharedBackendImpl, SharedModel, WeakBackend,
+        },
+        reader::{sse_reader::SseReader, stream_reader::StreamData},
+        tools::ToolDefinition,
+    },
+    errors::{self, Error, ErrorKind},
+};
+
+pub(crate) static OPENAI_NAME: &'static str = "OpenAI";
+
+pub(crate) fn not_openai() -> errors::Error {
+    errors::internal("Backend is not OpenAI")
+}
+
+/// Backend for servers implementing the OpenAI API
+/// like vLLM, LM Studio or llama.cpp.
+/// The server is not managed by us and has to be started externally.
+pub struct OpenAiBackend {
+    http_client: Client,
+    api_url: Url,
+    api_key: Option<String>,
+    models: Vec<SharedModel>,
+    self_ref: WeakBackend<OpenAiBackend>,
+}
+
+#[derive(Clone)]
+pub struct SharedOpenAiBackend(pub SharedBackendImpl<OpenAiBackend>);
+
+impl SharedOpenAiBackend {
+    pub fn new(mut api_url: Url, api_key: Option<String>) -> SharedBackend {
+        OpenAiBackend::prepare_api_url(&mut api_url);
+
+        Arc::new_cyclic(|me| {
+            RwLock::new(OpenAiBackend {
+                http_client: Client::new(),
+                api_url: api_url,
+                api_key: api_key,
+                models: Vec::new(),
+                self_ref: me.clone(),
+            })
+        })
+    }
+}
+
+impl Deref for SharedOpenAiBackend {
+    type Target = SharedBackendImpl<OpenAiBackend>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl OpenAiBackend {
+    async fn call_backend(
+        &self,
+        url: &str,
+        method: Method,
+        req_builder: impl FnOnce(RequestBuilder) -> RequestBuilder + 'static,
+    ) -> reqwest::Result<reqwest::Response> {
+        let url = self.api_url.join(url).unwrap();
+        let mut builder = self.http_client.request(method, url);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+        builder = req_builder(builder);
+
+        self.http_client.execute(builder.build()?).await
+    }
+
+    async fn call_backend_default(&self, url: &str) -> reqwest::Result<reqwest::Response> {
+        self.call_backend(url, Method::GET, |r| r).await
+    }
+
+    pub fn set_api_url(&mut self, url: impl IntoUrl) -> Result<(), errors::Error> {
+        let mut url = url.into_url()?;
+        OpenAiBackend::prepare_api_url(&mut url);
+
+        self.api_url = url;
+        Ok(())
+    }
+
+    /// Append trailing slash (/) if not already there
+    pub fn prepare_api_url(url: &mut Url) {
+        if !url.as_str().ends_with("/") {
+            url.set_path(&format!("{}/", url.path()));
+        }
+    }
+
+    pub fn get_api_url(&self) -> &Url {
+        &self.api_url
+    }
+
+    /// Sets the bearer token send with every request.
+    /// An empty key disables authentication.
+    pub fn set_api_key(&mut self, api_key: Option<String>) {
+        self.api_key = api_key.filter(|key| !key.is_empty());
+    }
+
+    pub fn has_api_key(&self) -> bool {
+        self.api_key.is_some()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ModelResInner {
+    id: String,
+    /// Context length reported by vLLM
+    max_model_len: Option<u32>,
+    /// Unix timestamp in seconds
+    created: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ModelResponse {
+    data: Vec<ModelResInner>,
+}
+
+#[async_trait]
+impl Backend for OpenAiBackend {
+    fn name(&self) -> &str {
+        OPENAI_NAME
+    }
+
+    fn models(&self) -> &[SharedModel] {
+        &self.models
+    }
+
+    async fn update_models(&mut self) -> Result<(), errors::Error> {
+        let res = self.call_backend_default("models").await?.error_for_status()?;
+        let model_json: ModelResponse = res.json().await?;
+
+        self.models = model_json.data
+            .into_iter()
+            .map(|m| Arc::new(RwLock::new(OpenAiModel {
+                info: ModelInfo {
+                    name: m.id.clone(),
+                    // The API doesn't tell us anything about the model,
+                    // so we can only guess embedding models by their name
+                    capabilities: if m.id.to_lowercase().contains("embed") {
+                        vec![Capability::Embedding]
+                    } else {
+                        vec![Capability::Completion]
+                    },
+                    id: m.id,
+                    size: 0,
+                    context_length: m.max_model_len,
+                    modified_at: m.created.and_then(|created| UtcDateTime::from_unix_timestamp(created).ok()),
+                    ..Default::default()
+                },
+                backend: self.self_ref.clone(),
+            })) as SharedModel)
+            .collect();
+        Ok(())
+    }
+
+    async fn get_running_models(&self) -> Result<Vec<RuntimeInfo>, errors::Error> {
+        // There is no API for querying loaded models.
+        // The server manages them on its own.
+        Ok(Vec::new())
+    }
+
+    async fn running(&self) -> bool {
+        let res = self
+            .call_backend("models", Method::GET, |req| {
+                req.header("Cache", "no-store")
+                    .timeout(Duration::from_secs(2))
+            })
+            .await;
+
+        match res {
+            Ok(res) => res.status().is_success(),
+            Err(_) => false,
+        }
+    }
+
+    async fn boot(&mut self) -> Result<(), errors::Error> {
+        // We cannot start the server ourselves
+        if self.running().await {
+            info!("Boot - OpenAI server is reachable at {}", self.api_url);
+            return Ok(());
+        }
+        Err(Error::BackendBoot {
+            reason: format!("Server at '{}' is not reachable", self.api_url),
+            backend: self.name().to_owned(),
+        })
+    }
+
+    async fn shutdown(&mut self) -> Result<(), errors::Error> {
+        self.models.clear();
+        Ok(())
+    }
+}
+
+pub struct OpenAiModel {
+    info: ModelInfo,
+    backend: WeakBackend<OpenAiBackend>,
+}
+
+impl OpenAiModel {
+    /// Gets a strong reference to the backend.
+    /// Fails if the backend has already been dropped.
+    fn access_backend(&self) -> Result<Arc<RwLock<OpenAiBackend>>, Error> {
+        self.backend
+            .upgrade()
+            .ok_or(errors::internal("Backend is already disposed"))
+    }
+}
+
+#[async_trait]
+impl Model for OpenAiModel {
+    fn info(&self) -> &ModelInfo {
+        &self.info
+    }
+
+    fn backend(&self) -> Option<SharedBackend> {
+        self.backend
+            .upgrade()
+            .map(|strong| strong as Arc<RwLock<dyn Backend>>)
+    }
+
+    async fn loaded(&self) -> Result<bool, Error> {
+        // Models are loaded on demand by the server
+        Ok(false)
+    }
+
+    async fn get_loaded_size(&self) -> Result<i64, Error> {
+        Ok(-1)
+    }
+
+    async fn get_runtime_info(&self) -> Result<Option<RuntimeInfo>, errors::Error> {
+        Ok(None)
+    }
+
+    async fn load(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn unload(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn prompt(
+        &self,
+        content: ChatMessage,
+        history: &[ChatMessage],
+        _think: Option<bool>,
+        options: &GenerationOptions,
+    ) -> Result<Box<dyn PromptResponse>, Error> {
+        let res: Response = {
+            let request = OpenAiChatRequest {
+                model: self.info.id.clone(),
+                stream: true,
+                stream_options: serde_json::json!({"include_usage": true}),
+                messages: history
+                    .iter()
+                    .chain(std::iter::once(&content))
+                    .map(OpenAiMessage::from)
+                    .collect(),
+                tools: options.tools.iter().map(ToolDefinition::to_function).collect(),
+                options: OpenAiOptions::from(options),
+            };
+
+            let strong_backend = self.access_backend()?;
+            let backend = strong_backend.read().await;
+
+            backend
+                .call_backend("chat/completions", Method::POST, move |req| {
+                    req.json(&request)
+                })
+                .await?
+        };
+        if !res.status().is_success() {
+            return Err(errors::response_error(res, &self.info.name, OPENAI_NAME).await);
+        }
+
+        let reader = SseReader::<OpenAiChatChunk>::new();
+        reader.start_reading_response(res);
+
+        Ok(Box::new(OpenAiPromptResponse { reader }))
+    }
+
+    async fn embed(&self, input: &[String]) -> Result<Embeddings, Error> {
+        let res: Response = {
+            let request = serde_json::json!({
+                "model": self.info.id,
+                "input": input
+            });
+
+            let strong_backend = self.access_backend()?;
+            let backend = strong_backend.read().await;
+
+            backend
+                .call_backend("embeddings", Method::POST, move |req| {
+                    req.json(&request)
+                })
+                .await?
+        };
+        if !res.status().is_success() {
+            return Err(errors::response_error(res, &self.info.name, OPENAI_NAME).await);
+        }
+
+        let mut embed_res: OpenAiEmbeddingResponse = res.json().await?;
+        embed_res.data.sort_by_key(|e| e.index);
+        Ok(Embeddings::new(embed_res.data.into_iter().map(|e| e.embedding).collect()))
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Response of `/embeddings`.
+/// The embeddings may not be in the order of the inputs.
+#[derive(Deserialize)]
+struct OpenAiEmbeddingResponse {
+    data: Vec<OpenAiEmbedding>,
+}
+
+#[derive(Serialize)]
+struct OpenAiChatRequest {
+    model: String,
+    stream: bool,
+    stream_options: serde_json::Value,
+    messages: Vec<OpenAiMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+    #[serde(flatten)]
+    options: OpenAiOptions,
+}
+
+/// Sampling parameters of a request.
+/// `top_k`, `min_p` and `repeat_penalty` are not part of the OpenAI API
+/// but are understood by llama.cpp and vLLM.
+/// The context length cannot be set per request.
+#[derive(Serialize)]
+struct OpenAiOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repeat_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+}
+
+impl From<&GenerationOptions> for OpenAiOptions {
+    fn from(options: &GenerationOptions) -> Self {
+        OpenAiOptions {
+            temperature: options.temperature,
+            top_p: options.top_p,
+            top_k: options.top_k,
+            min_p: options.min_p,
+            repeat_penalty: options.repeat_penalty,
+            seed: options.seed,
+            max_tokens: options.max_tokens,
+            stop: options.stop.clone(),
+            response_format: options.format.as_ref().map(|format| match format {
+                ResponseFormat::Json => serde_json::json!({"type": "json_object"}),
+                ResponseFormat::JsonSchema(schema) => serde_json::json!({
+                    "type": "json_schema",
+                    "json_schema": {"name": "response", "schema": schema, "strict": true}
+                }),
+            }),
+        }
+    }
+}
+
+/// Message in the format of the chat completions API.
+/// Messages with images use the multi part content format.
+#[derive(Serialize)]
+struct OpenAiMessage {
+    role: Role,
+    content: serde_json::Value,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+impl From<&ChatMessage> for OpenAiMessage {
+    fn from(msg: &ChatMessage) -> Self {
+        let content = if msg.images().is_empty() {
+            serde_json::json!(msg.content())
+        } else {
+            let mut parts = vec![serde_json::json!({"type": "text", "text": msg.content()})];
+            for image in msg.images() {
+                parts.push(serde_json::json!({
+                    "type": "image_url",
+                    "image_url": {"url": image_data_url(image)}
+                }));
+            }
+            serde_json::Value::Array(parts)
+        };
+
+        // The API expects the arguments as encoded JSON
+        let tool_calls = msg.tool_calls()
+            .iter()
+            .enumerate()
+            .map(|(i, call)| serde_json::json!({
+                "id": call.id.clone().unwrap_or_else(|| format!("call_{i}")),
+                "type": "function",
+                "function": {
+                    "name": call.function.name,
+                    "arguments": match &call.function.arguments {
+                        serde_json::Value::String(encoded) => encoded.clone(),
+                        arguments => arguments.to_string(),
+                    }
+                }
+            }))
+            .collect();
+
+        OpenAiMessage {
+            role: msg.role().clone(),
+            content,
+            tool_calls,
+            tool_call_id: msg.tool_call_id().map(str::to_owned),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct OpenAiDelta {
+    #[serde(default)]
+    content: Option<String>,
+    /// Thoughts of reasoning models (vLLM, DeepSeek, llama.cpp)
+    #[serde(default)]
+    reasoning_content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<OpenAiToolCallDelta>,
+}
+
+/// Fragment of a tool call.
+/// The arguments of a call are streamed in pieces and
+/// have to be joined using the index of the call.
+#[derive(Deserialize, Debug)]
+struct OpenAiToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    id: Option<String>,
+    function: Option<OpenAiFunctionDelta>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAiFunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAiChoice {
+    #[serde(default)]
+    delta: OpenAiDelta,
+    finish_reason: Option<String>,
+}
+
+/// Token counts send in the last chunk
+/// if `stream_options.include_usage` is set.
+#[derive(Deserialize, Debug)]
+struct OpenAiUsage {
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
+}
+
+/// Timings reported by llama.cpp in milliseconds.
+#[derive(Deserialize, Debug)]
+struct LlamaCppTimings {
+    prompt_n: Option<u64>,
+    prompt_ms: Option<f64>,
+    predicted_n: Option<u64>,
+    predicted_ms: Option<f64>,
+}
+
+/// Some servers report errors during generation
+/// as a regular event containing an error object.
+#[derive(Deserialize, Debug)]
+struct OpenAiChatChunk {
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    choices: Vec<OpenAiChoice>,
+    error: Option<serde_json::Value>,
+    usage: Option<OpenAiUsage>,
+    timings: Option<LlamaCppTimings>,
+}
+
+impl OpenAiChatChunk {
+    fn stats(&self) -> Option<GenerationStats> {
+        if self.usage.is_none() && self.timings.is_none() {
+            return None;
+        }
+        let ms_to_ns = |ms: Option<f64>| ms.map(|ms| (ms * 1_000_000.0) as u64);
+        let timings = self.timings.as_ref();
+        let prompt_eval_duration = ms_to_ns(timings.and_then(|t| t.prompt_ms));
+        let eval_duration = ms_to_ns(timings.and_then(|t| t.predicted_ms));
+
+        Some(GenerationStats {
+            prompt_eval_count: self.usage.as_ref().and_then(|u| u.prompt_tokens)
+                .or(timings.and_then(|t| t.prompt_n)),
+            eval_count: self.usage.as_ref().and_then(|u| u.completion_tokens)
+                .or(timings.and_then(|t| t.predicted_n)),
+            total_duration: prompt_eval_duration.zip(eval_duration).map(|(p, e)| p + e),
+            prompt_eval_duration,
+            eval_duration,
+            ..Default::default()
+        }.with_rates())
+    }
+}
+
+/// Tool call which is still being streamed.
+#[derive(Default)]
+struct PendingToolCall {
+    id: Option<String>,
+    name: String,
+    arguments: String,
+}
+
+/// Converts chunks into responses.
+/// Tool calls are collected over multiple chunks and
+/// passed on with the chunk finishing the choice.
+#[derive(Default)]
+struct OpenAiChunkConverter {
+    tool_calls: Vec<PendingToolCall>,
+    /// The choice has been finished but the last response is still missing
+    finished: bool,
+    /// Statistics of the chunk finishing the choice, e.g. timings of llama.cpp
+    finish_stats: Option<GenerationStats>,
+}
+
+impl OpenAiChunkConverter {
+    fn convert(&mut self, chunk: OpenAiChatChunk) -> ChatResponse {
+        if let Some(error) = chunk.error {
+            let msg = errors::backend_message(&error).unwrap_or(error.to_string());
+            return ChatResponse::error(ErrorKind::from_backend_message(&msg, &chunk.model, OPENAI_NAME));
+        }
+
+        // The usage is send in an extra chunk after the one containing the
+        // finish reason. If a server doesn't send it, the stream just ends
+        // and the response is finished by [OpenAiChunkConverter::finish].
+        let mut stats = chunk.stats();
+        let done = chunk.usage.is_some();
+        let (delta, finished) = chunk.choices
+            .into_iter()
+            .next()
+            .map(|c| (c.delta, c.finish_reason.is_some()))
+            .unwrap_or_default();
+        if !done && finished {
+            self.finish_stats = stats.take();
+        }
+        self.finished = (self.finished || finished) && !done;
+
+        for fragment in delta.tool_calls {
+            if self.tool_calls.len() <= fragment.index {
+                self.tool_calls.resize_with(fragment.index + 1, Default::default);
+            }
+            let call = &mut self.tool_calls[fragment.index];
+            if fragment.id.is_some() {
+                call.id = fragment.id;
+            }
+            if let Some(function) = fragment.function {
+                call.name.push_str(&function.name.unwrap_or_default());
+                call.arguments.push_str(&function.arguments.unwrap_or_default());
+            }
+        }
+
+        let content = delta.content.unwrap_or_default();
+        let message = if finished && !self.tool_calls.is_empty() {
+            let tool_calls = std::mem::take(&mut self.tool_calls)
+                .into_iter()
+                .map(|call| ToolCall {
+                    id: call.id,
+                    function: ToolCallFunction {
+                        name: call.name,
+                        arguments: serde_json::from_str(&call.arguments)
+                            .unwrap_or(serde_json::Value::String(call.arguments)),
+                    },
+                })
+                .collect();
+            ChatMessage::with_tool_calls(content, delta.reasoning_content, tool_calls)
+        } else {
+            ChatMessage::with_thoughts(Role::Assistant, content, delta.reasoning_content)
+        };
+
+        ChatResponse {
+            done,
+            message,
+            error: None,
+            stats,
+            context: None,
+        }
+    }
+
+    /// Last response if the stream ended after the finish reason without a usage chunk.
+    fn finish(&mut self) -> Option<ChatResponse> {
+        if !std::mem::take(&mut self.finished) {
+            return None;
+        }
+        Some(ChatResponse {
+            done: true,
+            message: ChatMessage::new(Role::Assistant, String::new()),
+            error: None,
+            stats: self.finish_stats.take(),
+            context: None,
+        })
+    }
+}
+
+struct OpenAiPromptResponse {
+    reader: SseReader<OpenAiChatChunk>,
+}
+
+#[async_trait]
+impl PromptResponse for OpenAiPromptResponse {
+    fn get_prompts(&mut self) -> Result<Receiver<ChatResponse>, errors::Error> {
+        let (sender, receiver) = channel(64);
+        let mut events = self.reader.receiver().ok_or(errors::internal("Data is already being unwrapped"))?;
+        tokio::spawn(async move {
+            let mut converter = OpenAiChunkConverter::default();
+            while let Some(data) = events.recv().await {
+                let response = match data {
+                    StreamData::Data(chunk) => converter.convert(chunk),
+                    StreamData::Error(e) => ChatResponse::error(e),
+                    StreamData::End => match converter.finish() {
+                        Some(response) => response,
+                        None => break,
+                    },
+                };
+                let done = response.done;
+                if sender.send(response).await.is_err() || done {
+                    break;
+                }
+            }
+        });
+        Ok(receiver)
+    }
+
+    async fn abort(&self) {
+        self.reader.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{OpenAiChatChunk, OpenAiChunkConverter};
+
+    fn chunk(value: serde_json::Value) -> OpenAiChatChunk {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn delta(delta: serde_json::Value, finish_reason: Option<&str>) -> OpenAiChatChunk {
+        chunk(json!({ "choices": [{ "delta": delta, "finish_reason": finish_reason }] }))
+    }
+
+    #[test]
+    fn joins_fragments_of_tool_calls() {
+        let mut converter = OpenAiChunkConverter::default();
+        let fragments = [
+            json!({ "tool_calls": [{ "index": 0, "id": "call_1", "function": { "name": "search", "arguments": "" } }] }),
+            json!({ "tool_calls": [{ "index": 0, "function": { "arguments": "{\"query\":" } }] }),
+            json!({ "tool_calls": [{ "index": 1, "id": "call_2", "function": { "name": "weather", "arguments": "{\"city\"" } }] }),
+            json!({ "tool_calls": [{ "index": 0, "function": { "arguments": " \"rust\"}" } }] }),
+            json!({ "tool_calls": [{ "index": 1, "function": { "arguments": ": \"Oslo\"}" } }] }),
+        ];
+        for fragment in fragments {
+            let response = converter.convert(delta(fragment, None));
+            assert!(response.message.tool_calls().is_empty() && !response.done);
+        }
+
+        let response = converter.convert(delta(json!({}), Some("tool_calls")));
+        let calls = response.message.tool_calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!((calls[0].id.as_deref(), calls[0].function.name.as_str()), (Some("call_1"), "search"));
+        assert_eq!(calls[0].function.arguments, json!({ "query": "rust" }));
+        assert_eq!((calls[1].id.as_deref(), calls[1].function.name.as_str()), (Some("call_2"), "weather"));
+        assert_eq!(calls[1].function.arguments, json!({ "city": "Oslo" }));
+    }
+
+    #[test]
+    fn keeps_invalid_arguments_as_text() {
+        let mut converter = OpenAiChunkConverter::default();
+        let fragment = json!({ "tool_calls": [{ "index": 0, "function": { "name": "search", "arguments": "{\"query\"" } }] });
+        converter.convert(delta(fragment, None));
+        let response = converter.convert(delta(json!({}), Some("tool_calls")));
+        assert_eq!(response.message.tool_calls()[0].function.arguments, json!("{\"query\""));
+    }
+
+    #[test]
+    fn finishes_without_usage_chunk() {
+        let mut converter = OpenAiChunkConverter::default();
+        assert!(converter.finish().is_none());
+
+        let response = converter.convert(delta(json!({ "content": "Hi" }), None));
+        assert_eq!((response.message.content(), response.done), ("Hi", false));
+        let response = converter.convert(delta(json!({ "content": "!" }), Some("stop")));
+        assert_eq!((response.message.content(), response.done), ("!", false));
+
+        // The stream ends without usage
+        let response = converter.finish().unwrap();
+        assert!(response.done && response.stats.is_none());
+        assert!(converter.finish().is_none());
+    }
+
+    #[test]
+    fn finishes_with_usage_chunk() {
+        let mut converter = OpenAiChunkConverter::default();
+        converter.convert(delta(json!({ "content": "Hi" }), Some("stop")));
+        let response = converter.convert(chunk(json!({
+            "choices": [],
+            "usage": { "prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15 }
+        })));
+        assert!(response.done && response.message.content().is_empty());
+        let stats = response.stats.unwrap();
+        assert_eq!((stats.prompt_eval_count, stats.eval_count), (Some(12), Some(3)));
+        assert!(converter.finish().is_none());
+    }
+
+    #[test]
+    fn reads_timings_of_llama_cpp() {
+        let mut converter = OpenAiChunkConverter::default();
+        let response = converter.convert(chunk(json!({
+            "choices": [{ "delta": {}, "finish_reason": "stop" }],
+            "timings": { "prompt_n": 10, "prompt_ms": 50.0, "predicted_n": 20, "predicted_ms": 400.0 }
+        })));
+        assert!(!response.done && response.stats.is_none());
+
+        let stats = converter.finish().unwrap().stats.unwrap();
+        assert_eq!((stats.prompt_eval_count, stats.eval_count), (Some(10), Some(20)));
+        assert_eq!((stats.prompt_eval_duration, stats.eval_duration), (Some(50_000_000), Some(400_000_000)));
+        assert_eq!(stats.total_duration, Some(450_000_000));
+        assert_eq!((stats.prompt_tokens_per_second, stats.tokens_per_second), (Some(200.0), Some(50.0)));
+    }
+
+    #[test]
+    fn passes_on_thoughts() {
+        let mut converter = OpenAiChunkConverter::default();
+        let response = converter.convert(delta(json!({ "reasoning_content": "Hmm" }), None));
+        assert_eq!(response.message.thoughts(), Some("Hmm"));
+    }
+
+    #[test]
+    fn reports_errors_in_chunks() {
+        let mut converter = OpenAiChunkConverter::default();
+        let response = converter.convert(chunk(json!({ "error": { "message": "Something went wrong" } })));
+        assert!(response.done && response.error.is_some());
+    }
+}
@@ -1 +1,4 @@
+pub(crate) mod line_framer;
 pub(crate) mod ndjson_reader;
+pub(crate) mod sse_reader;
+pub(crate) mod stream_reader;
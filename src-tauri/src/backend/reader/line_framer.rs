@@ -0,0 +1,64 @@
+use std::string::FromUtf8Error;
+
+/// Incremental splitter of a byte stream into UTF-8 lines.
+/// Lines may be terminated by LF, CRLF or a single CR.
+///
+/// Bytes are buffered until a line is complete and only then decoded.
+/// Since line breaks are never part of a multi-byte UTF-8 sequence,
+/// code points split across chunks are carried over to the next chunk.
+#[derive(Default)]
+pub(crate) struct LineFramer {
+    buffer: Vec<u8>,
+    // The last byte has been a CR and a following LF belongs to the same line ending
+    skip_lf: bool
+}
+
+impl LineFramer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the next chunk of the stream and returns every
+    /// line completed by it, without the line terminators.
+    pub fn feed(&mut self, mut chunk: &[u8]) -> Result<Vec<String>, FromUtf8Error> {
+        let mut lines = Vec::new();
+
+        if self.skip_lf && !chunk.is_empty() {
+            self.skip_lf = false;
+            if chunk[0] == b'\n' {
+                chunk = &chunk[1..];
+            }
+        }
+
+        while let Some(pos) = chunk.iter().position(|b| *b == b'\n' || *b == b'\r') {
+            self.buffer.extend_from_slice(&chunk[..pos]);
+            let line = std::mem::take(&mut self.buffer);
+            lines.push(String::from_utf8(line)?);
+
+            let is_cr = chunk[pos] == b'\r';
+            chunk = &chunk[pos + 1..];
+            if is_cr {
+                match chunk.first() {
+                    Some(b'\n') => chunk = &chunk[1..],
+                    Some(_) => {},
+                    // We don't know yet whether a LF follows
+                    None => self.skip_lf = true
+                }
+            }
+        }
+        self.buffer.extend_from_slice(chunk);
+
+        Ok(lines)
+    }
+
+    /// Ends the stream and returns the last line
+    /// if it has not been terminated.
+    pub fn finish(&mut self) -> Result<Option<String>, FromUtf8Error> {
+        self.skip_lf = false;
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        let line = std::mem::take(&mut self.buffer);
+        String::from_utf8(line).map(Some)
+    }
+}
@@ -1,145 +1,205 @@
-use async_trait::async_trait;
 use log::error;
 use serde::Deserialize;
-use core::str;
+use std::{collections::VecDeque, marker::PhantomData, string::FromUtf8Error};
 
-use bytes::Bytes;
-use tokio::sync::mpsc::{Sender, Receiver, channel};
-
-use crate::{backend::{chat::ChatResponse, llm::PromptResponse}, errors};
-
-pub(crate) enum NdJsonData<T> {
-    Data(T),
-    End
-}
+use crate::{
+    backend::reader::{line_framer::LineFramer, stream_reader::{StreamData, StreamDecoder, StreamReader}},
+    errors::ErrorKind
+};
 
 /// Reads raw responses as ND-JSON (like from Ollama) and parses them into
 /// a token stream. We expect UTF-8 data (the default using Ollama).
-pub(crate) struct NdJsonReader<T> {
-    tx_data: Sender<NdJsonData<Bytes>>,
-    rx_events: Option<Receiver<NdJsonData<T>>>
+pub(crate) type NdJsonReader<T> = StreamReader<NdJsonDecoder<T>>;
+
+/// Incremental decoder for newline-delimited JSON.
+/// Raw bytes can be fed in arbitrary chunks and every complete
+/// object can be taken right away by iterating over the decoder.
+pub(crate) struct NdJsonDecoder<T> {
+    lines: LineFramer,
+    pending: VecDeque<String>,
+    _marker: PhantomData<T>
 }
 
-impl<T> NdJsonReader<T>
+impl<T> NdJsonDecoder<T>
 where
-    T: for<'de> Deserialize<'de> + Send + 'static
+    T: for<'de> Deserialize<'de>
 {
     pub fn new() -> Self {
-        const BUFFER_SIZE: usize = 1024;
-        let (tx, mut rx) = channel(BUFFER_SIZE);
-        let (tx_ev, rx_ev) = channel(256);
-        let obj = NdJsonReader {
-            tx_data: tx,
-            rx_events: Some(rx_ev)
-        };
+        Self {
+            lines: LineFramer::new(),
+            pending: VecDeque::new(),
+            _marker: PhantomData
+        }
+    }
 
-        tokio::spawn(async move {
-            let mut buffer: Vec<NdJsonData<Bytes>> = Vec::with_capacity(BUFFER_SIZE);
-            let mut json_buffer = String::new();
+    /// Feeds the next chunk of raw data.
+    /// Fails if a completed line is not valid UTF-8.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), FromUtf8Error> {
+        self.pending.extend(self.lines.feed(chunk)?);
+        Ok(())
+    }
 
-            'outer: loop {
-                if rx.is_closed() {
-                    break;
-                }
+    /// Ends the stream. The last object may
+    /// not have been terminated by a newline.
+    pub fn finish(&mut self) -> Result<(), FromUtf8Error> {
+        self.pending.extend(self.lines.finish()?);
+        Ok(())
+    }
+}
 
-                // Buffer data until we have a complete JSON object
-                // We send the object right after parsing it and clear the JSON buffer
-                let _ = rx.recv_many(&mut buffer, BUFFER_SIZE).await;
-                for data in &buffer {
-                    match data {
-                        NdJsonData::End => break 'outer,
-                        NdJsonData::Data(data) => {
-                            // Buffer the data as string
-                            match str::from_utf8(&data) {
-                                Ok(str) => json_buffer.push_str(str),
-                                Err(e) => {
-                                    error!("Unexpected Ollama string encoding: {:?}", e);
-                                    let _ = tx_ev.send(NdJsonData::End).await;
-                                    break 'outer;
-                                }
-                            };
-
-                            // Search for newline
-                            let Some((json_slice, slice)) = json_buffer.split_once('\n') else {
-                                continue;
-                            };
-
-                            // We have a full JSON object
-                            match serde_json::from_str(&json_slice) {
-                                Ok(value) => {
-                                    let _ = tx_ev.send(NdJsonData::Data(value)).await;
-                                },
-                                Err(e) => {
-                                    error!("Invalid JSON received from Ollama chat completion: {:?}", e);
-                                    error!("Value: {json_slice}");
-                                    let _ = tx_ev.send(NdJsonData::End).await;
-                                    break 'outer;
-                                }
-                            }
-
-                            // Keep the remaining data without the newline
-                            json_buffer = slice.to_owned();
-                        }
-                    }
-                }
-                buffer.clear(); // Clear for the next chunk of data
+impl<T> Iterator for NdJsonDecoder<T>
+where
+    T: for<'de> Deserialize<'de>
+{
+    type Item = Result<T, serde_json::Error>;
+
+    /// Parses the next complete object.
+    /// Returns [None] if more data is needed.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.pending.pop_front()?;
+            if line.trim().is_empty() {
+                continue;
             }
-            let _ = tx_ev.send(NdJsonData::End);
-        });
-
-        return obj;
+            return Some(serde_json::from_str(&line).inspect_err(|_| {
+                error!("Value: {line}");
+            }));
+        }
     }
+}
 
-    /// Sender for queueing data received from Ollama
-    pub fn sender(&self) -> Sender<NdJsonData<Bytes>> {
-        self.tx_data.clone()
+impl<T> Default for NdJsonDecoder<T>
+where
+    T: for<'de> Deserialize<'de>
+{
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    pub fn receiver(&mut self) -> Option<Receiver<NdJsonData<T>>> {
-        self.rx_events.take()
-    }
+impl<T> StreamDecoder for NdJsonDecoder<T>
+where
+    T: for<'de> Deserialize<'de> + Send + 'static
+{
+    type Item = T;
 
-    pub fn start_reading_response(&self, mut res: reqwest::Response) {
-        let sink = self.sender();
-        tokio::spawn(async move {
-            while let Ok(chunk) = res.chunk().await {
-                if let Some(chunk) = chunk {
-                    if let Err(_) = sink.send(NdJsonData::Data(chunk)).await {
-                        break;
-                    }
-                } else {
-                    // We don't remove the data from the stream using "chunk".
-                    // So if we don't have any more data, we need to break manually
-                    break;
-                }
-            }
-            let _ = sink.send(NdJsonData::End).await;
-        });
-    }
-
-    pub fn start_unwrapping_data(&mut self, buffer_size: usize) -> Result<Receiver<T>, errors::Error> {
-        let (sender, receiver) = channel::<T>(buffer_size);
-        let mut data_receiver = self.receiver().ok_or(errors::internal("Data is already being unwrapped"))?;
-        tokio::spawn(async move {
-            while let Some(data) = data_receiver.recv().await {
-                if let NdJsonData::Data(data) = data {
-                    let _ = sender.send(data).await;
-                } else {
+    fn decode(&mut self, chunk: Option<&[u8]>) -> Vec<StreamData<T>> {
+        let fed = match chunk {
+            Some(chunk) => self.feed(chunk),
+            None => self.finish()
+        };
+        if let Err(e) = fed {
+            error!("Unexpected string encoding: {:?}", e);
+            return vec![StreamData::Error(ErrorKind::InvalidResponse(e.to_string()))];
+        }
+
+        let mut decoded = Vec::new();
+        for value in self {
+            match value {
+                Ok(value) => decoded.push(StreamData::Data(value)),
+                Err(e) => {
+                    error!("Invalid JSON received: {:?}", e);
+                    decoded.push(StreamData::Error(ErrorKind::InvalidResponse(e.to_string())));
                     break;
                 }
             }
-        });
-        Ok(receiver)
+        }
+        decoded
     }
 }
 
-#[async_trait]
-impl PromptResponse for NdJsonReader<ChatResponse> {
-    fn get_prompts(&mut self) -> Result<Receiver<ChatResponse>, errors::Error> {
-        self.start_unwrapping_data(64)
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::NdJsonDecoder;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Token {
+        content: String,
+        done: bool
+    }
+
+    // Contains two, three and four byte UTF-8 sequences
+    const STREAM: &str = concat!(
+        "{\"content\":\"Hällo\",\"done\":false}\n",
+        "{\"content\":\"🦀 €\",\"done\":false}\r\n",
+        "\n",
+        "{\"content\":\"日本\",\"done\":true}\n"
+    );
+
+    fn expected() -> Vec<Token> {
+        vec![
+            Token { content: "Hällo".into(), done: false },
+            Token { content: "🦀 €".into(), done: false },
+            Token { content: "日本".into(), done: true },
+        ]
+    }
+
+    fn decode_chunks<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> Vec<Token> {
+        let mut decoder = NdJsonDecoder::<Token>::new();
+        let mut tokens = Vec::new();
+        for chunk in chunks {
+            decoder.feed(chunk).unwrap();
+            tokens.extend((&mut decoder).map(Result::unwrap));
+        }
+        decoder.finish().unwrap();
+        tokens.extend((&mut decoder).map(Result::unwrap));
+        tokens
+    }
+
+    #[test]
+    fn decodes_multiple_objects_in_one_chunk() {
+        let mut decoder = NdJsonDecoder::<Token>::new();
+        decoder.feed(STREAM.as_bytes()).unwrap();
+        let tokens: Vec<Token> = (&mut decoder).map(Result::unwrap).collect();
+        assert_eq!(tokens, expected());
+    }
+
+    #[test]
+    fn decodes_at_every_chunk_boundary() {
+        let bytes = STREAM.as_bytes();
+        for i in 0..=bytes.len() {
+            for j in i..=bytes.len() {
+                let tokens = decode_chunks([&bytes[..i], &bytes[i..j], &bytes[j..]]);
+                assert_eq!(tokens, expected(), "split at {i} and {j}");
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_byte_by_byte() {
+        let tokens = decode_chunks(STREAM.as_bytes().chunks(1));
+        assert_eq!(tokens, expected());
+    }
+
+    #[test]
+    fn emits_objects_as_soon_as_complete() {
+        let mut decoder = NdJsonDecoder::<Token>::new();
+        decoder.feed(b"{\"content\":\"a\",\"done\":false}\n{\"content\":").unwrap();
+        assert_eq!(decoder.next().unwrap().unwrap().content, "a");
+        assert!(decoder.next().is_none());
+
+        decoder.feed(b"\"b\",\"done\":true}\n").unwrap();
+        assert_eq!(decoder.next().unwrap().unwrap().content, "b");
+    }
+
+    #[test]
+    fn decodes_unterminated_last_object() {
+        let tokens = decode_chunks([STREAM.trim_end().as_bytes()]);
+        assert_eq!(tokens, expected());
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        let mut decoder = NdJsonDecoder::<Token>::new();
+        assert!(decoder.feed(b"{\"content\":\"\xFF\",\"done\":true}\n").is_err());
     }
 
-    async fn abort(&self) {
-        let _ = self.tx_data.send(NdJsonData::End).await;
+    #[test]
+    fn reports_invalid_json() {
+        let mut decoder = NdJsonDecoder::<Token>::new();
+        decoder.feed(b"{\"content\":\n").unwrap();
+        assert!(decoder.next().unwrap().is_err());
     }
 }
@@ -0,0 +1,321 @@
+use log::{error, warn};
+use serde::Deserialize;
+use std::{marker::PhantomData, string::FromUtf8Error};
+
+use crate::{
+    backend::reader::{line_framer::LineFramer, stream_reader::{StreamData, StreamDecoder, StreamReader}},
+    errors::{self, ErrorKind}
+};
+
+/// Reads raw responses as server-sent events and parses the data
+/// of each event as JSON into a token stream.
+/// The stream ends with the `[DONE]` sentinel (OpenAI) or when the
+/// connection is closed.
+pub(crate) type SseReader<T> = StreamReader<SseJsonDecoder<T>>;
+
+/// Sentinel send by OpenAI compatible servers
+/// after the last event of a stream.
+pub(crate) const DONE_SENTINEL: &str = "[DONE]";
+
+/// Event type used if an event doesn't specify one.
+pub(crate) const DEFAULT_EVENT_TYPE: &str = "message";
+
+/// A single dispatched server-sent event.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SseEvent {
+    /// Type of the event, "message" if none has been given.
+    pub event: String,
+    /// Data of all `data:` lines joined by newlines.
+    pub data: String,
+    /// Last event ID seen in the stream.
+    pub id: Option<String>
+}
+
+/// Incremental decoder for the `text/event-stream` format.
+/// Bytes can be fed in arbitrary chunks and complete events are
+/// returned as soon as their terminating blank line has been received.
+/// See https://html.spec.whatwg.org/multipage/server-sent-events.html#parsing-an-event-stream
+#[derive(Default)]
+pub(crate) struct SseDecoder {
+    lines: LineFramer,
+    event_type: String,
+    data: String,
+    has_data: bool,
+    last_id: Option<String>
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds raw bytes into the decoder and returns all completed events.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<SseEvent>, FromUtf8Error> {
+        let events = self.lines
+            .feed(chunk)?
+            .iter()
+            .filter_map(|line| self.process_line(line))
+            .collect();
+        Ok(events)
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        // Blank line: Dispatch the event
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        // Comment
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, "")
+        };
+
+        match field {
+            "event" => self.event_type = value.to_owned(),
+            "data" => {
+                if self.has_data {
+                    self.data.push('\n');
+                }
+                self.data.push_str(value);
+                self.has_data = true;
+            },
+            "id" if !value.contains('\0') => self.last_id = Some(value.to_owned()),
+            // We don't reconnect, so "retry" is of no use for us.
+            // Unknown fields must be ignored.
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event_type = std::mem::take(&mut self.event_type);
+        if !self.has_data {
+            return None;
+        }
+        self.has_data = false;
+
+        Some(SseEvent {
+            event: if event_type.is_empty() { DEFAULT_EVENT_TYPE.to_owned() } else { event_type },
+            data: std::mem::take(&mut self.data),
+            id: self.last_id.clone()
+        })
+    }
+}
+
+/// Decodes the data of server-sent events as JSON.
+/// The data ends with the `[DONE]` sentinel (OpenAI) or an `error` event.
+pub(crate) struct SseJsonDecoder<T> {
+    events: SseDecoder,
+    _marker: PhantomData<T>
+}
+
+impl<T> Default for SseJsonDecoder<T> {
+    fn default() -> Self {
+        Self {
+            events: SseDecoder::new(),
+            _marker: PhantomData
+        }
+    }
+}
+
+impl<T> StreamDecoder for SseJsonDecoder<T>
+where
+    T: for<'de> Deserialize<'de> + Send + 'static
+{
+    type Item = T;
+
+    /// Events are only dispatched after a blank line,
+    /// so an unterminated event at the end is dropped.
+    fn decode(&mut self, chunk: Option<&[u8]>) -> Vec<StreamData<T>> {
+        let Some(chunk) = chunk else {
+            return Vec::new();
+        };
+        let events = match self.events.feed(chunk) {
+            Ok(events) => events,
+            Err(e) => {
+                error!("Unexpected event stream encoding: {:?}", e);
+                return vec![StreamData::Error(ErrorKind::InvalidResponse(e.to_string()))];
+            }
+        };
+
+        let mut decoded = Vec::new();
+        for event in events {
+            if event.data == DONE_SENTINEL {
+                decoded.push(StreamData::End);
+                break;
+            }
+            if event.event == "error" {
+                error!("Error event received: {}", event.data);
+                let msg = serde_json::from_str(&event.data)
+                    .ok()
+                    .and_then(|value| errors::backend_message(&value))
+                    .unwrap_or(event.data);
+                decoded.push(StreamData::Error(ErrorKind::Generation(msg)));
+                break;
+            }
+
+            match serde_json::from_str(&event.data) {
+                Ok(value) => decoded.push(StreamData::Data(value)),
+                Err(e) => {
+                    warn!("Invalid JSON received in '{}' event: {:?}", event.event, e);
+                    warn!("Value: {}", event.data);
+                    decoded.push(StreamData::Error(ErrorKind::InvalidResponse(e.to_string())));
+                    break;
+                }
+            }
+        }
+        decoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::{backend::reader::stream_reader::{StreamData, StreamDecoder}, errors::ErrorKind};
+
+    use super::{SseDecoder, SseEvent, SseJsonDecoder, DEFAULT_EVENT_TYPE, DONE_SENTINEL};
+
+    fn event(event: &str, data: &str, id: Option<&str>) -> SseEvent {
+        SseEvent { event: event.into(), data: data.into(), id: id.map(Into::into) }
+    }
+
+    fn decode_chunks<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        chunks
+            .into_iter()
+            .flat_map(|chunk| decoder.feed(chunk).unwrap())
+            .collect()
+    }
+
+    // Stream like sent by OpenAI compatible servers
+    const STREAM: &str = concat!(
+        ": keep-alive\n",
+        "\n",
+        "data: {\"content\":\"Hällo\"}\n",
+        "\n",
+        "event: delta\r\n",
+        "id: 7\r\n",
+        "data: {\"content\":\"🦀\"}\r\n",
+        "\r\n",
+        "data: [DONE]\n",
+        "\n"
+    );
+
+    fn expected() -> Vec<SseEvent> {
+        vec![
+            event(DEFAULT_EVENT_TYPE, "{\"content\":\"Hällo\"}", None),
+            event("delta", "{\"content\":\"🦀\"}", Some("7")),
+            event(DEFAULT_EVENT_TYPE, DONE_SENTINEL, Some("7")),
+        ]
+    }
+
+    #[test]
+    fn decodes_data_lines() {
+        assert_eq!(decode_chunks([STREAM.as_bytes()]), expected());
+    }
+
+    #[test]
+    fn decodes_at_every_chunk_boundary() {
+        let bytes = STREAM.as_bytes();
+        for i in 0..=bytes.len() {
+            let events = decode_chunks([&bytes[..i], &bytes[i..]]);
+            assert_eq!(events, expected(), "split at {i}");
+        }
+    }
+
+    #[test]
+    fn decodes_byte_by_byte() {
+        assert_eq!(decode_chunks(STREAM.as_bytes().chunks(1)), expected());
+    }
+
+    #[test]
+    fn joins_multi_line_data() {
+        let events = decode_chunks([b"data: first\ndata:second\ndata\n\n".as_slice()]);
+        assert_eq!(events, vec![event(DEFAULT_EVENT_TYPE, "first\nsecond\n", None)]);
+    }
+
+    #[test]
+    fn applies_event_type_to_next_event_only() {
+        let events = decode_chunks([b"event: error\ndata: {}\n\ndata: {}\n\n".as_slice()]);
+        assert_eq!(events, vec![
+            event("error", "{}", None),
+            event(DEFAULT_EVENT_TYPE, "{}", None),
+        ]);
+    }
+
+    #[test]
+    fn ignores_comments_and_unknown_fields() {
+        let events = decode_chunks([b": comment\nretry: 100\nfoo: bar\ndata: x\n: another\n\n".as_slice()]);
+        assert_eq!(events, vec![event(DEFAULT_EVENT_TYPE, "x", None)]);
+    }
+
+    #[test]
+    fn skips_events_without_data() {
+        let events = decode_chunks([b"event: ping\n\n\ndata: x\n\n".as_slice()]);
+        assert_eq!(events, vec![event(DEFAULT_EVENT_TYPE, "x", None)]);
+    }
+
+    #[test]
+    fn waits_for_blank_line() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.feed(b"data: x\n").unwrap().is_empty());
+        assert_eq!(decoder.feed(b"\n").unwrap(), vec![event(DEFAULT_EVENT_TYPE, "x", None)]);
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.feed(b"data: \xFF\n\n").is_err());
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Token {
+        content: String
+    }
+
+    fn decode_json(stream: &str) -> Vec<StreamData<Token>> {
+        let mut decoder = SseJsonDecoder::<Token>::default();
+        let mut decoded = decoder.decode(Some(stream.as_bytes()));
+        decoded.extend(decoder.decode(None));
+        decoded
+    }
+
+    #[test]
+    fn json_data_ends_with_done_sentinel() {
+        let decoded = decode_json(&format!("{STREAM}data: {{\"content\":\"late\"}}\n\n"));
+        let contents: Vec<_> = decoded
+            .iter()
+            .map(|data| match data {
+                StreamData::Data(token) => token.content.as_str(),
+                StreamData::End => "end",
+                StreamData::Error(_) => "error"
+            })
+            .collect();
+        assert_eq!(contents, ["Hällo", "🦀", "end"]);
+    }
+
+    #[test]
+    fn json_data_ends_with_error_event() {
+        let decoded = decode_json(concat!(
+            "data: {\"content\":\"a\"}\n\n",
+            "event: error\ndata: {\"error\":{\"message\":\"Out of memory\"}}\n\n",
+            "data: {\"content\":\"b\"}\n\n"
+        ));
+        assert_eq!(decoded.len(), 2);
+        assert!(matches!(&decoded[0], StreamData::Data(token) if token.content == "a"));
+        assert!(matches!(&decoded[1], StreamData::Error(ErrorKind::Generation(msg)) if msg == "Out of memory"));
+    }
+
+    #[test]
+    fn invalid_json_data_is_an_error() {
+        let decoded = decode_json("data: {\"content\":\n\ndata: {\"content\":\"b\"}\n\n");
+        assert_eq!(decoded.len(), 1);
+        assert!(matches!(&decoded[0], StreamData::Error(ErrorKind::InvalidResponse(_))));
+    }
+}
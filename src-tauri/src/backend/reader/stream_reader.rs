@@ -0,0 +1,270 @@
+use async_trait::async_trait;
+use log::error;
+
+use bytes::Bytes;
+use tokio::sync::mpsc::{Sender, Receiver, channel};
+use tokio_util::sync::CancellationToken;
+
+use crate::{backend::{chat::ChatResponse, llm::PromptResponse}, errors::{self, ErrorKind}};
+
+pub(crate) enum StreamData<T> {
+    Data(T),
+    /// The stream could not be read.
+    /// Always followed by [StreamData::End].
+    Error(ErrorKind),
+    End
+}
+
+/// Decodes the raw bytes of a streamed response into values,
+/// e.g. [NdJsonDecoder](super::ndjson_reader::NdJsonDecoder) for ND-JSON.
+pub(crate) trait StreamDecoder: Send + 'static {
+    type Item: Send + 'static;
+
+    /// Decodes the next chunk or, if `chunk` is [None], the rest at the end of the stream.
+    /// Returns every completed value. An error or the end of the data is returned last,
+    /// since the stream ends with it.
+    fn decode(&mut self, chunk: Option<&[u8]>) -> Vec<StreamData<Self::Item>>;
+}
+
+/// Reads raw responses and decodes them into a token stream using `D`.
+pub(crate) struct StreamReader<D: StreamDecoder> {
+    tx_data: Sender<StreamData<Bytes>>,
+    rx_events: Option<Receiver<StreamData<D::Item>>>,
+    // Shared between the reading and parsing tasks.
+    // Cancelling stops both and drops the HTTP connection.
+    cancel: CancellationToken
+}
+
+impl<D> StreamReader<D>
+where
+    D: StreamDecoder + Default
+{
+    pub fn new() -> Self {
+        Self::with_decoder(D::default())
+    }
+}
+
+impl<D> StreamReader<D>
+where
+    D: StreamDecoder
+{
+    pub fn with_decoder(mut decoder: D) -> Self {
+        const BUFFER_SIZE: usize = 1024;
+        let (tx, mut rx) = channel(BUFFER_SIZE);
+        let (tx_ev, rx_ev) = channel(256);
+        let cancel = CancellationToken::new();
+        let obj = StreamReader {
+            tx_data: tx,
+            rx_events: Some(rx_ev),
+            cancel: cancel.clone()
+        };
+
+        tokio::spawn(async move {
+            let mut buffer: Vec<StreamData<Bytes>> = Vec::with_capacity(BUFFER_SIZE);
+
+            'outer: loop {
+                if rx.is_closed() {
+                    break;
+                }
+
+                // Every value is send right after decoding it.
+                // Incomplete data is kept in the decoder until the next chunk.
+                let received = tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    received = rx.recv_many(&mut buffer, BUFFER_SIZE) => received
+                };
+                if received == 0 {
+                    break;
+                }
+                for data in &buffer {
+                    let decoded = match data {
+                        StreamData::Data(data) => decoder.decode(Some(data)),
+                        StreamData::End => decoder.decode(None),
+                        StreamData::Error(e) => {
+                            let _ = tx_ev.send(StreamData::Error(e.clone())).await;
+                            break 'outer;
+                        }
+                    };
+
+                    for value in decoded {
+                        match value {
+                            StreamData::Data(value) => {
+                                // Nobody is interested in the rest anymore
+                                if tx_ev.send(StreamData::Data(value)).await.is_err() {
+                                    break 'outer;
+                                }
+                            },
+                            StreamData::Error(e) => {
+                                let _ = tx_ev.send(StreamData::Error(e)).await;
+                                break 'outer;
+                            },
+                            StreamData::End => break 'outer
+                        }
+                    }
+
+                    if let StreamData::End = data {
+                        break 'outer;
+                    }
+                }
+                buffer.clear(); // Clear for the next chunk of data
+            }
+            // Stop reading the response if parsing ended first
+            cancel.cancel();
+            let _ = tx_ev.send(StreamData::End).await;
+        });
+
+        obj
+    }
+
+    /// Sender for queueing data received from the backend
+    pub fn sender(&self) -> Sender<StreamData<Bytes>> {
+        self.tx_data.clone()
+    }
+
+    pub fn receiver(&mut self) -> Option<Receiver<StreamData<D::Item>>> {
+        self.rx_events.take()
+    }
+
+    pub fn start_reading_response(&self, mut res: reqwest::Response) {
+        let sink = self.sender();
+        let cancel = self.cancel.clone();
+        tokio::spawn(async move {
+            loop {
+                let chunk = tokio::select! {
+                    // Dropping the response closes the connection,
+                    // which stops the generation in the backend right away.
+                    _ = cancel.cancelled() => return,
+                    chunk = res.chunk() => chunk
+                };
+                let chunk = match chunk {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => break,
+                    // E.g. the backend crashed or the connection was reset
+                    Err(e) => {
+                        error!("Reading the response failed: {:?}", e);
+                        let _ = sink.send(StreamData::Error(ErrorKind::Generation(format!("Connection lost: {e}")))).await;
+                        break;
+                    }
+                };
+                if sink.send(StreamData::Data(chunk)).await.is_err() {
+                    break;
+                }
+            }
+            let _ = sink.send(StreamData::End).await;
+        });
+    }
+
+    /// Cancels reading and parsing the response.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Forwards the parsed data into the returned receiver until the stream ends.
+    /// Read errors end the stream without being forwarded.
+    pub fn start_unwrapping_data(&mut self, buffer_size: usize) -> Result<Receiver<D::Item>, errors::Error> {
+        self.start_mapping_data(buffer_size, |data| data, |_| None)
+    }
+
+    /// Maps the parsed data using `map` and forwards it into the returned
+    /// receiver until the stream ends. A read error ends the stream and
+    /// is forwarded if `map_error` returns a value for it.
+    pub fn start_mapping_data<U>(
+        &mut self,
+        buffer_size: usize,
+        mut map: impl FnMut(D::Item) -> U + Send + 'static,
+        map_error: impl Fn(ErrorKind) -> Option<U> + Send + 'static
+    ) -> Result<Receiver<U>, errors::Error>
+    where
+        U: Send + 'static
+    {
+        let (sender, receiver) = channel::<U>(buffer_size);
+        let mut data_receiver = self.receiver().ok_or(errors::internal("Data is already being unwrapped"))?;
+        tokio::spawn(async move {
+            while let Some(data) = data_receiver.recv().await {
+                let data = match data {
+                    StreamData::Data(data) => map(data),
+                    StreamData::Error(e) => {
+                        if let Some(data) = map_error(e) {
+                            let _ = sender.send(data).await;
+                        }
+                        break;
+                    },
+                    StreamData::End => break
+                };
+                if sender.send(data).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(receiver)
+    }
+}
+
+#[async_trait]
+impl<D> PromptResponse for StreamReader<D>
+where
+    D: StreamDecoder,
+    D::Item: Into<ChatResponse>
+{
+    fn get_prompts(&mut self) -> Result<Receiver<ChatResponse>, errors::Error> {
+        self.start_mapping_data(64, D::Item::into, |e| Some(ChatResponse::error(e)))
+    }
+
+    async fn abort(&self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use serde::Deserialize;
+
+    use crate::{backend::reader::ndjson_reader::NdJsonReader, errors::ErrorKind};
+
+    use super::StreamData;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Token {
+        content: String
+    }
+
+    #[tokio::test]
+    async fn forwards_values_until_the_end() {
+        let mut reader = NdJsonReader::<Token>::new();
+        let sink = reader.sender();
+        for chunk in ["{\"content\":\"a\"}\n{\"cont", "ent\":\"b\"}\n{\"content\":\"c\"}"] {
+            sink.send(StreamData::Data(Bytes::from(chunk))).await.unwrap();
+        }
+        sink.send(StreamData::End).await.unwrap();
+
+        let mut tokens = reader.start_unwrapping_data(8).unwrap();
+        let mut contents = Vec::new();
+        while let Some(token) = tokens.recv().await {
+            contents.push(token.content);
+        }
+        assert_eq!(contents, ["a", "b", "c"]);
+        assert!(reader.start_unwrapping_data(8).is_err());
+    }
+
+    #[tokio::test]
+    async fn forwards_read_errors_if_mapped() {
+        let mut reader = NdJsonReader::<Token>::new();
+        let sink = reader.sender();
+        sink.send(StreamData::Data(Bytes::from("{\"content\":\"a\"}\n"))).await.unwrap();
+        sink.send(StreamData::Error(ErrorKind::Generation("Connection lost".into()))).await.unwrap();
+
+        let mut tokens = reader.start_mapping_data(8, |token| Ok(token.content), |e| Some(Err(e))).unwrap();
+        assert!(matches!(tokens.recv().await, Some(Ok(content)) if content == "a"));
+        assert!(matches!(tokens.recv().await, Some(Err(ErrorKind::Generation(_)))));
+        assert!(tokens.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn cancelling_ends_the_stream() {
+        let mut reader = NdJsonReader::<Token>::new();
+        let mut tokens = reader.start_unwrapping_data(8).unwrap();
+        reader.cancel();
+        assert!(tokens.recv().await.is_none());
+    }
+}
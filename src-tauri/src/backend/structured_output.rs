@@ -0,0 +1,114 @@
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+
+use async_trait::async_trait;
+use log::warn;
+use tokio::sync::mpsc::{channel, Receiver};
+
+use crate::{
+    backend::{
+        chat::{ChatResponse, Role},
+        llm::{PromptResponse, ResponseFormat},
+    },
+    errors::{self, ErrorKind},
+};
+
+/// Validates a complete response against the requested format.
+/// Returns every violation found.
+pub fn validate_response(format: &ResponseFormat, content: &str) -> Result<(), Vec<String>> {
+    let value: serde_json::Value = serde_json::from_str(content)
+        .map_err(|e| vec![format!("Response is not valid JSON: {e}")])?;
+
+    let ResponseFormat::JsonSchema(schema) = format else {
+        return Ok(());
+    };
+    let validator = jsonschema::validator_for(schema)
+        .map_err(|e| vec![format!("Invalid JSON schema: {e}")])?;
+
+    let violations: Vec<String> = validator
+        .iter_errors(&value)
+        .map(|e| match e.instance_path.to_string() {
+            path if path.is_empty() => e.to_string(),
+            path => format!("{path}: {e}")
+        })
+        .collect();
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+/// Wraps a [PromptResponse] and validates the assembled
+/// response once the generation has finished.
+/// Violations are reported as the last response.
+/// Incomplete responses, e.g. after aborting, are not validated.
+pub struct ValidatingPromptResponse {
+    inner: Box<dyn PromptResponse>,
+    format: ResponseFormat,
+    aborted: Arc<AtomicBool>,
+}
+
+impl ValidatingPromptResponse {
+    pub fn new(inner: Box<dyn PromptResponse>, format: ResponseFormat) -> Self {
+        Self { inner, format, aborted: Arc::new(AtomicBool::new(false)) }
+    }
+}
+
+#[async_trait]
+impl PromptResponse for ValidatingPromptResponse {
+    fn get_prompts(&mut self) -> Result<Receiver<ChatResponse>, errors::Error> {
+        let mut prompts = self.inner.get_prompts()?;
+        let format = self.format.clone();
+        let aborted = self.aborted.clone();
+        let (sender, receiver) = channel(64);
+
+        tokio::spawn(async move {
+            let mut content = String::new();
+            let mut last_prompt: Option<ChatResponse> = None;
+            while let Some(mut prompt) = prompts.recv().await {
+                if prompt.error.is_some() {
+                    let _ = sender.send(prompt).await;
+                    return;
+                }
+
+                // Only the answer following the last tool call must match
+                if *prompt.message.role() == Role::Tool {
+                    content.clear();
+                } else {
+                    content.push_str(prompt.message.content());
+                }
+                if prompt.done {
+                    // Hold back the last response until we have validated
+                    prompt.done = false;
+                    last_prompt = Some(prompt);
+                    break;
+                }
+                if sender.send(prompt).await.is_err() {
+                    return;
+                }
+            }
+
+            // The stream ended without a final response, e.g. after aborting
+            // or losing the connection, so the content may just be incomplete
+            let Some(mut prompt) = last_prompt else {
+                return;
+            };
+            if aborted.load(Ordering::Relaxed) {
+                return;
+            }
+            let result = validate_response(&format, &content);
+            prompt.done = result.is_ok();
+            let _ = sender.send(prompt).await;
+            if let Err(violations) = result {
+                warn!("Response violates the requested format: {:?}", violations);
+                let _ = sender.send(ChatResponse::error(ErrorKind::SchemaViolation(violations))).await;
+            }
+        });
+        Ok(receiver)
+    }
+
+    async fn abort(&self) {
+        self.aborted.store(true, Ordering::Relaxed);
+        self.inner.abort().await;
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{
+    backend::{chat::{ChatMessage, Role}, llm::{complete, GenerationOptions, Model}},
+    chat_repository::MAX_TITLE_LEN,
+    errors::Error,
+};
+
+/// Title of chats without any text to derive one from.
+pub const DEFAULT_TITLE: &str = "New Chat";
+
+const INSTRUCTION: &str = "You write a brief title for the conversation given by the user.
+The title names the topic in at most five words and uses the language of the conversation.
+Only output the title as plain text without quotes, Markdown or a trailing period.";
+
+/// Titles are short, so a few tokens are enough.
+/// Also stops models which ignore the instruction.
+const MAX_TITLE_TOKENS: u32 = 48;
+/// Only the topic of a message matters for the title,
+/// so long messages are shortened to keep the prompt small.
+const MAX_MESSAGE_CHARS: usize = 2000;
+/// Number of words of a title derived from the first prompt.
+const FALLBACK_WORDS: usize = 6;
+
+/// Asks `model` for a title of the conversation in `history`.
+/// Only the first exchange is considered.
+/// Returns [None] if there is nothing to name or the answer is no usable title.
+pub async fn generate_title(model: &dyn Model, history: &[ChatMessage]) -> Result<Option<String>, Error> {
+    let transcript = first_exchange(history)
+        .map(|message| {
+            let speaker = if *message.role() == Role::User { "User" } else { "Assistant" };
+            let content: String = message.content().trim().chars().take(MAX_MESSAGE_CHARS).collect();
+            format!("{speaker}: {content}")
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if transcript.is_empty() {
+        return Ok(None);
+    }
+
+    let instruction = ChatMessage::new(Role::System, INSTRUCTION.to_owned());
+    let content = ChatMessage::new(Role::User, format!("Conversation:\n\n{transcript}"));
+    let options = GenerationOptions {
+        temperature: Some(0.2),
+        max_tokens: Some(MAX_TITLE_TOKENS),
+        ..Default::default()
+    };
+    let answer = complete(model, content, &[instruction], &options).await?;
+    Ok(clean_title(&answer))
+}
+
+/// Title derived from the first words of the first prompt,
+/// used if no model is available.
+pub fn fallback_title(history: &[ChatMessage]) -> String {
+    let Some(line) = first_exchange(history)
+        .find(|message| *message.role() == Role::User)
+        .and_then(|message| message.content().lines().map(strip_markdown).find(|line| !line.is_empty()))
+    else {
+        return DEFAULT_TITLE.to_owned();
+    };
+
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let mut title = words[..words.len().min(FALLBACK_WORDS)].join(" ");
+    if words.len() > FALLBACK_WORDS {
+        title.push('…');
+    }
+    truncate(&title)
+}
+
+/// The first user message with text and the answer to it.
+fn first_exchange(history: &[ChatMessage]) -> impl Iterator<Item = &ChatMessage> {
+    history
+        .iter()
+        .filter(|message| matches!(message.role(), Role::User | Role::Assistant))
+        .filter(|message| !message.content().trim().is_empty())
+        .skip_while(|message| *message.role() != Role::User)
+        .take(2)
+}
+
+/// Extracts the title from the answer of a model, which
+/// may still contain thoughts, a label, quotes or Markdown.
+fn clean_title(answer: &str) -> Option<String> {
+    let answer = answer.rsplit_once("</think>").map_or(answer, |(_, answer)| answer);
+    let line = answer.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = ["Title:", "title:", "TITLE:"]
+        .iter()
+        .find_map(|label| line.strip_prefix(label))
+        .unwrap_or(line);
+    let quote = |c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '“' | '”' | '„' | '«' | '»');
+    // The period may be inside or outside of the quotes
+    let title = strip_markdown(line)
+        .trim_matches(quote)
+        .trim_end_matches('.')
+        .trim_end_matches(quote)
+        .to_owned();
+    (!title.is_empty()).then(|| truncate(&title))
+}
+
+fn strip_markdown(line: &str) -> String {
+    line
+        .trim_start_matches(|c: char| c.is_whitespace() || matches!(c, '#' | '>' | '-'))
+        .replace(['*', '_', '`'], "")
+        .trim()
+        .to_owned()
+}
+
+fn truncate(title: &str) -> String {
+    title.chars().take(MAX_TITLE_LEN).collect::<String>().trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::chat::{ChatMessage, Role},
+        chat_repository::MAX_TITLE_LEN,
+    };
+
+    use super::{clean_title, fallback_title, DEFAULT_TITLE};
+
+    #[test]
+    fn cleans_answers_of_models() {
+        assert_eq!(clean_title("Rust lifetimes").as_deref(), Some("Rust lifetimes"));
+        assert_eq!(clean_title("\"Rust lifetimes\"").as_deref(), Some("Rust lifetimes"));
+        assert_eq!(clean_title("„Rust-Lebenszeiten“").as_deref(), Some("Rust-Lebenszeiten"));
+        assert_eq!(clean_title("Title: 'Rust lifetimes'.").as_deref(), Some("Rust lifetimes"));
+        assert_eq!(clean_title("«Rust lifetimes.»").as_deref(), Some("Rust lifetimes"));
+        assert_eq!(clean_title("## **Rust** `lifetimes`").as_deref(), Some("Rust lifetimes"));
+        assert_eq!(clean_title("<think>A title about Rust</think>\n\nRust lifetimes").as_deref(), Some("Rust lifetimes"));
+    }
+
+    #[test]
+    fn takes_the_first_line_of_answers() {
+        let answer = "\n\nTitle: Rust lifetimes\nThis title sums up the conversation.";
+        assert_eq!(clean_title(answer).as_deref(), Some("Rust lifetimes"));
+    }
+
+    #[test]
+    fn rejects_empty_answers() {
+        assert_eq!(clean_title(""), None);
+        assert_eq!(clean_title("  \n\"\"\n"), None);
+        assert_eq!(clean_title("<think>Hmm</think>"), None);
+    }
+
+    #[test]
+    fn caps_the_length() {
+        let title = clean_title(&"Rust ".repeat(100)).unwrap();
+        assert_eq!(title.chars().count(), MAX_TITLE_LEN);
+
+        let history = [ChatMessage::new(Role::User, "ä".repeat(300))];
+        assert_eq!(fallback_title(&history).chars().count(), MAX_TITLE_LEN);
+    }
+
+    #[test]
+    fn falls_back_to_the_first_prompt() {
+        let history = [
+            ChatMessage::new(Role::System, "You are helpful".into()),
+            ChatMessage::new(Role::User, "  \n# How do *lifetimes* work in Rust\nAnd why?".into()),
+            ChatMessage::new(Role::Assistant, "They are...".into()),
+        ];
+        assert_eq!(fallback_title(&history), "How do lifetimes work in Rust");
+
+        let history = [ChatMessage::new(Role::User, "one two three four five six seven".into())];
+        assert_eq!(fallback_title(&history), "one two three four five six…");
+    }
+
+    #[test]
+    fn falls_back_to_the_default_title() {
+        assert_eq!(fallback_title(&[]), DEFAULT_TITLE);
+        let history = [ChatMessage::new(Role::User, " ".into()), ChatMessage::new(Role::Assistant, "Hi".into())];
+        assert_eq!(fallback_title(&history), DEFAULT_TITLE);
+    }
+}
@@ -0,0 +1,217 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use log::warn;
+use serde::Serialize;
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{errors::{self, Error}, knowledge::SharedKnowledgeStore};
+
+pub type SharedTool = Arc<dyn Tool>;
+pub type SharedToolRegistry = Arc<ToolRegistry>;
+
+/// Maximum number of characters of a file returned by [ReadTextFileTool].
+const MAX_FILE_CHARS: usize = 32 * 1024;
+
+/// Description of a tool as presented to a model.
+#[derive(Serialize, Clone, Debug)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    /// JSON Schema of the arguments object
+    pub parameters: Value
+}
+
+impl ToolDefinition {
+    /// The definition in the `{"type": "function", ...}` format
+    /// understood by both Ollama and the OpenAI API.
+    pub fn to_function(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": self
+        })
+    }
+}
+
+/// A function which may be called by a model.
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn definition(&self) -> &ToolDefinition;
+
+    /// Executes the tool with arguments already validated
+    /// against [ToolDefinition::parameters].
+    /// The result is passed back to the model as is.
+    async fn call(&self, arguments: Value) -> Result<String, Error>;
+}
+
+/// All tools a model may use, identified by their name.
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: HashMap<String, SharedTool>
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, tool: SharedTool) {
+        self.tools.insert(tool.definition().name.clone(), tool);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SharedTool> {
+        self.tools.get(name)
+    }
+
+    /// Definitions of all registered tools sorted by name.
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        let mut definitions: Vec<ToolDefinition> = self.tools
+            .values()
+            .map(|tool| tool.definition().clone())
+            .collect();
+        definitions.sort_by(|a, b| a.name.cmp(&b.name));
+        definitions
+    }
+
+    /// Calls the tool `name` with the given arguments.
+    /// Failures are returned as text, so the model can react to them.
+    pub async fn call(&self, name: &str, arguments: Value) -> String {
+        let Some(tool) = self.get(name) else {
+            return format!("Error: Unknown tool '{name}'");
+        };
+
+        // Some models send the arguments as encoded JSON
+        let arguments = match arguments {
+            Value::String(encoded) => serde_json::from_str(&encoded).unwrap_or(Value::String(encoded)),
+            Value::Null => json!({}),
+            arguments => arguments
+        };
+        if let Err(violations) = validate_arguments(&tool.definition().parameters, &arguments) {
+            return format!("Error: Invalid arguments for '{name}': {}", violations.join("; "));
+        }
+
+        match tool.call(arguments).await {
+            Ok(result) => result,
+            Err(e) => {
+                warn!("Tool '{}' failed: {:?}", name, e);
+                format!("Error: {e}")
+            }
+        }
+    }
+}
+
+fn validate_arguments(schema: &Value, arguments: &Value) -> Result<(), Vec<String>> {
+    let validator = jsonschema::validator_for(schema)
+        .map_err(|e| vec![format!("Invalid JSON schema: {e}")])?;
+    let violations: Vec<String> = validator
+        .iter_errors(arguments)
+        .map(|e| e.to_string())
+        .collect();
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+/// Returns the current date and time.
+struct CurrentTimeTool {
+    definition: ToolDefinition
+}
+
+impl CurrentTimeTool {
+    fn new() -> Self {
+        Self {
+            definition: ToolDefinition {
+                name: "get_current_time".to_owned(),
+                description: "Returns the current UTC date and time in RFC 3339 format".to_owned(),
+                parameters: json!({"type": "object", "properties": {}})
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for CurrentTimeTool {
+    fn definition(&self) -> &ToolDefinition {
+        &self.definition
+    }
+
+    async fn call(&self, _arguments: Value) -> Result<String, Error> {
+        OffsetDateTime::now_utc().format(&Rfc3339).map_err(|e| errors::internal(e.to_string()))
+    }
+}
+
+/// Reads a local text file inside the folder of a knowledge base.
+/// Any other file is rejected since the path is chosen by the model.
+/// Long files are truncated to [MAX_FILE_CHARS].
+struct ReadTextFileTool {
+    definition: ToolDefinition,
+    knowledge: SharedKnowledgeStore
+}
+
+impl ReadTextFileTool {
+    fn new(knowledge: SharedKnowledgeStore) -> Self {
+        Self {
+            knowledge,
+            definition: ToolDefinition {
+                name: "read_text_file".to_owned(),
+                description: "Reads the content of a local text file in the folder of a knowledge base".to_owned(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {"type": "string", "description": "Absolute path of the file"}
+                    },
+                    "required": ["path"]
+                })
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for ReadTextFileTool {
+    fn definition(&self) -> &ToolDefinition {
+        &self.definition
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String, Error> {
+        let path = arguments["path"].as_str().unwrap_or_default();
+        // Resolves ".." and symbolic links pointing out of the folders
+        let path = match tokio::fs::canonicalize(path).await {
+            Ok(path) if self.is_allowed(&path).await => path,
+            // Don't tell the model whether other files exist
+            _ => return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "Only files in the folders of knowledge bases can be read"
+            )))
+        };
+        let content = tokio::fs::read_to_string(&path).await?;
+        match content.char_indices().nth(MAX_FILE_CHARS) {
+            Some((end, _)) => Ok(format!("{}\n[truncated]", &content[..end])),
+            None => Ok(content)
+        }
+    }
+}
+
+impl ReadTextFileTool {
+    /// Whether the canonical `path` is inside the folder of a knowledge base.
+    async fn is_allowed(&self, path: &Path) -> bool {
+        for info in self.knowledge.list().await {
+            if let Ok(folder) = tokio::fs::canonicalize(&info.folder).await {
+                if path.starts_with(&folder) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+pub fn build_tool_registry(knowledge: SharedKnowledgeStore) -> SharedToolRegistry {
+    let mut registry = ToolRegistry::new();
+    registry.register(Arc::new(CurrentTimeTool::new()));
+    registry.register(Arc::new(ReadTextFileTool::new(knowledge)));
+    Arc::new(registry)
+}
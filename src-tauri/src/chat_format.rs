@@ -0,0 +1,198 @@
+use serde::Serialize;
+use serde_json::Value;
+use time::UtcDateTime;
+
+use crate::{backend::chat::{serialize_utc_datetime, Chat}, errors::Error};
+
+/// Identifies files of exported chats.
+pub const FORMAT_NAME: &str = "whisper2-chats";
+
+/// Version of the file format written by exports.
+/// Increase it together with a new migration if the format changes.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// JSON Schema of the current format, so other tools can produce compatible files.
+pub const SCHEMA: &str = include_str!("../schemas/chat-export.schema.json");
+
+/// Migrations of the file format. The first one migrates
+/// version 1 to 2, the second one 2 to 3 and so on.
+const MIGRATIONS: [fn(Value) -> Value; 2] = [
+    v1_to_v2,
+    v2_to_v3
+];
+
+/// Content of an exported JSON file.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatFile<'a> {
+    format: &'static str,
+    version: u32,
+    #[serde(serialize_with = "serialize_utc_datetime")]
+    exported_at: UtcDateTime,
+    chats: &'a [Chat]
+}
+
+impl<'a> ChatFile<'a> {
+    pub fn new(chats: &'a [Chat]) -> Self {
+        Self {
+            format: FORMAT_NAME,
+            version: FORMAT_VERSION,
+            exported_at: UtcDateTime::now(),
+            chats
+        }
+    }
+}
+
+/// Whether `value` is an exported file of any version.
+pub fn is_chat_file(value: &Value) -> bool {
+    value.get("format").and_then(Value::as_str) == Some(FORMAT_NAME)
+}
+
+/// Migrates an exported file to the current version and returns its chats.
+/// Single chats are not validated, so broken ones can be reported on their own.
+pub fn read_chat_file(mut value: Value) -> Result<Vec<Value>, Error> {
+    let mut version = file_version(&value)?;
+    if version > FORMAT_VERSION {
+        return Err(Error::UnsupportedFileVersion(version));
+    }
+    while version < FORMAT_VERSION {
+        value = MIGRATIONS[version as usize - 1](value);
+        version += 1;
+    }
+
+    match value.get_mut("chats").map(Value::take) {
+        Some(Value::Array(chats)) => Ok(chats),
+        _ => Err(Error::InvalidChat("The file contains no list of chats".into()))
+    }
+}
+
+fn file_version(value: &Value) -> Result<u32, Error> {
+    match value {
+        // Exports before the format had a version
+        Value::Array(_) => Ok(1),
+        value if is_chat_file(value) => value
+            .get("version")
+            .and_then(Value::as_u64)
+            .filter(|version| *version >= 1)
+            .map(|version| version.min(u32::MAX as u64) as u32)
+            .ok_or(Error::InvalidChat("The file has no valid format version".into())),
+        _ => Err(Error::InvalidChat("The file contains no exported chats".into()))
+    }
+}
+
+/// Version 1 files are a bare array of chats.
+fn v1_to_v2(chats: Value) -> Value {
+    serde_json::json!({
+        "format": FORMAT_NAME,
+        "version": 2,
+        "chats": chats
+    })
+}
+
+/// Version 3 stores the history as a tree. Messages of version 2 have no IDs,
+/// which are assigned when the chats are read, so only the version changes.
+fn v2_to_v3(mut file: Value) -> Value {
+    file["version"] = 3.into();
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    use crate::{
+        backend::chat::{Chat, ChatMessage, Role, ToolCall, ToolCallFunction},
+        errors::Error
+    };
+
+    use super::{read_chat_file, ChatFile, FORMAT_NAME, FORMAT_VERSION, SCHEMA};
+
+    fn legacy_chat() -> Value {
+        json!({
+            "uuid": "8c0b5a0e-2f6f-4a8e-9c53-0d6e1e2b3f4a",
+            "title": "Legacy",
+            "created_at": "2024-05-01 12:30:00",
+            "history": [
+                { "role": "user", "content": "Hello" },
+                { "role": "assistant", "content": "Hi" }
+            ]
+        })
+    }
+
+    fn read_chats(file: Value) -> Vec<Chat> {
+        read_chat_file(file)
+            .unwrap()
+            .into_iter()
+            .map(|chat| serde_json::from_value(chat).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn migrates_bare_arrays_of_version_1() {
+        let chats = read_chats(json!([legacy_chat()]));
+        assert_eq!(chats.len(), 1);
+        assert_eq!(chats[0].title, "Legacy");
+        assert_eq!(chats[0].history.len(), 2);
+    }
+
+    #[test]
+    fn migrates_flat_histories_of_version_2() {
+        let file = json!({ "format": FORMAT_NAME, "version": 2, "chats": [legacy_chat()] });
+        let mut chats = read_chats(file);
+        let chat = &mut chats[0];
+        assert!(chat.history.iter().all(|message| message.id().is_none()));
+
+        chat.normalize();
+        let path: Vec<&str> = chat.active_path().iter().map(|message| message.content()).collect();
+        assert_eq!(path, ["Hello", "Hi"]);
+        assert_eq!(chat.history[1].parent(), chat.history[0].id());
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let file = json!({ "format": FORMAT_NAME, "version": FORMAT_VERSION + 1, "chats": [] });
+        assert!(matches!(read_chat_file(file), Err(Error::UnsupportedFileVersion(v)) if v == FORMAT_VERSION + 1));
+    }
+
+    #[test]
+    fn rejects_missing_versions() {
+        for file in [
+            json!({ "format": FORMAT_NAME, "chats": [] }),
+            json!({ "format": FORMAT_NAME, "version": 0, "chats": [] }),
+            json!({ "format": FORMAT_NAME, "version": "3", "chats": [] })
+        ] {
+            assert!(matches!(read_chat_file(file), Err(Error::InvalidChat(_))));
+        }
+        assert!(matches!(read_chat_file(json!({ "chats": [] })), Err(Error::InvalidChat(_))));
+    }
+
+    #[test]
+    fn exports_match_the_schema() {
+        let mut chat = Chat {
+            uuid: Uuid::new_v4(),
+            title: "Export".into(),
+            history: Vec::new(),
+            current: None,
+            created_at: time::UtcDateTime::now(),
+            knowledge_bases: vec![Uuid::new_v4()]
+        };
+        let question = chat.add_message(None, ChatMessage::new(Role::User, "Search".into()).with_images(vec!["iVBORw0KGgo=".into()]));
+        let call = ToolCall {
+            id: Some("call_1".into()),
+            function: ToolCallFunction { name: "search".into(), arguments: json!({ "query": "rust" }) }
+        };
+        let answer = chat.add_message(question.id(), ChatMessage::with_tool_calls(String::new(), Some("Hmm".into()), vec![call.clone()]));
+        let result = chat.add_message(answer.id(), ChatMessage::tool_result(&call, "Found".into()));
+        chat.add_message(result.id(), ChatMessage::new(Role::Assistant, "Done".into()));
+
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+        let validator = jsonschema::options().should_validate_formats(true).build(&schema).unwrap();
+        let mut file = serde_json::to_value(ChatFile::new(&[chat])).unwrap();
+        let errors: Vec<String> = validator.iter_errors(&file).map(|e| e.to_string()).collect();
+        assert!(errors.is_empty(), "{errors:?}");
+
+        file["chats"][0]["history"][0]["role"] = "bot".into();
+        assert!(!validator.is_valid(&file));
+    }
+}
@@ -0,0 +1,868 @@
+use std::{collections::{HashMap, HashSet}, path::{Path, PathBuf}, sync::{Arc, Mutex, MutexGuard}};
+
+use log::{info, warn};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Wry};
+use time::UtcDateTime;
+use uuid::Uuid;
+
+use crate::{
+    backend::{chat::{serialize_utc_datetime, timestamp_to_string, Chat, ChatMessage, Role}, context::RollingSummary},
+    errors::{self, Error},
+};
+
+pub type SharedChatRepository = Arc<ChatRepository>;
+
+const DATABASE_PATH: &str = "chats.sqlite3";
+/// Store file of the chats before they moved into the database
+const LEGACY_STORE_PATH: &str = "chats.json";
+const STORE_MIGRATED_KEY: &str = "store_migrated";
+pub const MAX_TITLE_LEN: usize = 256;
+
+/// Markers around matches in snippets of the full-text search.
+/// Control characters don't occur in chats, unlike HTML tags.
+const HIGHLIGHT_START: char = '\u{2}';
+const HIGHLIGHT_END: char = '\u{3}';
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS chats (
+        uuid TEXT PRIMARY KEY,
+        title TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        knowledge_bases TEXT NOT NULL DEFAULT '[]'
+    );
+    CREATE TABLE IF NOT EXISTS messages (
+        chat_uuid TEXT NOT NULL REFERENCES chats(uuid) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        role TEXT NOT NULL,
+        content TEXT NOT NULL,
+        thoughts TEXT,
+        images TEXT,
+        tool_calls TEXT,
+        tool_name TEXT,
+        tool_call_id TEXT,
+        PRIMARY KEY (chat_uuid, position)
+    );
+    CREATE INDEX IF NOT EXISTS chats_created_at ON chats(created_at);
+    -- Titles (position NULL) and messages of all chats
+    CREATE VIRTUAL TABLE IF NOT EXISTS chats_fts USING fts5(
+        chat_uuid UNINDEXED,
+        position UNINDEXED,
+        text,
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+";
+
+type Migration = fn(&Transaction) -> Result<(), Error>;
+
+/// Migrations of the schema, applied in order after [SCHEMA].
+/// The number of applied migrations is stored as `user_version`.
+const MIGRATIONS: [Migration; 2] = [
+    add_message_tree,
+    add_rolling_summaries
+];
+
+/// Overview of a chat without its history.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSummary {
+    pub uuid: Uuid,
+    pub title: String,
+    #[serde(serialize_with = "serialize_utc_datetime")]
+    pub created_at: UtcDateTime,
+    pub message_count: usize
+}
+
+impl From<&Chat> for ChatSummary {
+    fn from(chat: &Chat) -> Self {
+        Self {
+            uuid: chat.uuid,
+            title: chat.title.clone(),
+            created_at: chat.created_at,
+            message_count: chat.history.len()
+        }
+    }
+}
+
+/// Part of a search snippet.
+#[derive(Serialize, Clone, Debug)]
+pub struct SnippetPart {
+    pub text: String,
+    /// Whether the text matches the query
+    pub highlight: bool
+}
+
+/// A chat matching a search query.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSearchResult {
+    pub uuid: Uuid,
+    pub title: String,
+    #[serde(serialize_with = "serialize_utc_datetime")]
+    pub created_at: UtcDateTime,
+    /// Position of the best matching message in the history.
+    /// [None] if the title matched.
+    pub position: Option<usize>,
+    pub snippet: Vec<SnippetPart>
+}
+
+/// Persistence of all chats in an SQLite database.
+/// Chats are validated before they are written.
+pub struct ChatRepository {
+    conn: Mutex<Connection>
+}
+
+impl ChatRepository {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        Self::init(conn)
+    }
+
+    /// Repository which is not stored anywhere, for tests.
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, Error> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(SCHEMA)?;
+        let repo = Self {
+            conn: Mutex::new(conn)
+        };
+        repo.migrate()?;
+        Ok(repo)
+    }
+
+    fn migrate(&self) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            migration(&tx)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+            info!("Migrated the chat database to version {}", i + 1);
+        }
+        Ok(())
+    }
+
+    fn conn(&self) -> Result<MutexGuard<'_, Connection>, Error> {
+        self.conn.lock().map_err(errors::internal)
+    }
+
+    /// Summaries of all chats, the newest first.
+    pub fn list(&self) -> Result<Vec<ChatSummary>, Error> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT c.uuid, c.title, c.created_at, (SELECT COUNT(*) FROM messages m WHERE m.chat_uuid = c.uuid)
+            FROM chats c ORDER BY c.created_at DESC"
+        )?;
+        let rows = stmt.query_map([], |row| Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, usize>(3)?
+        )))?;
+
+        let mut summaries = Vec::new();
+        for row in rows {
+            let (uuid, title, created_at, message_count) = row?;
+            summaries.push(ChatSummary {
+                uuid: parse_uuid(&uuid)?,
+                title,
+                created_at: parse_timestamp(&created_at)?,
+                message_count
+            });
+        }
+        Ok(summaries)
+    }
+
+    pub fn get(&self, uuid: &Uuid) -> Result<Chat, Error> {
+        let conn = self.conn()?;
+        read_chat(&conn, uuid)
+    }
+
+    /// All chats, the newest first.
+    pub fn all(&self) -> Result<Vec<Chat>, Error> {
+        self.list()?.iter().map(|summary| self.get(&summary.uuid)).collect()
+    }
+
+    pub fn exists(&self, uuid: &Uuid) -> Result<bool, Error> {
+        let conn = self.conn()?;
+        chat_exists(&conn, uuid)
+    }
+
+    /// Adds a new chat. Fails if a chat with the same UUID exists.
+    /// Messages without an ID get one, see [Chat::normalize].
+    pub fn create(&self, chat: &Chat) -> Result<(), Error> {
+        validate_chat(chat)?;
+        let chat = &normalized(chat);
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        if chat_exists(&tx, &chat.uuid)? {
+            return Err(Error::InvalidChat(format!("Chat {} already exists", chat.uuid)));
+        }
+        write_chat(&tx, chat)?;
+        Ok(tx.commit()?)
+    }
+
+    /// Adds or replaces a chat.
+    pub fn save(&self, chat: &Chat) -> Result<(), Error> {
+        self.save_all(std::slice::from_ref(chat))
+    }
+
+    /// Adds or replaces multiple chats at once.
+    /// Nothing is written if any chat is invalid.
+    pub fn save_all(&self, chats: &[Chat]) -> Result<(), Error> {
+        for chat in chats {
+            validate_chat(chat)?;
+        }
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        for chat in chats {
+            delete_chat(&tx, &chat.uuid)?;
+            write_chat(&tx, &normalized(chat))?;
+        }
+        Ok(tx.commit()?)
+    }
+
+    /// Adds `message` as an answer to the current message.
+    /// Returns the stored message with its ID.
+    pub fn append_message(&self, uuid: &Uuid, message: ChatMessage) -> Result<ChatMessage, Error> {
+        self.add_message(uuid, None, message, true)
+    }
+
+    /// Adds `message` as an answer to `parent`, which starts a new branch if `parent`
+    /// has been answered already. The new message becomes the current one.
+    /// [None] adds another first message.
+    pub fn fork(&self, uuid: &Uuid, parent: Option<Uuid>, message: ChatMessage) -> Result<ChatMessage, Error> {
+        self.add_message(uuid, parent, message, false)
+    }
+
+    fn add_message(&self, uuid: &Uuid, parent: Option<Uuid>, message: ChatMessage, to_current: bool)
+    -> Result<ChatMessage, Error>
+    {
+        validate_message(&message).map_err(Error::InvalidChat)?;
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let current = tx.query_row(
+            "SELECT current FROM chats WHERE uuid = ?1",
+            [uuid.to_string()],
+            |row| row.get::<_, Option<String>>(0)
+        )
+        .optional()
+        ?
+        .ok_or(Error::ChatNotFound(uuid.to_string()))?;
+
+        let parent = match to_current {
+            true => current.as_deref().map(parse_uuid).transpose()?,
+            false => parent
+        };
+        if let Some(parent) = parent {
+            if !message_exists(&tx, uuid, &parent)? {
+                return Err(Error::InvalidChat(format!("Message {parent} does not exist")));
+            }
+        }
+        let message = message.with_parent(parent);
+        if let Some(id) = message.id() {
+            if message_exists(&tx, uuid, &id)? {
+                return Err(Error::InvalidChat(format!("Message {id} already exists")));
+            }
+        }
+
+        let position: usize = tx.query_row(
+            "SELECT COUNT(*) FROM messages WHERE chat_uuid = ?1",
+            [uuid.to_string()],
+            |row| row.get(0)
+        )?;
+        write_message(&tx, uuid, position, &message)?;
+        set_current(&tx, uuid, message.id())?;
+        tx.commit()?;
+        Ok(message)
+    }
+
+    /// Makes the branch containing the message `id` the active one.
+    /// Returns the chat with its new current message.
+    pub fn switch_branch(&self, uuid: &Uuid, id: &Uuid) -> Result<Chat, Error> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let mut chat = read_chat(&tx, uuid)?;
+        if !chat.switch_branch(id) {
+            return Err(Error::InvalidChat(format!("Message {id} does not exist")));
+        }
+        set_current(&tx, uuid, chat.current)?;
+        tx.commit()?;
+        Ok(chat)
+    }
+
+    pub fn rename(&self, uuid: &Uuid, title: String) -> Result<(), Error> {
+        validate_title(&title)?;
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let changed = tx.execute("UPDATE chats SET title = ?2 WHERE uuid = ?1", params![uuid.to_string(), title])
+            ?;
+        if changed == 0 {
+            return Err(Error::ChatNotFound(uuid.to_string()));
+        }
+        tx.execute(
+            "UPDATE chats_fts SET text = ?2 WHERE chat_uuid = ?1 AND position IS NULL",
+            params![uuid.to_string(), title]
+        )?;
+        Ok(tx.commit()?)
+    }
+
+    pub fn set_knowledge_bases(&self, uuid: &Uuid, knowledge_bases: Vec<Uuid>) -> Result<(), Error> {
+        let conn = self.conn()?;
+        let changed = conn.execute(
+            "UPDATE chats SET knowledge_bases = ?2 WHERE uuid = ?1",
+            params![uuid.to_string(), serde_json::to_string(&knowledge_bases)?]
+        )?;
+        if changed == 0 {
+            return Err(Error::ChatNotFound(uuid.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Rolling summary of the older turns of the chat, see [RollingSummary].
+    pub fn summary(&self, uuid: &Uuid) -> Result<Option<RollingSummary>, Error> {
+        let conn = self.conn()?;
+        let row = conn.query_row(
+            "SELECT content, through, updated_at FROM chat_summaries WHERE chat_uuid = ?1",
+            [uuid.to_string()],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        ).optional()?;
+        row
+            .map(|(content, through, updated_at)| Ok(RollingSummary {
+                content,
+                through: parse_uuid(&through)?,
+                updated_at: parse_timestamp(&updated_at)?
+            }))
+            .transpose()
+    }
+
+    /// Replaces the rolling summary of the chat.
+    pub fn set_summary(&self, uuid: &Uuid, summary: &RollingSummary) -> Result<(), Error> {
+        let conn = self.conn()?;
+        if !chat_exists(&conn, uuid)? {
+            return Err(Error::ChatNotFound(uuid.to_string()));
+        }
+        conn.execute(
+            "INSERT INTO chat_summaries (chat_uuid, content, through, updated_at) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(chat_uuid) DO UPDATE SET content = ?2, through = ?3, updated_at = ?4",
+            params![
+                uuid.to_string(),
+                summary.content,
+                summary.through.to_string(),
+                timestamp_to_string(&summary.updated_at)?
+            ]
+        )?;
+        Ok(())
+    }
+
+    pub fn delete(&self, uuid: &Uuid) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        if !delete_chat(&tx, uuid)? {
+            return Err(Error::ChatNotFound(uuid.to_string()));
+        }
+        tx.execute("DELETE FROM chat_summaries WHERE chat_uuid = ?1", [uuid.to_string()])?;
+        Ok(tx.commit()?)
+    }
+
+    pub fn delete_all(&self) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute_batch(
+            "DELETE FROM chats_fts; DELETE FROM messages; DELETE FROM chats; DELETE FROM chat_summaries;"
+        )?;
+        Ok(tx.commit()?)
+    }
+
+    /// Finds the chats whose title or messages contain all words of `query`.
+    /// The last word may be incomplete. Results are ordered by relevance.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<ChatSearchResult>, Error> {
+        let Some(fts_query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT f.chat_uuid, f.position, snippet(chats_fts, 2, ?2, ?3, '…', 16), c.title, c.created_at
+            FROM chats_fts f JOIN chats c ON c.uuid = f.chat_uuid
+            WHERE chats_fts MATCH ?1
+            ORDER BY rank"
+        )?;
+        let rows = stmt.query_map(
+            params![fts_query, HIGHLIGHT_START.to_string(), HIGHLIGHT_END.to_string()],
+            |row| Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<usize>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?
+            ))
+        )?;
+
+        // Only keep the best match of each chat
+        let mut results: Vec<ChatSearchResult> = Vec::new();
+        for row in rows {
+            let (uuid, position, snippet, title, created_at) = row?;
+            let uuid = parse_uuid(&uuid)?;
+            if results.iter().any(|r| r.uuid == uuid) {
+                continue;
+            }
+            results.push(ChatSearchResult {
+                uuid,
+                title,
+                created_at: parse_timestamp(&created_at)?,
+                position,
+                snippet: split_snippet(&snippet)
+            });
+            if results.len() >= limit {
+                break;
+            }
+        }
+        Ok(results)
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<String>, Error> {
+        let conn = self.conn()?;
+        Ok(conn.query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| row.get(0)).optional()?)
+    }
+
+    fn set_meta(&self, key: &str, value: &str) -> Result<(), Error> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO meta (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = ?2",
+            [key, value]
+        )?;
+        Ok(())
+    }
+}
+
+fn parse_uuid(uuid: &str) -> Result<Uuid, Error> {
+    Uuid::parse_str(uuid).map_err(|e| errors::internal(format!("Invalid UUID '{uuid}': {e}")))
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<UtcDateTime, Error> {
+    UtcDateTime::parse(timestamp, &time::format_description::well_known::Rfc3339)
+        .map_err(|e| errors::internal(format!("Invalid timestamp '{timestamp}': {e}")))
+}
+
+fn chat_exists(conn: &Connection, uuid: &Uuid) -> Result<bool, Error> {
+    let row = conn.query_row("SELECT 1 FROM chats WHERE uuid = ?1", [uuid.to_string()], |_| Ok(())).optional()?;
+    Ok(row.is_some())
+}
+
+fn read_chat(conn: &Connection, uuid: &Uuid) -> Result<Chat, Error> {
+    let (title, created_at, knowledge_bases, current) = conn.query_row(
+        "SELECT title, created_at, knowledge_bases, current FROM chats WHERE uuid = ?1",
+        [uuid.to_string()],
+        |row| Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<String>>(3)?
+        ))
+    )
+    .optional()
+    ?
+    .ok_or(Error::ChatNotFound(uuid.to_string()))?;
+
+    let mut stmt = conn.prepare(
+        "SELECT role, content, thoughts, images, tool_calls, tool_name, tool_call_id, id, parent
+        FROM messages WHERE chat_uuid = ?1 ORDER BY position"
+    )?;
+    let rows = stmt.query_map([uuid.to_string()], |row| Ok((
+        row.get::<_, String>(0)?,
+        row.get::<_, String>(1)?,
+        row.get::<_, Option<String>>(2)?,
+        row.get::<_, Option<String>>(3)?,
+        row.get::<_, Option<String>>(4)?,
+        row.get::<_, Option<String>>(5)?,
+        row.get::<_, Option<String>>(6)?,
+        row.get::<_, String>(7)?,
+        row.get::<_, Option<String>>(8)?
+    )))?;
+
+    let mut history = Vec::new();
+    for row in rows {
+        let (role, content, thoughts, images, tool_calls, tool_name, tool_call_id, id, parent) = row?;
+        let parse_json = |json: Option<String>| json
+            .map(|json| serde_json::from_str::<serde_json::Value>(&json))
+            .transpose();
+        let message = serde_json::json!({
+            "id": parse_uuid(&id)?,
+            "parent": parent.as_deref().map(parse_uuid).transpose()?,
+            "role": role,
+            "content": content,
+            "thoughts": thoughts,
+            "images": parse_json(images)?,
+            "tool_calls": parse_json(tool_calls)?,
+            "tool_name": tool_name,
+            "tool_call_id": tool_call_id
+        });
+        history.push(serde_json::from_value(message)?);
+    }
+
+    Ok(Chat {
+        uuid: *uuid,
+        title,
+        history,
+        current: current.as_deref().map(parse_uuid).transpose()?,
+        created_at: parse_timestamp(&created_at)?,
+        knowledge_bases: serde_json::from_str(&knowledge_bases)?
+    })
+}
+
+fn normalized(chat: &Chat) -> Chat {
+    let mut chat = chat.clone();
+    chat.normalize();
+    chat
+}
+
+fn message_exists(conn: &Connection, uuid: &Uuid, id: &Uuid) -> Result<bool, Error> {
+    let row = conn.query_row(
+        "SELECT 1 FROM messages WHERE chat_uuid = ?1 AND id = ?2",
+        [uuid.to_string(), id.to_string()],
+        |_| Ok(())
+    ).optional()?;
+    Ok(row.is_some())
+}
+
+fn set_current(tx: &Transaction, uuid: &Uuid, current: Option<Uuid>) -> Result<(), Error> {
+    tx.execute(
+        "UPDATE chats SET current = ?2 WHERE uuid = ?1",
+        params![uuid.to_string(), current.map(|id| id.to_string())]
+    )?;
+    Ok(())
+}
+
+fn write_chat(tx: &Transaction, chat: &Chat) -> Result<(), Error> {
+    tx.execute(
+        "INSERT INTO chats (uuid, title, created_at, knowledge_bases, current) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            chat.uuid.to_string(),
+            chat.title,
+            timestamp_to_string(&chat.created_at)?,
+            serde_json::to_string(&chat.knowledge_bases)?,
+            chat.current.map(|id| id.to_string())
+        ]
+    )?;
+    tx.execute(
+        "INSERT INTO chats_fts (chat_uuid, position, text) VALUES (?1, NULL, ?2)",
+        params![chat.uuid.to_string(), chat.title]
+    )?;
+
+    for (position, message) in chat.history.iter().enumerate() {
+        write_message(tx, &chat.uuid, position, message)?;
+    }
+    Ok(())
+}
+
+fn write_message(tx: &Transaction, uuid: &Uuid, position: usize, message: &ChatMessage) -> Result<(), Error> {
+    let role = serde_json::to_value(message.role())?;
+    let images = (!message.images().is_empty())
+        .then(|| serde_json::to_string(message.images()))
+        .transpose()?;
+    let tool_calls = (!message.tool_calls().is_empty())
+        .then(|| serde_json::to_string(message.tool_calls()))
+        .transpose()?;
+
+    let id = message.id().ok_or(errors::internal("Messages need an ID before they are written"))?;
+
+    tx.execute(
+        "INSERT INTO messages (chat_uuid, position, role, content, thoughts, images, tool_calls, tool_name, tool_call_id, id, parent)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            uuid.to_string(),
+            position,
+            role.as_str(),
+            message.content(),
+            message.thoughts(),
+            images,
+            tool_calls,
+            message.tool_name(),
+            message.tool_call_id(),
+            id.to_string(),
+            message.parent().map(|id| id.to_string())
+        ]
+    )?;
+
+    // Tool results are not written by the user or the model
+    if *message.role() != Role::Tool && !message.content().is_empty() {
+        tx.execute(
+            "INSERT INTO chats_fts (chat_uuid, position, text) VALUES (?1, ?2, ?3)",
+            params![uuid.to_string(), position, message.content()]
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns whether the chat existed.
+fn delete_chat(tx: &Transaction, uuid: &Uuid) -> Result<bool, Error> {
+    tx.execute("DELETE FROM chats_fts WHERE chat_uuid = ?1", [uuid.to_string()])?;
+    let deleted = tx.execute("DELETE FROM chats WHERE uuid = ?1", [uuid.to_string()])?;
+    Ok(deleted > 0)
+}
+
+/// Converts user input into an FTS5 query.
+/// Every word is quoted, so FTS5 operators in the input have no effect.
+fn fts_query(query: &str) -> Option<String> {
+    let words: Vec<String> = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    if words.is_empty() {
+        return None;
+    }
+    // Match incomplete words while typing
+    Some(format!("{}*", words.join(" ")))
+}
+
+fn split_snippet(snippet: &str) -> Vec<SnippetPart> {
+    let mut parts = Vec::new();
+    let mut highlight = false;
+    for (i, text) in snippet.split([HIGHLIGHT_START, HIGHLIGHT_END]).enumerate() {
+        if i > 0 {
+            highlight = !highlight;
+        }
+        if !text.is_empty() {
+            parts.push(SnippetPart { text: text.to_owned(), highlight });
+        }
+    }
+    parts
+}
+
+/// Checks a chat before it is written.
+/// Messages with an ID must form a tree, see [Chat::normalize].
+pub fn validate_chat(chat: &Chat) -> Result<(), Error> {
+    validate_title(&chat.title)?;
+    let mut ids = HashSet::new();
+    for (i, message) in chat.history.iter().enumerate() {
+        let invalid = |reason: String| Error::InvalidChat(format!("Message {i}: {reason}"));
+        validate_message(message).map_err(invalid)?;
+        let Some(id) = message.id() else {
+            continue;
+        };
+        if let Some(parent) = message.parent().filter(|parent| !ids.contains(parent)) {
+            return Err(invalid(format!("Parent {parent} must come before the message")));
+        }
+        if !ids.insert(id) {
+            return Err(invalid(format!("ID {id} is not unique")));
+        }
+    }
+    if let Some(current) = chat.current.filter(|current| !ids.contains(current)) {
+        return Err(Error::InvalidChat(format!("Current message {current} does not exist")));
+    }
+    Ok(())
+}
+
+fn validate_title(title: &str) -> Result<(), Error> {
+    if title.trim().is_empty() {
+        return Err(Error::InvalidChat("Title must not be empty".into()));
+    }
+    if title.chars().count() > MAX_TITLE_LEN {
+        return Err(Error::InvalidChat(format!("Title must not be longer than {MAX_TITLE_LEN} characters")));
+    }
+    Ok(())
+}
+
+fn validate_message(message: &ChatMessage) -> Result<(), String> {
+    let is_base64 = |image: &str| !image.is_empty() && image
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'='));
+    if !message.images().iter().all(|image| is_base64(image)) {
+        return Err("Images must be base64 encoded".into());
+    }
+
+    match message.role() {
+        Role::User if message.content().trim().is_empty() && message.images().is_empty() => {
+            Err("User messages must not be empty".into())
+        },
+        Role::Tool if message.tool_name().is_none() => {
+            Err("Tool messages need the name of the called tool".into())
+        },
+        _ => Ok(())
+    }
+}
+
+/// Stores the history of chats as trees, so they can have branches.
+/// Existing messages form a single branch, each one answering the message before it.
+fn add_message_tree(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch("
+        ALTER TABLE messages ADD COLUMN id TEXT;
+        ALTER TABLE messages ADD COLUMN parent TEXT;
+        ALTER TABLE chats ADD COLUMN current TEXT;
+    ")?;
+
+    let messages = {
+        let mut stmt = tx.prepare("SELECT chat_uuid, position FROM messages ORDER BY chat_uuid, position")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?)))?;
+        rows.collect::<Result<Vec<_>, _>>()?
+    };
+    let mut previous: Option<(String, Uuid)> = None;
+    for (chat_uuid, position) in messages {
+        let id = Uuid::new_v4();
+        let parent = previous
+            .filter(|(previous_chat, _)| *previous_chat == chat_uuid)
+            .map(|(_, parent)| parent.to_string());
+        tx.execute(
+            "UPDATE messages SET id = ?3, parent = ?4 WHERE chat_uuid = ?1 AND position = ?2",
+            params![chat_uuid, position, id.to_string(), parent]
+        )?;
+        tx.execute("UPDATE chats SET current = ?2 WHERE uuid = ?1", params![chat_uuid, id.to_string()])?;
+        previous = Some((chat_uuid, id));
+    }
+
+    tx.execute_batch("CREATE UNIQUE INDEX messages_id ON messages(chat_uuid, id);")?;
+    Ok(())
+}
+
+/// Stores summaries of the older turns of chats, see [RollingSummary].
+/// Unlike messages, summaries are kept when a chat is saved again, which replaces its rows.
+/// A summary of messages which have been removed is ignored, since it can't be
+/// matched to the history anymore.
+fn add_rolling_summaries(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch("
+        CREATE TABLE chat_summaries (
+            chat_uuid TEXT PRIMARY KEY,
+            content TEXT NOT NULL,
+            through TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+    ")?;
+    Ok(())
+}
+
+/// Imports the chats of the store file used by older versions.
+/// The store maps the UUIDs onto the chats without them. Chats which have been
+/// imported before are skipped, and the import is repeated on the next start
+/// until no chat has been rejected. The file itself is left untouched.
+fn migrate_store(repo: &ChatRepository, store_path: &Path) -> Result<(), Error> {
+    if repo.meta(STORE_MIGRATED_KEY)?.is_some() {
+        return Ok(());
+    }
+    let entries = match std::fs::read(store_path) {
+        Ok(data) => serde_json::from_slice::<HashMap<String, serde_json::Value>>(&data)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+        Err(e) => return Err(e.into())
+    };
+
+    let mut chats = Vec::new();
+    let mut rejected = 0;
+    for (key, mut value) in entries {
+        if let Some(obj) = value.as_object_mut() {
+            obj.insert("uuid".to_owned(), serde_json::Value::String(key.clone()));
+        }
+        let chat = serde_json::from_value::<Chat>(value)
+            .map_err(Error::from)
+            .and_then(|chat| validate_chat(&chat).map(|_| chat));
+        match chat {
+            Ok(chat) if repo.exists(&chat.uuid)? => {},
+            Ok(chat) => chats.push(chat),
+            Err(e) => {
+                warn!("Cannot migrate chat {}: {}", key, e);
+                rejected += 1;
+            }
+        }
+    }
+    repo.save_all(&chats)?;
+    info!("Migrated {} chats from {:?}", chats.len(), store_path);
+
+    if rejected == 0 {
+        repo.set_meta(STORE_MIGRATED_KEY, &timestamp_to_string(&UtcDateTime::now())?)?;
+    } else {
+        warn!("{} chats of {:?} have been rejected, retrying on the next start", rejected, store_path);
+    }
+    Ok(())
+}
+
+pub fn build_chat_repository(app: &AppHandle<Wry>) -> Result<SharedChatRepository, Error> {
+    let dir = app.path().app_data_dir().unwrap_or(PathBuf::from("."));
+    let repo = ChatRepository::open(&dir.join(DATABASE_PATH))?;
+    migrate_store(&repo, &dir.join(LEGACY_STORE_PATH))?;
+    Ok(Arc::new(repo))
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+    use serde_json::json;
+
+    use super::{migrate_store, ChatRepository, STORE_MIGRATED_KEY};
+
+    fn legacy_store(chats: serde_json::Value) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("chats-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, serde_json::to_vec(&chats).unwrap()).unwrap();
+        path
+    }
+
+    // Written by the frontend of older versions, the UUID is only the key
+    fn legacy_chat(title: &str) -> serde_json::Value {
+        json!({
+            "title": title,
+            "createdAt": "2025-06-30T18:04:12.345Z",
+            "history": [
+                {"role": "user", "content": "What is a monad?"},
+                {"role": "assistant", "content": "A monoid in the category of endofunctors.", "thoughts": "Keep it short"}
+            ]
+        })
+    }
+
+    #[test]
+    fn migrates_legacy_store() {
+        let repo = ChatRepository::init(Connection::open_in_memory().unwrap()).unwrap();
+        let uuids = [uuid::Uuid::new_v4(), uuid::Uuid::new_v4()];
+        let store = legacy_store(json!({
+            uuids[0].to_string(): legacy_chat("Monads"),
+            uuids[1].to_string(): legacy_chat("Functors")
+        }));
+
+        migrate_store(&repo, &store).unwrap();
+        let chat = repo.get(&uuids[0]).unwrap();
+        assert_eq!(chat.title, "Monads");
+        assert_eq!(chat.history.len(), 2);
+        assert_eq!(chat.history[1].content(), "A monoid in the category of endofunctors.");
+        assert_eq!(repo.get(&uuids[1]).unwrap().title, "Functors");
+        assert!(repo.meta(STORE_MIGRATED_KEY).unwrap().is_some());
+        std::fs::remove_file(store).unwrap();
+    }
+
+    #[test]
+    fn retries_after_rejected_chats() {
+        let repo = ChatRepository::init(Connection::open_in_memory().unwrap()).unwrap();
+        let (valid, invalid) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let store = legacy_store(json!({
+            valid.to_string(): legacy_chat("Monads"),
+            invalid.to_string(): {"title": 1}
+        }));
+
+        migrate_store(&repo, &store).unwrap();
+        assert!(repo.exists(&valid).unwrap());
+        assert!(repo.meta(STORE_MIGRATED_KEY).unwrap().is_none());
+
+        // Chats imported before are kept as they are
+        repo.rename(&valid, "Renamed".into()).unwrap();
+        migrate_store(&repo, &store).unwrap();
+        assert_eq!(repo.get(&valid).unwrap().title, "Renamed");
+        std::fs::remove_file(store).unwrap();
+    }
+
+    #[test]
+    fn migrates_missing_store() {
+        let repo = ChatRepository::init(Connection::open_in_memory().unwrap()).unwrap();
+        migrate_store(&repo, &std::env::temp_dir().join("does-not-exist.json")).unwrap();
+        assert!(repo.list().unwrap().is_empty());
+        assert!(repo.meta(STORE_MIGRATED_KEY).unwrap().is_some());
+    }
+}
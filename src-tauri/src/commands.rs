@@ -2,6 +2,8 @@ pub(crate) mod process_commands;
 pub(crate) mod backend_commands;
 pub(crate) mod chat_commands;
 pub(crate) mod ollama_commands;
+pub(crate) mod openai_commands;
+pub(crate) mod knowledge_commands;
 
 #[tauri::command]
 pub fn is_debug() -> bool {
@@ -21,25 +23,64 @@ macro_rules! init {
             crate::commands::backend_commands::shutdown_backend,
             crate::commands::backend_commands::update_models_in_backend,
             crate::commands::backend_commands::get_models_for_backend,
+            crate::commands::backend_commands::get_embedding_models_for_backend,
             crate::commands::backend_commands::get_running_models_in_backend,
             // Models
             crate::commands::backend_commands::is_model_loaded,
             crate::commands::backend_commands::get_model_loaded_size,
             crate::commands::backend_commands::get_model_runtime_info,
+            crate::commands::backend_commands::get_model_card,
             crate::commands::backend_commands::load_model,
             crate::commands::backend_commands::unload_model,
+            crate::commands::backend_commands::embed,
             crate::commands::backend_commands::prompt_model,
+            crate::commands::backend_commands::regenerate_chat_message,
             crate::commands::backend_commands::stop_prompt,
+            crate::commands::backend_commands::get_available_tools,
             // Chats
+            crate::commands::chat_commands::list_chats,
+            crate::commands::chat_commands::get_chat,
+            crate::commands::chat_commands::search_chats,
+            crate::commands::chat_commands::create_chat,
+            crate::commands::chat_commands::append_chat_message,
+            crate::commands::chat_commands::fork_chat,
+            crate::commands::chat_commands::switch_chat_branch,
+            crate::commands::chat_commands::rename_chat,
+            crate::commands::chat_commands::generate_chat_title,
+            crate::commands::chat_commands::set_title_model,
+            crate::commands::chat_commands::get_title_model,
+            crate::commands::chat_commands::set_chat_knowledge_bases,
+            crate::commands::chat_commands::delete_chat,
+            crate::commands::chat_commands::delete_all_chats,
+            crate::commands::chat_commands::render_chats,
             crate::commands::chat_commands::save_chats,
+            crate::commands::chat_commands::export_chats_to_path,
             crate::commands::chat_commands::import_chats,
+            crate::commands::chat_commands::import_chats_from_path,
+            crate::commands::chat_commands::get_chat_export_schema,
+            // Knowledge bases
+            crate::commands::knowledge_commands::list_knowledge_bases,
+            crate::commands::knowledge_commands::create_knowledge_base,
+            crate::commands::knowledge_commands::update_knowledge_base,
+            crate::commands::knowledge_commands::rebuild_knowledge_base,
+            crate::commands::knowledge_commands::query_knowledge_base,
+            crate::commands::knowledge_commands::delete_knowledge_base,
             // Ollama
             crate::commands::ollama_commands::ollama_set_api_url,
             crate::commands::ollama_commands::ollama_get_api_url,
             crate::commands::ollama_commands::ollama_set_models_path,
             crate::commands::ollama_commands::ollama_get_models_path,
             crate::commands::ollama_commands::ollama_pull_model,
-            crate::commands::ollama_commands::ollama_delete_model
+            crate::commands::ollama_commands::ollama_create_model,
+            crate::commands::ollama_commands::ollama_import_gguf,
+            crate::commands::ollama_commands::ollama_parse_modelfile,
+            crate::commands::ollama_commands::ollama_render_modelfile,
+            crate::commands::ollama_commands::ollama_delete_model,
+            // OpenAI
+            crate::commands::openai_commands::openai_set_api_url,
+            crate::commands::openai_commands::openai_get_api_url,
+            crate::commands::openai_commands::openai_set_api_key,
+            crate::commands::openai_commands::openai_has_api_key
         ]
     };
 }
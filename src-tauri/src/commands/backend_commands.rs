@@ -1,8 +1,11 @@
 use std::sync::Arc;
-use log::trace;
+use log::{trace, warn};
+use serde::Serialize;
 use tauri::{ipc::Channel, Manager, Resource, ResourceId, State};
+use tokio::sync::mpsc::Receiver;
+use uuid::Uuid;
 
-use crate::{backend::{chat::{ChatMessage, ChatResponse}, llm::{ModelInfo, PromptResponse, RuntimeInfo, SharedBackend, SharedModel}, BackendStore}, errors::Error};
+use crate::{backend::{agent::{AgentPromptResponse, DEFAULT_MAX_TOOL_STEPS}, context::{fit_context, fit_rolling_context, ContextPolicy, ContextReport, RollingSummary}, chat::{ChatMessage, ChatResponse, Role}, structured_output::ValidatingPromptResponse, llm::{DeferredPromptResponse, Embeddings, GenerationOptions, Model, ModelCard, ModelInfo, PromptResponse, RuntimeInfo, SharedBackend, SharedModel}, tools::{SharedToolRegistry, ToolDefinition}, BackendStore}, chat_repository::SharedChatRepository, commands::knowledge_commands::{context_message, retrieve_chunks, DEFAULT_RETRIEVED_CHUNKS}, errors::{Error, ErrorKind}, knowledge::SharedKnowledgeStore};
 
 pub(super) fn get_backend(backend_name: &str, store: &BackendStore)
 -> Result<SharedBackend, Error>
@@ -13,7 +16,7 @@ pub(super) fn get_backend(backend_name: &str, store: &BackendStore)
         .ok_or(Error::BackendNotFound(backend_name.to_owned()))
 }
 
-async fn get_model(backend_name: &str, model_name: &str, store: &BackendStore)
+pub(super) async fn get_model(backend_name: &str, model_name: &str, store: &BackendStore)
 -> Result<SharedModel, Error>
 {
     let backend_ = get_backend(backend_name, &store)?;
@@ -102,7 +105,28 @@ pub async fn get_models_for_backend(backend_name: &str, store: State<'_, Backend
         let mut models = Vec::new();
         for m in backend.models() {
             let model = m.read().await;
-            models.push(model.info().clone());
+            if !model.info().is_embedding_only() {
+                models.push(model.info().clone());
+            }
+        }
+
+        Ok(models)
+    })
+}
+
+/// Models which can only be used for computing embeddings.
+/// These are not part of [get_models_for_backend].
+#[tauri::command]
+pub async fn get_embedding_models_for_backend(backend_name: &str, store: State<'_, BackendStore>)
+-> Result<Vec<ModelInfo>, Error>
+{
+    with_llm!(backend_name, &store, read|backend {
+        let mut models = Vec::new();
+        for m in backend.models() {
+            let model = m.read().await;
+            if model.info().is_embedding_only() {
+                models.push(model.info().clone());
+            }
         }
 
         Ok(models)
@@ -147,6 +171,17 @@ pub async fn get_model_runtime_info(backend_name: &str, model_name: &str, store:
     })
 }
 
+/// Everything the backend knows about the model
+/// including its Modelfile and system prompt.
+#[tauri::command]
+pub async fn get_model_card(backend_name: &str, model_name: &str, store: State<'_, BackendStore>)
+-> Result<ModelCard, Error>
+{
+    with_llm!(backend_name, &store, model_name, read|model {
+        model.card().await
+    })
+}
+
 #[tauri::command]
 pub async fn load_model(backend_name: &str, model_name: &str, store: State<'_, BackendStore>)
 -> Result<(), Error>
@@ -165,45 +200,238 @@ pub async fn unload_model(backend_name: &str, model_name: &str, store: State<'_,
     })
 }
 
+#[tauri::command]
+pub async fn embed(backend_name: &str, model_name: &str, store: State<'_, BackendStore>, input: Vec<String>)
+-> Result<Embeddings, Error>
+{
+    with_llm!(backend_name, &store, model_name, read|model {
+        model.embed(&input).await
+    })
+}
+
 #[tauri::command]
 pub async fn prompt_model(
     backend_name: &str, model_name: &str, store: State<'_, BackendStore>,
+    tool_registry: State<'_, SharedToolRegistry>,
+    knowledge: State<'_, SharedKnowledgeStore>,
+    chats: State<'_, SharedChatRepository>,
     content: ChatMessage, history: Vec<ChatMessage>, think: bool,
+    options: Option<GenerationOptions>,
+    tools: Option<Vec<String>>,
+    max_tool_steps: Option<u32>,
+    knowledge_bases: Option<Vec<Uuid>>,
+    chat_uuid: Option<Uuid>,
     response_channel: Channel<ChatResponse>,
     app_handle: tauri::AppHandle
 ) -> Result<ResourceId, Error>
 {
-    with_llm!(backend_name, &store, model_name, read|model {
-        let mut res = model.prompt(content, &history, Some(think)).await?;
-        drop(model); // Don't need to lock the model anymore
-        let mut prompts = res.get_prompts()?;
-        let rid = app_handle.resources_table().add_arc(Arc::new(PromptResponseResource(res)));
+    let request = PromptRequest { content, history, think, options, tools, max_tool_steps, knowledge_bases, chat_uuid };
+    start_prompt(backend_name, model_name, &store, &tool_registry, &knowledge, &chats, request, response_channel, app_handle)
+        .await
+}
 
-        tokio::spawn(async move {
-            loop {
-                if let Some(prompt) = prompts.recv().await {
-                    let done = prompt.done;
-                    if response_channel.send(prompt).is_err() || done {
-                        break;
-                    }
-                } else {
-                    // We need to send a "done" msg
-                    // in case the the generation gets aborted
-                    // for whatever reason since we cannot
-                    // close the response_channel
+/// A response generated again by [regenerate_chat_message].
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegeneratedReply {
+    /// Resource of the response like the one returned by [prompt_model]
+    pub rid: ResourceId,
+    /// Prompt the new response answers.
+    /// The response should be added to the chat below it, next to the old one.
+    pub parent: Uuid
+}
+
+/// Generates another response to the prompt answered by the message `message_id`
+/// of a stored chat. The history before the prompt and the knowledge bases of the chat are used.
+#[tauri::command]
+pub async fn regenerate_chat_message(
+    backend_name: &str, model_name: &str, store: State<'_, BackendStore>,
+    tool_registry: State<'_, SharedToolRegistry>,
+    knowledge: State<'_, SharedKnowledgeStore>,
+    chats: State<'_, SharedChatRepository>,
+    uuid: Uuid, message_id: Uuid, think: bool,
+    options: Option<GenerationOptions>,
+    tools: Option<Vec<String>>,
+    max_tool_steps: Option<u32>,
+    response_channel: Channel<ChatResponse>,
+    app_handle: tauri::AppHandle
+) -> Result<RegeneratedReply, Error>
+{
+    let chat = chats.get(&uuid)?;
+    let (content, history) = chat
+        .prompt_of(&message_id)
+        .ok_or(Error::InvalidChat(format!("Message {message_id} does not answer a prompt")))?;
+    let parent = content.id().ok_or(Error::Internal("Stored messages have an ID".into()))?;
+
+    let request = PromptRequest {
+        content, history, think, options, tools, max_tool_steps,
+        knowledge_bases: Some(chat.knowledge_bases),
+        chat_uuid: Some(uuid)
+    };
+    let rid = start_prompt(
+        backend_name, model_name, &store, &tool_registry, &knowledge, &chats, request, response_channel, app_handle
+    ).await?;
+    Ok(RegeneratedReply { rid, parent })
+}
+
+/// Arguments of a prompt shared by [prompt_model] and [regenerate_chat_message].
+struct PromptRequest {
+    content: ChatMessage,
+    history: Vec<ChatMessage>,
+    think: bool,
+    options: Option<GenerationOptions>,
+    tools: Option<Vec<String>>,
+    max_tool_steps: Option<u32>,
+    knowledge_bases: Option<Vec<Uuid>>,
+    /// Stored chat the prompt belongs to, which keeps its rolling summary
+    chat_uuid: Option<Uuid>
+}
+
+/// Prompts the model and forwards its responses to `response_channel`.
+#[allow(clippy::too_many_arguments)]
+async fn start_prompt(
+    backend_name: &str, model_name: &str, store: &BackendStore,
+    tool_registry: &SharedToolRegistry,
+    knowledge: &SharedKnowledgeStore,
+    chats: &SharedChatRepository,
+    request: PromptRequest,
+    response_channel: Channel<ChatResponse>,
+    app_handle: tauri::AppHandle
+) -> Result<ResourceId, Error>
+{
+    let PromptRequest { content, mut history, think, options, tools, max_tool_steps, knowledge_bases, chat_uuid } = request;
+    let mut options = options.unwrap_or_default();
+    options.tools = tools
+        .unwrap_or_default()
+        .iter()
+        .map(|name| tool_registry
+            .get(name)
+            .map(|tool| tool.definition().clone())
+            .ok_or(Error::Internal(format!("Tool '{name}' does not exist"))))
+        .collect::<Result<_, _>>()?;
+
+    // Provide relevant parts of the attached folders
+    if let Some(uuids) = knowledge_bases.filter(|uuids| !uuids.is_empty()) {
+        let chunks = retrieve_chunks(&uuids, content.content(), DEFAULT_RETRIEVED_CHUNKS, knowledge, store).await?;
+        history.extend(context_message(&chunks));
+    }
+
+    // The agent prompts the model again, so we can't use with_llm here
+    let model = get_model(backend_name, model_name, store).await?;
+    let deferred = DeferredPromptResponse::default();
+    let rid = app_handle.resources_table().add_arc(Arc::new(PromptResponseResource(Box::new(deferred.clone()))));
+
+    let tool_registry = tool_registry.clone();
+    let chats = chats.clone();
+    tokio::spawn(async move {
+        // Fitting the history may summarize it, which takes a while,
+        // so it's done here where the prompt can be stopped already
+        let prepared = deferred.prepare(async {
+            let (history, context, summary) =
+                fit_chat_context(&*model.read().await, &content, history, &options, chat_uuid, &chats).await?;
+            let mut res = model.read().await.prompt(content.clone(), &history, Some(think), &options).await?;
+            if !options.tools.is_empty() {
+                let mut messages = history;
+                messages.push(content);
+                res = Box::new(AgentPromptResponse::new(
+                    res, model, tool_registry, messages, Some(think), options.clone(),
+                    max_tool_steps.unwrap_or(DEFAULT_MAX_TOOL_STEPS)
+                ));
+            }
+            if let Some(format) = options.format {
+                res = Box::new(ValidatingPromptResponse::new(res, format));
+            }
+            Ok::<_, Error>((res, context, summary))
+        }).await;
+
+        let mut summary = None;
+        let prompts = match prepared {
+            Some(Ok((res, context, updated))) => {
+                summary = updated;
+                // Report the shortened history before the answer
+                if let Some(context) = context {
                     let _ = response_channel.send(ChatResponse {
-                        done: true,
-                        message: ChatMessage::default()
+                        done: false,
+                        message: ChatMessage::new(Role::Assistant, String::new()),
+                        error: None,
+                        stats: None,
+                        context: Some(context)
                     });
-                    trace!("Prompt generation ended prematurely");
-                    break;
                 }
+                deferred.start(res).await
+            },
+            Some(Err(e)) => Err(e),
+            // Stopped while preparing
+            None => Ok(None)
+        };
+        let answered = match prompts {
+            Ok(prompts) => forward_prompts(prompts, &response_channel).await,
+            Err(e) => {
+                let _ = response_channel.send(ChatResponse::error(ErrorKind::from(&e)));
+                false
             }
-            prompts.close();
-            stop_prompt(rid, app_handle).await;
-        });
-        Ok(rid)
-    })
+        };
+        // The summary only replaces the history once the prompt has been answered
+        if let (Some(uuid), Some(summary), true) = (chat_uuid, summary, answered && !deferred.is_aborted()) {
+            if let Err(e) = chats.set_summary(&uuid, &summary) {
+                warn!("Failed to store the summary of chat {uuid}: {e}");
+            }
+        }
+        stop_prompt(rid, app_handle).await;
+    });
+    Ok(rid)
+}
+
+/// Forwards `prompts` to `response_channel` until the last response.
+/// A response which is done is sent in any case, since the channel can't be closed.
+/// Returns whether the generation finished without an error.
+async fn forward_prompts(prompts: Option<Receiver<ChatResponse>>, response_channel: &Channel<ChatResponse>) -> bool {
+    if let Some(mut prompts) = prompts {
+        while let Some(prompt) = prompts.recv().await {
+            let (done, failed) = (prompt.done, prompt.error.is_some());
+            if response_channel.send(prompt).is_err() {
+                return false;
+            }
+            if done {
+                return !failed;
+            }
+        }
+    }
+    // We need to send a "done" msg
+    // in case the the generation gets aborted
+    // for whatever reason since we cannot
+    // close the response_channel
+    let _ = response_channel.send(ChatResponse {
+        done: true,
+        message: ChatMessage::default(),
+        error: None,
+        stats: None,
+        context: None
+    });
+    trace!("Prompt generation ended prematurely");
+    false
+}
+
+/// Fits `history` into the context window of `model`.
+/// With [ContextPolicy::RollingSummary], the summary of the chat is used
+/// and the updated one is returned, to be stored once the prompt is answered.
+async fn fit_chat_context(
+    model: &dyn Model, content: &ChatMessage, history: Vec<ChatMessage>, options: &GenerationOptions,
+    chat_uuid: Option<Uuid>, chats: &SharedChatRepository
+) -> Result<(Vec<ChatMessage>, Option<ContextReport>, Option<RollingSummary>), Error>
+{
+    let Some(uuid) = chat_uuid.filter(|_| options.context_policy == Some(ContextPolicy::RollingSummary)) else {
+        let (history, report) = fit_context(model, content, history, options).await?;
+        return Ok((history, report, None));
+    };
+    let summary = chats.summary(&uuid)?;
+    fit_rolling_context(model, content, history, options, summary).await
+}
+
+#[tauri::command]
+pub fn get_available_tools(tool_registry: State<'_, SharedToolRegistry>) -> Vec<ToolDefinition>
+{
+    tool_registry.definitions()
 }
 
 #[tauri::command]
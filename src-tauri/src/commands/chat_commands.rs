@@ -1,10 +1,29 @@
-use std::io::BufReader;
+use std::path::PathBuf;
 
-use crate::{backend::chat::{Chat, timestamp_to_string}, errors};
+use crate::{
+    backend::{chat::{Chat, ChatMessage}, title::{fallback_title, generate_title}, BackendStore},
+    chat_format,
+    chat_repository::{ChatSearchResult, ChatSummary, SharedChatRepository},
+    errors,
+    export::{self, ExportFormat, ExportReport},
+    import::{self, ImportFormat, ImportReport, MergeStrategy},
+    settings::{AppSettings, TitleModel},
+};
 use crate::errors::Error;
-use tauri::{AppHandle, Manager, WebviewWindow};
+use tauri::{AppHandle, Manager, State, WebviewWindow};
+use log::{info, warn};
 use tauri_plugin_dialog::DialogExt;
-use tauri_plugin_store::StoreExt;
+use uuid::Uuid;
+
+use super::backend_commands::get_model;
+
+/// The chats with the given UUIDs or all chats if [None].
+fn selected_chats(uuids: Option<&[Uuid]>, chats: &SharedChatRepository) -> Result<Vec<Chat>, Error> {
+    match uuids {
+        Some(uuids) => uuids.iter().map(|uuid| chats.get(uuid)).collect(),
+        None => chats.all()
+    }
+}
 
 fn get_main_window(app: &AppHandle) -> Result<WebviewWindow, Error>
 {
@@ -14,46 +33,198 @@ fn get_main_window(app: &AppHandle) -> Result<WebviewWindow, Error>
 }
 
 #[tauri::command]
-pub async fn save_chats(store_name: &str, app: AppHandle) 
--> Result<(), Error> 
+pub async fn list_chats(chats: State<'_, SharedChatRepository>)
+-> Result<Vec<ChatSummary>, Error>
+{
+    chats.list()
+}
+
+/// Number of search results if not specified otherwise.
+const DEFAULT_SEARCH_LIMIT: usize = 50;
+
+/// Full-text search over the titles and messages of all chats.
+#[tauri::command]
+pub async fn search_chats(query: String, limit: Option<usize>, chats: State<'_, SharedChatRepository>)
+-> Result<Vec<ChatSearchResult>, Error>
+{
+    chats.search(&query, limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+}
+
+#[tauri::command]
+pub async fn get_chat(uuid: Uuid, chats: State<'_, SharedChatRepository>)
+-> Result<Chat, Error>
+{
+    chats.get(&uuid)
+}
+
+/// Stores a new chat. Returns it with the IDs assigned to its messages.
+#[tauri::command]
+pub async fn create_chat(mut chat: Chat, chats: State<'_, SharedChatRepository>)
+-> Result<Chat, Error>
+{
+    chat.normalize();
+    chats.create(&chat)?;
+    Ok(chat)
+}
+
+/// Adds a message to the active branch. Returns it with its ID.
+#[tauri::command]
+pub async fn append_chat_message(uuid: Uuid, message: ChatMessage, chats: State<'_, SharedChatRepository>)
+-> Result<ChatMessage, Error>
+{
+    chats.append_message(&uuid, message)
+}
+
+/// Adds a message below `parent`, e.g. an edited prompt or a regenerated response.
+/// The new branch becomes the active one.
+#[tauri::command]
+pub async fn fork_chat(uuid: Uuid, parent: Option<Uuid>, message: ChatMessage, chats: State<'_, SharedChatRepository>)
+-> Result<ChatMessage, Error>
+{
+    chats.fork(&uuid, parent, message)
+}
+
+/// Activates the branch containing the message `message_id`.
+#[tauri::command]
+pub async fn switch_chat_branch(uuid: Uuid, message_id: Uuid, chats: State<'_, SharedChatRepository>)
+-> Result<Chat, Error>
+{
+    chats.switch_branch(&uuid, &message_id)
+}
+
+#[tauri::command]
+pub async fn rename_chat(uuid: Uuid, title: String, chats: State<'_, SharedChatRepository>)
+-> Result<(), Error>
+{
+    chats.rename(&uuid, title)
+}
+
+/// Names a chat after its first exchange and stores the title.
+/// The configured title model is used, otherwise the given model, which is usually the one of the chat.
+/// Without any available model, the title is derived from the first prompt.
+#[tauri::command]
+pub async fn generate_chat_title(
+    uuid: Uuid, backend_name: Option<String>, model_name: Option<String>,
+    store: State<'_, BackendStore>, settings: State<'_, AppSettings>, chats: State<'_, SharedChatRepository>
+) -> Result<String, Error>
 {
-    // Get Chats and format them into a list together with the id
-    let chat_store = app.store(&store_name).map_err(errors::internal)?;
-    let mut chats: Vec<Chat> = Vec::with_capacity(chat_store.length());
-    for key in chat_store.keys().iter() {
-        if let Some(mut chat) = chat_store.get(&key) {
-            let c = chat.as_object_mut().ok_or(Error::Internal("Wrong chat format!".into()))?;
-            c.insert("uuid".into(), serde_json::json!(key.to_owned()));
-            let chat: Chat = serde_json::from_value(chat)?;
-            chats.push(chat);
-        }
+    let chat = chats.get(&uuid)?;
+    let history: Vec<ChatMessage> = chat.active_path().into_iter().cloned().collect();
+
+    let model = match settings.read().await.title_model() {
+        Some(model) => Some((model.backend_name, model.model_name)),
+        None => backend_name.zip(model_name)
+    };
+    let mut title = None;
+    if let Some((backend_name, model_name)) = model {
+        let generated = match get_model(&backend_name, &model_name, &store).await {
+            Ok(model) => generate_title(&*model.read().await, &history).await,
+            Err(e) => Err(e)
+        };
+        title = generated
+            .inspect_err(|e| warn!("Cannot generate a title with '{model_name}': {e}"))
+            .ok()
+            .flatten();
     }
 
+    let title = title.unwrap_or_else(|| fallback_title(&history));
+    chats.rename(&uuid, title.clone())?;
+    Ok(title)
+}
+
+/// Model used by [generate_chat_title]. [None] uses the model of the chat.
+#[tauri::command]
+pub async fn set_title_model(model: Option<TitleModel>, settings: State<'_, AppSettings>)
+-> Result<(), Error>
+{
+    settings.read().await.store_title_model(model.as_ref());
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_title_model(settings: State<'_, AppSettings>)
+-> Result<Option<TitleModel>, Error>
+{
+    Ok(settings.read().await.title_model())
+}
+
+#[tauri::command]
+pub async fn set_chat_knowledge_bases(uuid: Uuid, knowledge_bases: Vec<Uuid>, chats: State<'_, SharedChatRepository>)
+-> Result<(), Error>
+{
+    chats.set_knowledge_bases(&uuid, knowledge_bases)
+}
+
+#[tauri::command]
+pub async fn delete_chat(uuid: Uuid, chats: State<'_, SharedChatRepository>)
+-> Result<(), Error>
+{
+    chats.delete(&uuid)
+}
+
+#[tauri::command]
+pub async fn delete_all_chats(chats: State<'_, SharedChatRepository>)
+-> Result<(), Error>
+{
+    chats.delete_all()
+}
+
+/// Renders the chats with the given UUIDs, e.g. to copy them into the clipboard.
+#[tauri::command]
+pub async fn render_chats(uuids: Vec<Uuid>, format: ExportFormat, chats: State<'_, SharedChatRepository>)
+-> Result<String, Error>
+{
+    export::render(&selected_chats(Some(&uuids), &chats)?, format)
+}
+
+/// Exports the selected chats or all chats into a file chosen by the user.
+/// Only [ExportFormat::Json] can be imported again.
+#[tauri::command]
+pub async fn save_chats(
+    app: AppHandle, format: Option<ExportFormat>, uuids: Option<Vec<Uuid>>,
+    chats: State<'_, SharedChatRepository>
+) -> Result<Option<ExportReport>, Error>
+{
+    let format = format.unwrap_or_default();
+    let selected = selected_chats(uuids.as_deref(), &chats)?;
+
     let main_window = get_main_window(&app)?;
     let Some(path) = app
         .dialog()
         .file()
         .set_title("Save Chats")
-        .add_filter("Chats", &["json"])
-        .set_file_name("chats.json")
+        .add_filter(format.name(), &[format.extension()])
+        .set_file_name(format!("chats.{}", format.extension()))
         .set_parent(&main_window)
         .blocking_save_file() else {
-            return Ok(());
+            return Ok(None);
         };
 
-    println!("Saving chats to {path:?}");
-    let file = std::fs::File::create(
-        path.into_path().map_err(errors::internal)?
-    )?;
-    serde_json::to_writer_pretty(file, &chats).map_err(|e| Error::SerdeJson(e))
+    info!("Saving chats to {path:?}");
+    let path = path.into_path().map_err(errors::internal)?;
+    export::export_to_file(&selected, &path, format, false).map(Some)
 }
 
+/// Exports the selected chats or all chats to `path` without asking the user.
 #[tauri::command]
-pub async fn import_chats(store_name: &str, app: AppHandle)
--> Result<(), Error>
+pub async fn export_chats_to_path(
+    path: PathBuf, format: Option<ExportFormat>, uuids: Option<Vec<Uuid>>, dry_run: Option<bool>,
+    chats: State<'_, SharedChatRepository>
+) -> Result<ExportReport, Error>
 {
-    let chat_store = app.store(&store_name).map_err(errors::internal)?;
+    let selected = selected_chats(uuids.as_deref(), &chats)?;
+    export::export_to_file(&selected, &path, format.unwrap_or_default(), dry_run.unwrap_or(false))
+}
 
+/// Imports chats from a file chosen by the user.
+/// Existing chats with the same UUID are replaced unless another `strategy` is given.
+/// The format of the file is detected if not given.
+#[tauri::command]
+pub async fn import_chats(
+    app: AppHandle, format: Option<ImportFormat>, strategy: Option<MergeStrategy>,
+    chats: State<'_, SharedChatRepository>
+) -> Result<Option<ImportReport>, Error>
+{
     let main_window = get_main_window(&app)?;
     let Some(path) = app
         .dialog()
@@ -62,24 +233,29 @@ pub async fn import_chats(store_name: &str, app: AppHandle)
         .add_filter("Chats", &["json"])
         .set_parent(&main_window)
         .blocking_pick_file() else {
-            return Ok(());
+            return Ok(None);
         };
 
-    println!("Importing chats from {path:?}");
-    let file = std::fs::File::open(
-        path.into_path().map_err(|e| Error::Internal(e.to_string()))?
-    )?;
-    let reader = BufReader::new(file);
-
-    // Map chat ids onto their chats
-    let chats: Vec<Chat> = serde_json::from_reader(reader).map_err(|e| Error::SerdeJson(e))?;
-    for chat in chats {
-        let timestamp = timestamp_to_string(&chat.created_at)?;
-        chat_store.set(chat.uuid.to_string(), serde_json::json!({
-            "title": chat.title,
-            "createdAt": timestamp,
-            "history": chat.history
-        }));
-    }
-    chat_store.save().map_err(errors::internal)
+    info!("Importing chats from {path:?}");
+    let path = path.into_path().map_err(errors::internal)?;
+    import::import_file(&chats, &path, format, None, strategy.unwrap_or_default(), false).map(Some)
+}
+
+/// Imports the selected chats or all chats of the file at `path` without asking the user.
+#[tauri::command]
+pub async fn import_chats_from_path(
+    path: PathBuf, format: Option<ImportFormat>, uuids: Option<Vec<Uuid>>,
+    strategy: Option<MergeStrategy>, dry_run: Option<bool>,
+    chats: State<'_, SharedChatRepository>
+) -> Result<ImportReport, Error>
+{
+    let (strategy, dry_run) = (strategy.unwrap_or_default(), dry_run.unwrap_or(false));
+    import::import_file(&chats, &path, format, uuids.as_deref(), strategy, dry_run)
+}
+
+/// JSON Schema of files written by [ExportFormat::Json].
+#[tauri::command]
+pub fn get_chat_export_schema() -> Result<serde_json::Value, Error>
+{
+    Ok(serde_json::from_str(chat_format::SCHEMA)?)
 }
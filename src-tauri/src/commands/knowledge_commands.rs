@@ -0,0 +1,183 @@
+use std::path::PathBuf;
+
+use log::warn;
+use tauri::State;
+use uuid::Uuid;
+
+use crate::{
+    backend::{chat::{ChatMessage, Role}, BackendStore},
+    commands::backend_commands::get_model,
+    errors::{self, Error},
+    knowledge::{
+        index::{IndexReport, KnowledgeBase, KnowledgeBaseInfo, RetrievedChunk},
+        SharedKnowledgeBase, SharedKnowledgeStore,
+    },
+};
+
+/// Number of chunks retrieved if not specified otherwise.
+pub(super) const DEFAULT_RETRIEVED_CHUNKS: usize = 5;
+
+/// Updates the index of the knowledge base and saves it if anything changed.
+/// Changes are saved even if indexing failed half way.
+async fn update_index(
+    base: &SharedKnowledgeBase, rebuild: bool,
+    knowledge: &SharedKnowledgeStore, store: &BackendStore
+) -> Result<IndexReport, Error>
+{
+    let mut base = base.write().await;
+    let model = get_model(&base.info().backend_name, &base.info().embedding_model, store).await?;
+    if rebuild {
+        base.clear();
+    }
+    let report = base.update(&model).await;
+    if rebuild || report.as_ref().map_or(true, IndexReport::changed) {
+        knowledge.save(&base).await?;
+    }
+    report
+}
+
+/// Updates the index before retrieving if files of the folder have changed.
+/// The folder is checked at most every few seconds and without holding the
+/// write lock, so prompts only wait for changed files being embedded.
+async fn refresh_index(
+    base: &SharedKnowledgeBase,
+    knowledge: &SharedKnowledgeStore, store: &BackendStore
+) -> Result<(), Error>
+{
+    let found = {
+        let base = base.read().await;
+        if !base.needs_scan() {
+            return Ok(());
+        }
+        base.scan().await?
+    };
+
+    let mut base = base.write().await;
+    if !base.has_changes(&found) {
+        base.mark_scanned();
+        return Ok(());
+    }
+    let model = get_model(&base.info().backend_name, &base.info().embedding_model, store).await?;
+    let report = base.update_files(found, &model).await;
+    knowledge.save(&base).await?;
+    report.map(|_| ())
+}
+
+/// Retrieves the chunks of the knowledge bases most relevant to `query`.
+/// The indices are refreshed before, so changed files are taken into account.
+pub(super) async fn retrieve_chunks(
+    uuids: &[Uuid], query: &str, top_k: usize,
+    knowledge: &SharedKnowledgeStore, store: &BackendStore
+) -> Result<Vec<RetrievedChunk>, Error>
+{
+    let mut chunks = Vec::new();
+    for uuid in uuids {
+        let base = knowledge.get(uuid).await?;
+        if let Err(e) = refresh_index(&base, knowledge, store).await {
+            warn!("Cannot update knowledge base {}: {:?}", uuid, e);
+        }
+
+        let base = base.read().await;
+        let model = get_model(&base.info().backend_name, &base.info().embedding_model, store).await?;
+        let query_embedding = model
+            .read()
+            .await
+            .embed(&[query.to_owned()])
+            .await?
+            .embeddings
+            .pop()
+            .ok_or(errors::internal("Backend returned no embedding"))?;
+        base.check_dimension(query_embedding.len())?;
+        chunks.extend(base.search(&query_embedding, top_k));
+    }
+    chunks.sort_by(|a, b| b.score.total_cmp(&a.score));
+    chunks.truncate(top_k);
+    Ok(chunks)
+}
+
+/// Message providing the retrieved chunks to the model.
+/// [None] if no chunks have been retrieved.
+pub(super) fn context_message(chunks: &[RetrievedChunk]) -> Option<ChatMessage> {
+    if chunks.is_empty() {
+        return None;
+    }
+    let mut context = String::from(
+        "Use the following excerpts of local files to answer the next message if they are relevant. \
+        Mention the files you use.\n"
+    );
+    for chunk in chunks {
+        context.push_str(&format!(
+            "\n<file path=\"{}\" lines=\"{}-{}\">\n{}\n</file>\n",
+            chunk.path, chunk.start_line, chunk.end_line, chunk.text
+        ));
+    }
+    Some(ChatMessage::new(Role::System, context))
+}
+
+#[tauri::command]
+pub async fn list_knowledge_bases(knowledge: State<'_, SharedKnowledgeStore>)
+-> Result<Vec<KnowledgeBaseInfo>, Error>
+{
+    Ok(knowledge.list().await)
+}
+
+/// Creates a knowledge base for `folder` and indexes it
+/// using the embedding model `model_name`.
+#[tauri::command]
+pub async fn create_knowledge_base(
+    name: String, folder: PathBuf, backend_name: String, model_name: String,
+    knowledge: State<'_, SharedKnowledgeStore>, store: State<'_, BackendStore>
+) -> Result<KnowledgeBaseInfo, Error>
+{
+    if !folder.is_dir() {
+        return Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Folder {folder:?} does not exist")
+        )));
+    }
+    // Fail early if the model doesn't exist
+    get_model(&backend_name, &model_name, &store).await?;
+
+    let base = knowledge.insert(KnowledgeBase::new(name, folder, backend_name, model_name)).await?;
+    update_index(&base, false, &knowledge, &store).await?;
+    let info = base.read().await.info().clone();
+    Ok(info)
+}
+
+/// Indexes new and modified files and removes deleted ones.
+#[tauri::command]
+pub async fn update_knowledge_base(
+    uuid: Uuid,
+    knowledge: State<'_, SharedKnowledgeStore>, store: State<'_, BackendStore>
+) -> Result<IndexReport, Error>
+{
+    let base = knowledge.get(&uuid).await?;
+    update_index(&base, false, &knowledge, &store).await
+}
+
+/// Indexes all files again.
+#[tauri::command]
+pub async fn rebuild_knowledge_base(
+    uuid: Uuid,
+    knowledge: State<'_, SharedKnowledgeStore>, store: State<'_, BackendStore>
+) -> Result<IndexReport, Error>
+{
+    let base = knowledge.get(&uuid).await?;
+    update_index(&base, true, &knowledge, &store).await
+}
+
+#[tauri::command]
+pub async fn query_knowledge_base(
+    uuid: Uuid, query: String, top_k: Option<usize>,
+    knowledge: State<'_, SharedKnowledgeStore>, store: State<'_, BackendStore>
+) -> Result<Vec<RetrievedChunk>, Error>
+{
+    retrieve_chunks(&[uuid], &query, top_k.unwrap_or(DEFAULT_RETRIEVED_CHUNKS), &knowledge, &store).await
+}
+
+#[tauri::command]
+pub async fn delete_knowledge_base(uuid: Uuid, knowledge: State<'_, SharedKnowledgeStore>)
+-> Result<(), Error>
+{
+    knowledge.delete(&uuid).await
+}
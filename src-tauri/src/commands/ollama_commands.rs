@@ -2,8 +2,9 @@ use std::path::PathBuf;
 
 use log::trace;
 use tauri::{ipc::Channel, State};
+use tokio::sync::mpsc::Receiver;
 
-use crate::{backend::{ollama::{not_ollama, OllamaBackend, OllamaPullProgress, OLLAMA_NAME}, BackendStore}, commands::backend_commands::get_backend, errors::{self, Error}, settings::AppSettings, with_llm};
+use crate::{backend::{modelfile::Modelfile, ollama::{not_ollama, OllamaBackend, OllamaCreateRequest, OllamaPullProgress, OLLAMA_NAME}, BackendStore}, commands::backend_commands::get_backend, errors::{self, Error}, settings::AppSettings, with_llm};
 
 #[tauri::command]
 pub async fn ollama_set_api_url(
@@ -72,27 +73,106 @@ pub async fn ollama_pull_model(
 {
     with_llm!(OLLAMA_NAME, &store, write|backend {
         let ollama = backend.to_mut::<OllamaBackend>().ok_or(not_ollama())?;
-        let mut progress_receiver = ollama.pull_model(&tag).await?;
-        tokio::spawn(async move {
-            let mut iter: u64 = 0;
-            while let Some(progress) = progress_receiver.recv().await {
-                if iter % 64 == 0 || progress.status == "success" || progress.error.is_some() {
-                    trace!("Pulling {tag} - {:?}", progress);
-                    if let Err(e) = progress_channel.send(progress) {
-                        trace!("Pulling model - Channel closed: {:?}", e);
-                        break;
-                    }
-                }
-                iter += 1;
-            }
-            let _ = progress_channel.send(OllamaPullProgress {
-                status: "done".into(), digest: None, total: None, completed: None, error: None
-            });
-        });
+        let progress_receiver = ollama.pull_model(&tag).await?;
+        tokio::spawn(forward_progress("Pulling", tag, progress_receiver, progress_channel));
         Ok(())
     })
 }
 
+/// Creates the model `name` from `modelfile`, e.g. with another system prompt.
+/// The adapters of the Modelfile are uploaded first, reported like by [ollama_import_gguf].
+/// The progress is reported like by [ollama_pull_model].
+#[tauri::command]
+pub async fn ollama_create_model(
+    name: String,
+    modelfile: Modelfile,
+    quantize: Option<String>,
+    progress_channel: Channel<OllamaPullProgress>,
+    store: State<'_, BackendStore>,
+)
+-> Result<(), errors::Error>
+{
+    with_llm!(OLLAMA_NAME, &store, read|backend {
+        let ollama = backend.to::<OllamaBackend>().ok_or(not_ollama())?;
+        let progress_receiver = ollama.create_model(name.clone(), modelfile, quantize)?;
+        tokio::spawn(forward_progress("Creating", name, progress_receiver, progress_channel));
+        Ok(())
+    })
+}
+
+/// Imports the local GGUF file at `path` as the model `name`.
+/// Hashing and uploading the file is reported before the progress of [ollama_create_model].
+#[tauri::command]
+pub async fn ollama_import_gguf(
+    name: String,
+    path: String,
+    quantize: Option<String>,
+    progress_channel: Channel<OllamaPullProgress>,
+    store: State<'_, BackendStore>,
+)
+-> Result<(), errors::Error>
+{
+    let path = PathBuf::from(path);
+    if !path.is_file() {
+        return Err(Error::Io(std::io::Error::new(std::io::ErrorKind::NotFound, "GGUF file does not exist")));
+    }
+    if !path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gguf")) {
+        return Err(Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Only GGUF files can be imported")));
+    }
+
+    let request = OllamaCreateRequest {
+        model: name.clone(),
+        quantize,
+        ..Default::default()
+    };
+    with_llm!(OLLAMA_NAME, &store, read|backend {
+        let ollama = backend.to::<OllamaBackend>().ok_or(not_ollama())?;
+        let progress_receiver = ollama.import_gguf(request, path);
+        tokio::spawn(forward_progress("Importing", name, progress_receiver, progress_channel));
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn ollama_parse_modelfile(text: &str) -> Result<Modelfile, errors::Error> {
+    Modelfile::parse(text)
+}
+
+#[tauri::command]
+pub fn ollama_render_modelfile(modelfile: Modelfile) -> String {
+    modelfile.to_string()
+}
+
+/// Sends every 64th progress of `action` on the model `name` to `progress_channel`,
+/// and all of them changing the status or reporting an error. Ends with a `done` status.
+async fn forward_progress(
+    action: &'static str,
+    name: String,
+    mut progress_receiver: Receiver<OllamaPullProgress>,
+    progress_channel: Channel<OllamaPullProgress>
+) {
+    let mut iter: u64 = 0;
+    let mut last_status = None;
+    while let Some(progress) = progress_receiver.recv().await {
+        // Statuses like "hashing" or "success" may only be sent once
+        let changed = last_status.as_ref() != Some(&progress.status);
+        if changed {
+            last_status = Some(progress.status.clone());
+        }
+        if changed || iter.is_multiple_of(64) || progress.error.is_some() {
+            trace!("{action} {name} - {:?}", progress);
+            if let Err(e) = progress_channel.send(progress) {
+                trace!("{action} model - Channel closed: {:?}", e);
+                break;
+            }
+        }
+        iter += 1;
+    }
+    let _ = progress_channel.send(OllamaPullProgress {
+        status: "done".into(), ..Default::default()
+    });
+}
+
 #[tauri::command]
 pub async fn ollama_delete_model(tag: String, store: State<'_, BackendStore>)
 -> Result<(), errors::Error>
@@ -0,0 +1,56 @@
+use tauri::State;
+
+use crate::{backend::{openai::{not_openai, OpenAiBackend, OPENAI_NAME}, BackendStore}, commands::backend_commands::get_backend, errors, settings::AppSettings, with_llm};
+
+#[tauri::command]
+pub async fn openai_set_api_url(
+    url: &str,
+    store: State<'_, BackendStore>,
+    settings: State<'_, AppSettings>
+)
+-> Result<(), errors::Error>
+{
+    with_llm!(OPENAI_NAME, &store, write|backend {
+        let openai = backend.to_mut::<OpenAiBackend>().ok_or(not_openai())?;
+        openai.set_api_url(url)?;
+        settings.read().await.store_openai_url(url);
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub async fn openai_get_api_url(store: State<'_, BackendStore>)
+-> Result<String, errors::Error>
+{
+    with_llm!(OPENAI_NAME, &store, read|backend {
+        let openai = backend.to::<OpenAiBackend>().ok_or(not_openai())?;
+        Ok(openai.get_api_url().to_string())
+    })
+}
+
+#[tauri::command]
+pub async fn openai_set_api_key(
+    api_key: Option<String>,
+    store: State<'_, BackendStore>,
+    settings: State<'_, AppSettings>
+)
+-> Result<(), errors::Error>
+{
+    with_llm!(OPENAI_NAME, &store, write|backend {
+        let openai = backend.to_mut::<OpenAiBackend>().ok_or(not_openai())?;
+        openai.set_api_key(api_key.clone());
+        settings.read().await.store_openai_api_key(api_key.as_deref().filter(|key| !key.is_empty()));
+        Ok(())
+    })
+}
+
+/// The API key itself is never handed out to the frontend.
+#[tauri::command]
+pub async fn openai_has_api_key(store: State<'_, BackendStore>)
+-> Result<bool, errors::Error>
+{
+    with_llm!(OPENAI_NAME, &store, read|backend {
+        let openai = backend.to::<OpenAiBackend>().ok_or(not_openai())?;
+        Ok(openai.has_api_key())
+    })
+}
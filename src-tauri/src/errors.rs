@@ -1,4 +1,4 @@
-use reqwest::StatusCode;
+use reqwest::{Response, StatusCode};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -8,12 +8,30 @@ pub enum Error {
     Http(#[from] reqwest::Error),
     #[error(transparent)]
     SerdeJson(#[from] serde_json::Error),
+    #[error("Database error")]
+    Database(#[from] rusqlite::Error),
     #[error("Backend not found: {0}")]
     BackendNotFound(String),
     #[error("Error starting backend '{backend:?}': {reason:?}")]
     BackendBoot{reason: String, backend: String},
     #[error("Model '{model:?}' not found in backend '{backend:?}'")]
     ModelNotFound{model: String, backend: String},
+    #[error("Knowledge base not found: {0}")]
+    KnowledgeBaseNotFound(String),
+    #[error("The index of knowledge base '{0}' has been built with another embedding model")]
+    IndexOutdated(String),
+    #[error("Chat not found: {0}")]
+    ChatNotFound(String),
+    #[error("Invalid chat: {0}")]
+    InvalidChat(String),
+    #[error("Chat file has the unsupported format version {0}")]
+    UnsupportedFileVersion(u32),
+    #[error("Invalid Modelfile: {0}")]
+    InvalidModelfile(String),
+    #[error("Uploading '{file}' failed: {reason}")]
+    Upload{file: String, reason: String},
+    #[error("Generation failed: {0:?}")]
+    Generation(ErrorKind),
     #[error("Internal error - There is a bug: {0}")]
     Internal(String),
     #[error("Internal error")]
@@ -25,7 +43,39 @@ pub fn internal(msg: impl ToString) -> Error {
     Error::Internal(msg.to_string())
 }
 
-#[derive(serde::Serialize)]
+/// Extracts the error message out of an error object send by a backend.
+/// Supports `{"error": "msg"}` (Ollama), `{"error": {"message": "msg"}}` (OpenAI)
+/// and `{"message": "msg"}` or `{"detail": "msg"}` (vLLM and others).
+pub fn backend_message(value: &serde_json::Value) -> Option<String> {
+    let error = value.get("error").unwrap_or(value);
+    if let Some(msg) = error.as_str() {
+        return Some(msg.to_owned());
+    }
+    ["message", "detail"]
+        .iter()
+        .find_map(|key| error.get(key).and_then(|msg| msg.as_str()))
+        .map(|msg| msg.to_owned())
+}
+
+/// Converts an unsuccessful response of a chat completion into an error.
+pub async fn response_error(res: Response, model: &str, backend: &str) -> Error {
+    let status = res.status();
+    let http_error = match res.error_for_status_ref() {
+        Ok(_) => return internal("Response has been successful"),
+        Err(e) => e
+    };
+
+    let msg = res.json::<serde_json::Value>().await.ok().and_then(|body| backend_message(&body));
+    match msg {
+        _ if status == StatusCode::NOT_FOUND => {
+            Error::ModelNotFound { model: model.to_owned(), backend: backend.to_owned() }
+        },
+        Some(msg) => Error::Generation(ErrorKind::from_backend_message(&msg, model, backend)),
+        None => Error::Http(http_error)
+    }
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
 #[serde(tag = "kind", content = "message")]
 #[serde(rename_all = "camelCase")]
 pub enum ErrorKind {
@@ -34,45 +84,127 @@ pub enum ErrorKind {
     BackendNotFound(String),
     BackendBoot{reason: String, backend: String},
     ModelNotFound{model: String, backend: String},
+    KnowledgeBaseNotFound(String),
+    /// The embeddings of the named knowledge base don't match its model anymore,
+    /// so the knowledge base has to be rebuilt.
+    IndexOutdated(String),
+    ChatNotFound(String),
+    /// A chat failed validation before it was written.
+    InvalidChat(String),
+    /// The chat file has been written by a newer version.
+    UnsupportedFileVersion(u32),
+    /// A Modelfile could not be parsed or used.
+    InvalidModelfile(String),
+    /// A file could not be uploaded to the backend, e.g. an adapter of a new model.
+    Upload{file: String, reason: String},
+    /// Reading or writing the chat database failed.
+    Database(String),
+    /// The backend could not allocate enough memory for the model.
+    OutOfMemory(String),
+    /// The prompt exceeds the context length of the model.
+    ContextTooLong(String),
+    /// The backend send data we cannot parse.
+    InvalidResponse(String),
+    /// The response doesn't match the requested format or JSON schema.
+    SchemaViolation(Vec<String>),
+    /// The model still called tools after the maximum number of steps.
+    ToolStepLimit(u32),
+    /// Any other error reported by the backend during generation.
+    Generation(String),
     Internal(String)
 }
 
-impl serde::Serialize for Error {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        let error_kind = match self {
-            Self::Io(e) => {
+impl ErrorKind {
+    /// Classifies an error message reported by a backend
+    /// during a chat completion.
+    pub fn from_backend_message(msg: &str, model: &str, backend: &str) -> Self {
+        let lower = msg.to_lowercase();
+        if lower.contains("not found") && lower.contains("model") {
+            ErrorKind::ModelNotFound { model: model.to_owned(), backend: backend.to_owned() }
+        } else if lower.contains("out of memory")
+            || lower.contains("more system memory")
+            || lower.contains("unable to allocate")
+            || lower.contains("insufficient memory") {
+            ErrorKind::OutOfMemory(msg.to_owned())
+        } else if lower.contains("context length")
+            || lower.contains("context window")
+            || lower.contains("context_length_exceeded")
+            || lower.contains("too many tokens") {
+            ErrorKind::ContextTooLong(msg.to_owned())
+        } else {
+            ErrorKind::Generation(msg.to_owned())
+        }
+    }
+}
+
+impl From<&Error> for ErrorKind {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::Io(e) => {
                 let error_msg = e.to_string();
                 ErrorKind::Io(error_msg)
             },
-            Self::SerdeJson(e) => {
+            Error::SerdeJson(e) => {
                 let error_msg = e.to_string();
                 ErrorKind::Io(error_msg)
             }
-            Self::Http(e) => {
+            Error::Http(e) => {
                 ErrorKind::Http {
                     status_code: e.status().unwrap_or(StatusCode::OK).as_u16(),
                     status_msg: e.to_string()
                 }
             },
-            Self::BackendNotFound(e) => {
+            Error::BackendNotFound(e) => {
                 ErrorKind::BackendNotFound(e.to_owned())
             }
-            Self::BackendBoot{reason, backend} => {
+            Error::BackendBoot{reason, backend} => {
                 ErrorKind::BackendBoot {reason: reason.to_owned(), backend: backend.to_owned()}
             }
-            Self::ModelNotFound { model, backend } => {
+            Error::ModelNotFound { model, backend } => {
                 ErrorKind::ModelNotFound { model: model.to_owned(), backend: backend.to_owned() }
             }
-            Self::Internal(msg) => {
+            Error::KnowledgeBaseNotFound(uuid) => {
+                ErrorKind::KnowledgeBaseNotFound(uuid.to_owned())
+            }
+            Error::IndexOutdated(name) => {
+                ErrorKind::IndexOutdated(name.to_owned())
+            }
+            Error::ChatNotFound(uuid) => {
+                ErrorKind::ChatNotFound(uuid.to_owned())
+            }
+            Error::InvalidChat(reason) => {
+                ErrorKind::InvalidChat(reason.to_owned())
+            }
+            Error::UnsupportedFileVersion(version) => {
+                ErrorKind::UnsupportedFileVersion(*version)
+            }
+            Error::InvalidModelfile(reason) => {
+                ErrorKind::InvalidModelfile(reason.to_owned())
+            }
+            Error::Upload { file, reason } => {
+                ErrorKind::Upload { file: file.to_owned(), reason: reason.to_owned() }
+            }
+            Error::Database(e) => {
+                ErrorKind::Database(e.to_string())
+            }
+            Error::Generation(kind) => {
+                kind.clone()
+            }
+            Error::Internal(msg) => {
                 ErrorKind::Internal(msg.to_owned())
             },
-            Self::Unknown => {
-                ErrorKind::Internal(self.to_string())
+            Error::Unknown => {
+                ErrorKind::Internal(error.to_string())
             }
-        };
-        error_kind.serialize(serializer)
+        }
+    }
+}
+
+impl serde::Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        ErrorKind::from(self).serialize(serializer)
     }
 }
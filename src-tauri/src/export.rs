@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use time::UtcDateTime;
+
+use crate::{backend::chat::{Chat, Role}, chat_format::ChatFile, chat_repository::ChatSummary, errors::Error};
+
+pub(crate) mod html;
+pub(crate) mod markdown;
+
+/// File formats chats can be exported to.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    /// Serialized [Chat]s in a versioned [ChatFile], which can be imported again
+    #[default]
+    Json,
+    Markdown,
+    /// A single page without external resources
+    Html
+}
+
+impl ExportFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "JSON",
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Html => "HTML"
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html"
+        }
+    }
+}
+
+/// What has been written by an export.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportReport {
+    pub path: PathBuf,
+    pub format: ExportFormat,
+    pub chats: Vec<ChatSummary>,
+    /// Size of the file
+    pub bytes: usize,
+    /// Nothing has been written if set
+    pub dry_run: bool
+}
+
+/// Renders `chats` into a single document.
+/// Documents only contain the active branch of each chat, JSON contains all branches.
+pub fn render(chats: &[Chat], format: ExportFormat) -> Result<String, Error> {
+    match format {
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(&ChatFile::new(chats))?),
+        ExportFormat::Markdown => Ok(markdown::render(chats)),
+        ExportFormat::Html => Ok(html::render(chats))
+    }
+}
+
+/// Renders `chats` and writes them to `path`, replacing an existing file.
+/// With `dry_run`, only the report is created.
+pub fn export_to_file(chats: &[Chat], path: &Path, format: ExportFormat, dry_run: bool) -> Result<ExportReport, Error> {
+    let content = render(chats, format)?;
+    if !dry_run {
+        std::fs::write(path, &content)?;
+    }
+    Ok(ExportReport {
+        path: path.to_owned(),
+        format,
+        chats: chats.iter().map(ChatSummary::from).collect(),
+        bytes: content.len(),
+        dry_run
+    })
+}
+
+fn role_label(role: &Role) -> &'static str {
+    match role {
+        Role::System => "System",
+        Role::User => "User",
+        Role::Assistant => "Assistant",
+        Role::Tool => "Tool"
+    }
+}
+
+/// Readable timestamp like `2025-07-14 09:30 UTC`.
+fn format_timestamp(timestamp: &UtcDateTime) -> String {
+    format!("{} {:02}:{:02} UTC", timestamp.date(), timestamp.hour(), timestamp.minute())
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::backend::chat::{parse_timestamp, Chat, ChatMessage, Role, ToolCall, ToolCallFunction};
+
+    use super::{export_to_file, render, ExportFormat};
+
+    /// Chat with a replaced answer in another branch, thoughts,
+    /// a tool call, its result and an image.
+    pub(super) fn sample_chat() -> Chat {
+        let mut chat = Chat {
+            uuid: Uuid::new_v4(),
+            title: "Rust <search>".into(),
+            history: Vec::new(),
+            current: None,
+            created_at: parse_timestamp("2025-07-14T09:30:00Z").unwrap(),
+            knowledge_bases: Vec::new()
+        };
+        let prompt = chat.add_message(None, ChatMessage::new(Role::User, "Hello <b>world</b>".into()));
+        chat.add_message(prompt.id(), ChatMessage::new(Role::Assistant, "Old answer".into()));
+        let call = ToolCall {
+            id: Some("call_1".into()),
+            function: ToolCallFunction { name: "search".into(), arguments: serde_json::json!({"query": "rust"}) }
+        };
+        let answer = chat.add_message(prompt.id(), ChatMessage::with_tool_calls(
+            "Let me search.".into(), Some("Thinking *hard*".into()), vec![call.clone()]
+        ));
+        let result = chat.add_message(answer.id(), ChatMessage::tool_result(&call, "Found ``` <i>fences</i>".into()));
+        chat.add_message(
+            result.id(),
+            ChatMessage::new(Role::User, "Look at this".into()).with_images(vec!["iVBORw0KGgo=".into()])
+        );
+        chat
+    }
+
+    #[test]
+    fn documents_contain_only_the_active_branch() {
+        let chats = [sample_chat()];
+        for format in [ExportFormat::Markdown, ExportFormat::Html] {
+            let document = render(&chats, format).unwrap();
+            assert!(!document.contains("Old answer"), "{} contains another branch", format.name());
+            assert!(document.contains("Let me search."));
+            assert!(document.contains("Look at this"));
+        }
+        assert!(render(&chats, ExportFormat::Json).unwrap().contains("Old answer"));
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        let chats = [sample_chat()];
+        let path = std::env::temp_dir().join(format!("export-test-{}.md", Uuid::new_v4()));
+        let report = export_to_file(&chats, &path, ExportFormat::Markdown, true).unwrap();
+        assert!(!path.exists());
+        assert_eq!(report.bytes, render(&chats, ExportFormat::Markdown).unwrap().len());
+        assert_eq!(report.chats.len(), 1);
+
+        let report = export_to_file(&chats, &path, ExportFormat::Markdown, false).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len() as usize, report.bytes);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+use pulldown_cmark::{Event, Options, Parser};
+
+use crate::backend::chat::{image_data_url, Chat, ChatMessage, Role};
+
+use super::{format_timestamp, role_label};
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; line-height: 1.5; max-width: 50rem; margin: 2rem auto; padding: 0 1rem; color: #1f2328; }
+article + article { border-top: 1px solid #d0d7de; margin-top: 3rem; }
+.created { color: #59636e; }
+.message { border-radius: 0.5rem; padding: 0.25rem 1rem; margin: 1rem 0; background: #f6f8fa; }
+.message.user { background: #ddf4ff; }
+.message.system, .message.tool { background: #fff8c5; }
+.role { font-size: 0.9rem; text-transform: uppercase; color: #59636e; }
+details { color: #59636e; margin: 0.5rem 0; }
+pre { overflow-x: auto; background: #eff1f3; padding: 0.5rem; border-radius: 0.25rem; }
+img { max-width: 100%; }
+";
+
+/// Renders `chats` as a complete HTML document.
+/// Styles and images are embedded, so the file can be opened anywhere.
+pub fn render(chats: &[Chat]) -> String {
+    let title = match chats {
+        [chat] => escape(&chat.title),
+        _ => "Chats".to_owned()
+    };
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+        <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n"
+    );
+    for chat in chats {
+        render_chat(chat, &mut out);
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn render_chat(chat: &Chat, out: &mut String) {
+    out.push_str(&format!(
+        "<article>\n<h1>{}</h1>\n<p class=\"created\">{}</p>\n",
+        escape(chat.title.trim()),
+        format_timestamp(&chat.created_at)
+    ));
+    for message in chat.active_path() {
+        render_message(message, out);
+    }
+    out.push_str("</article>\n");
+}
+
+fn render_message(message: &ChatMessage, out: &mut String) {
+    let role = role_label(message.role());
+    out.push_str(&format!("<section class=\"message {}\">\n", role.to_lowercase()));
+    match message.tool_name() {
+        Some(name) if *message.role() == Role::Tool => {
+            out.push_str(&format!("<h2 class=\"role\">{role} <code>{}</code></h2>\n", escape(name)));
+        },
+        _ => out.push_str(&format!("<h2 class=\"role\">{role}</h2>\n"))
+    }
+
+    if let Some(thoughts) = message.thoughts().filter(|t| !t.trim().is_empty()) {
+        out.push_str(&format!(
+            "<details>\n<summary>Thoughts</summary>\n{}</details>\n",
+            markdown_to_html(thoughts)
+        ));
+    }
+
+    let content = message.content();
+    if !content.trim().is_empty() {
+        if *message.role() == Role::Tool {
+            out.push_str(&format!("<pre>{}</pre>\n", escape(content)));
+        } else {
+            out.push_str(&markdown_to_html(content));
+        }
+    }
+
+    for (i, image) in message.images().iter().enumerate() {
+        out.push_str(&format!("<img src=\"{}\" alt=\"Image {}\">\n", image_data_url(image), i + 1));
+    }
+
+    for call in message.tool_calls() {
+        let arguments = serde_json::to_string_pretty(&call.function.arguments).unwrap_or_default();
+        out.push_str(&format!(
+            "<p>Calls <code>{}</code> with</p>\n<pre>{}</pre>\n",
+            escape(&call.function.name),
+            escape(&arguments)
+        ));
+    }
+    out.push_str("</section>\n");
+}
+
+/// Converts the Markdown of a message to HTML.
+/// Raw HTML in the message is shown as text instead of being rendered.
+fn markdown_to_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let parser = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        event => event
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, parser);
+    html
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::tests::sample_chat;
+
+    use super::{escape, markdown_to_html, render};
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(escape("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+    }
+
+    #[test]
+    fn raw_html_in_messages_becomes_text() {
+        let html = markdown_to_html("Hello <b>world</b>\n\n<div onclick=\"alert(1)\">Hi</div>\n\n**bold**");
+        assert!(!html.contains("<b>") && !html.contains("<div"));
+        assert!(html.contains("&lt;b&gt;world&lt;/b&gt;"));
+        assert!(html.contains("&lt;div onclick=\"alert(1)\"&gt;Hi&lt;/div&gt;"));
+        assert!(html.contains("<strong>bold</strong>"));
+    }
+
+    #[test]
+    fn renders_thoughts_images_and_tool_calls() {
+        let html = render(&[sample_chat()]);
+        assert!(html.contains("<title>Rust &lt;search&gt;</title>"));
+        assert!(html.contains("<details>\n<summary>Thoughts</summary>\n<p>Thinking <em>hard</em></p>\n</details>\n"));
+        assert!(html.contains("<p>Calls <code>search</code> with</p>\n<pre>{\n  &quot;query&quot;: &quot;rust&quot;\n}</pre>\n"));
+        assert!(html.contains("<h2 class=\"role\">Tool <code>search</code></h2>\n<pre>Found ``` &lt;i&gt;fences&lt;/i&gt;</pre>\n"));
+        assert!(html.contains("<img src=\"data:image/png;base64,iVBORw0KGgo=\" alt=\"Image 1\">"));
+    }
+}
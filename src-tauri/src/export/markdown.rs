@@ -0,0 +1,93 @@
+use crate::backend::chat::{image_data_url, Chat, ChatMessage, Role};
+
+use super::{format_timestamp, role_label};
+
+/// Renders `chats` as Markdown separated by horizontal rules.
+/// Thoughts are collapsed into `<details>` blocks, which most
+/// Markdown renderers (GitHub, GitLab, Gitea, ...) support.
+pub fn render(chats: &[Chat]) -> String {
+    chats
+        .iter()
+        .map(render_chat)
+        .collect::<Vec<_>>()
+        .join("---\n\n")
+}
+
+fn render_chat(chat: &Chat) -> String {
+    let mut out = format!("# {}\n\n*{}*\n\n", chat.title.trim(), format_timestamp(&chat.created_at));
+    for message in chat.active_path() {
+        render_message(message, &mut out);
+    }
+    out
+}
+
+fn render_message(message: &ChatMessage, out: &mut String) {
+    match (message.role(), message.tool_name()) {
+        (Role::Tool, Some(name)) => out.push_str(&format!("### Tool `{name}`\n\n")),
+        (role, _) => out.push_str(&format!("### {}\n\n", role_label(role)))
+    }
+
+    if let Some(thoughts) = message.thoughts().filter(|t| !t.trim().is_empty()) {
+        out.push_str(&format!(
+            "<details>\n<summary>Thoughts</summary>\n\n{}\n\n</details>\n\n",
+            thoughts.trim()
+        ));
+    }
+
+    let content = message.content().trim();
+    if !content.is_empty() {
+        if *message.role() == Role::Tool {
+            out.push_str(&code_block(content, ""));
+        } else {
+            out.push_str(content);
+            out.push_str("\n\n");
+        }
+    }
+
+    for (i, image) in message.images().iter().enumerate() {
+        out.push_str(&format!("![Image {}]({})\n\n", i + 1, image_data_url(image)));
+    }
+
+    for call in message.tool_calls() {
+        out.push_str(&format!("Calls `{}` with\n\n", call.function.name));
+        let arguments = serde_json::to_string_pretty(&call.function.arguments).unwrap_or_default();
+        out.push_str(&code_block(&arguments, "json"));
+    }
+}
+
+/// Fenced code block, which cannot be closed by backticks in `text`.
+fn code_block(text: &str, lang: &str) -> String {
+    let mut longest_run = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest_run = longest_run.max(run);
+    }
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!("{fence}{lang}\n{text}\n{fence}\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::tests::sample_chat;
+
+    use super::{code_block, render};
+
+    #[test]
+    fn code_block_fence_is_longer_than_backticks_in_text() {
+        assert_eq!(code_block("plain", "json"), "```json\nplain\n```\n\n");
+        assert_eq!(code_block("a `b` c", ""), "```\na `b` c\n```\n\n");
+        assert_eq!(code_block("a ``` b", ""), "````\na ``` b\n````\n\n");
+        assert_eq!(code_block("`````", ""), "``````\n`````\n``````\n\n");
+    }
+
+    #[test]
+    fn renders_thoughts_images_and_tool_calls() {
+        let markdown = render(&[sample_chat()]);
+        assert!(markdown.starts_with("# Rust <search>\n\n*2025-07-14 09:30 UTC*\n\n"));
+        assert!(markdown.contains("<details>\n<summary>Thoughts</summary>\n\nThinking *hard*\n\n</details>\n\n"));
+        assert!(markdown.contains("Calls `search` with\n\n```json\n{\n  \"query\": \"rust\"\n}\n```\n\n"));
+        assert!(markdown.contains("### Tool `search`\n\n````\nFound ``` <i>fences</i>\n````\n\n"));
+        assert!(markdown.contains("![Image 1](data:image/png;base64,iVBORw0KGgo=)"));
+    }
+}
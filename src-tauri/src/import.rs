@@ -0,0 +1,468 @@
+use std::{collections::HashMap, io::BufReader, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::UtcDateTime;
+use uuid::Uuid;
+
+use crate::{
+    backend::chat::{timestamp_from_number, Chat, ChatMessage},
+    chat_format,
+    chat_repository::{validate_chat, ChatRepository, ChatSummary, MAX_TITLE_LEN},
+    errors::Error,
+};
+
+pub(crate) mod chatgpt;
+pub(crate) mod open_webui;
+
+/// File formats chats can be imported from.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportFormat {
+    /// Chats exported as [crate::export::ExportFormat::Json] by any version
+    Json,
+    /// `conversations.json` of a ChatGPT data export
+    ChatGpt,
+    /// Chats exported by Open WebUI
+    OpenWebUi
+}
+
+impl ImportFormat {
+    /// Guesses the format of a file from its first chat.
+    fn detect(file: &Value) -> Self {
+        let first = match file {
+            Value::Array(values) => values.first(),
+            value => Some(value)
+        };
+        match first {
+            _ if chat_format::is_chat_file(file) => ImportFormat::Json,
+            Some(value) if value.get("mapping").is_some() => ImportFormat::ChatGpt,
+            Some(value) if value.get("chat").is_some_and(Value::is_object) => ImportFormat::OpenWebUi,
+            _ => ImportFormat::Json
+        }
+    }
+}
+
+/// How to import a chat whose UUID already exists.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum MergeStrategy {
+    /// Keep the existing chat
+    Skip,
+    /// Replace the existing chat
+    #[default]
+    Overwrite,
+    /// Import the chat with a new UUID
+    KeepBoth,
+    /// Add the messages missing in the existing chat, diverging ones as new branches
+    Merge
+}
+
+/// A chat which has not been imported.
+#[derive(Serialize, Clone, Debug)]
+pub struct ImportIssue {
+    /// [None] if the chat couldn't be read at all
+    pub uuid: Option<Uuid>,
+    pub title: Option<String>,
+    pub reason: String
+}
+
+impl ImportIssue {
+    fn new(chat: &Chat, reason: String) -> Self {
+        Self {
+            uuid: Some(chat.uuid),
+            title: Some(chat.title.clone()),
+            reason
+        }
+    }
+}
+
+/// What has been written by an import.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub path: PathBuf,
+    pub format: ImportFormat,
+    /// Chats which didn't exist before.
+    /// Chats imported with [MergeStrategy::KeepBoth] have their new UUID.
+    pub created: Vec<ChatSummary>,
+    /// Existing chats which have been replaced or merged
+    pub updated: Vec<ChatSummary>,
+    /// Existing chats which have been kept
+    pub skipped: Vec<ImportIssue>,
+    /// Chats which are unreadable or invalid
+    pub rejected: Vec<ImportIssue>,
+    /// Nothing has been written if set
+    pub dry_run: bool
+}
+
+/// Reads the chats of a file and converts them if necessary.
+/// The format is detected if [None].
+/// Chats which cannot be read are returned as issues,
+/// so a single broken chat doesn't prevent importing the others.
+pub fn read_chats(path: &Path, format: Option<ImportFormat>)
+-> Result<(ImportFormat, Vec<Result<Chat, ImportIssue>>), Error>
+{
+    let reader = BufReader::new(std::fs::File::open(path)?);
+    let file: Value = serde_json::from_reader(reader)?;
+    let format = format.unwrap_or_else(|| ImportFormat::detect(&file));
+    let values = match (format, file) {
+        (ImportFormat::Json, file) => chat_format::read_chat_file(file)?,
+        (_, Value::Array(values)) => values,
+        // Exports of a single chat
+        (_, value) => vec![value]
+    };
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    let chats = values
+        .into_iter()
+        .map(|value| {
+            let uuid = ["uuid", "id", "conversation_id"]
+                .iter()
+                .find_map(|key| value.get(key).and_then(Value::as_str))
+                .and_then(|uuid| Uuid::parse_str(uuid).ok());
+            let title = value.get("title").and_then(Value::as_str).map(|title| title.to_owned());
+            let chat = match format {
+                ImportFormat::Json => serde_json::from_value(value).map_err(|e| e.to_string()),
+                ImportFormat::ChatGpt => chatgpt::convert(value, dir),
+                ImportFormat::OpenWebUi => open_webui::convert(value)
+            };
+            chat
+                .map(|mut chat: Chat| {
+                    chat.normalize();
+                    chat
+                })
+                .map_err(|reason| ImportIssue { uuid, title, reason })
+        })
+        .collect();
+    Ok((format, chats))
+}
+
+/// UUID of an imported chat.
+/// Other tools use UUIDs as well, so importing a chat twice can be detected.
+fn chat_uuid(id: Option<&str>) -> Uuid {
+    id.and_then(|id| Uuid::parse_str(id).ok()).unwrap_or_else(Uuid::new_v4)
+}
+
+fn title_or_default(title: Option<&str>) -> String {
+    match title.map(str::trim).filter(|title| !title.is_empty()) {
+        Some(title) => title.chars().take(MAX_TITLE_LEN).collect(),
+        None => "Imported Chat".to_owned()
+    }
+}
+
+/// Timestamp from fractional seconds since the epoch, now if [None].
+fn timestamp_from_secs(secs: Option<f64>) -> UtcDateTime {
+    secs.and_then(timestamp_from_number).unwrap_or_else(UtcDateTime::now)
+}
+
+/// Imports the chats of the file at `path` into `repo`.
+/// If `uuids` is given, only these chats are imported and
+/// all of them must be contained in the file.
+/// Nothing is written if `dry_run` is set.
+pub fn import_file(
+    repo: &ChatRepository, path: &Path, format: Option<ImportFormat>, uuids: Option<&[Uuid]>,
+    strategy: MergeStrategy, dry_run: bool
+) -> Result<ImportReport, Error>
+{
+    let (format, mut chats) = read_chats(path, format)?;
+    if let Some(uuids) = uuids {
+        let selected = |chat: &Result<Chat, ImportIssue>| match chat {
+            Ok(chat) => Some(chat.uuid),
+            Err(issue) => issue.uuid
+        };
+        if let Some(missing) = uuids.iter().find(|uuid| !chats.iter().any(|chat| selected(chat) == Some(**uuid))) {
+            return Err(Error::ChatNotFound(missing.to_string()));
+        }
+        chats.retain(|chat| selected(chat).is_some_and(|uuid| uuids.contains(&uuid)));
+    }
+
+    let mut report = ImportReport {
+        path: path.to_owned(),
+        format,
+        created: Vec::new(),
+        updated: Vec::new(),
+        skipped: Vec::new(),
+        rejected: Vec::new(),
+        dry_run
+    };
+    // Chats to write. Also checked for existing chats, so a file
+    // may contain the same chat more than once.
+    let mut pending: Vec<Chat> = Vec::new();
+    let mut pending_index: HashMap<Uuid, usize> = HashMap::new();
+
+    for chat in chats {
+        let chat = match chat {
+            Ok(chat) => chat,
+            Err(issue) => {
+                report.rejected.push(issue);
+                continue;
+            }
+        };
+        if let Err(e) = validate_chat(&chat) {
+            report.rejected.push(ImportIssue::new(&chat, e.to_string()));
+            continue;
+        }
+
+        let existing = match pending_index.get(&chat.uuid) {
+            Some(i) => Some(pending[*i].clone()),
+            None if repo.exists(&chat.uuid)? => Some(repo.get(&chat.uuid)?),
+            None => None
+        };
+        let (chat, created) = match (existing, strategy) {
+            (None, _) => (chat, true),
+            (Some(_), MergeStrategy::Skip) => {
+                report.skipped.push(ImportIssue::new(&chat, "Chat already exists".into()));
+                continue;
+            },
+            (Some(_), MergeStrategy::Overwrite) => (chat, false),
+            (Some(_), MergeStrategy::KeepBoth) => (Chat { uuid: Uuid::new_v4(), ..chat }, true),
+            (Some(existing), MergeStrategy::Merge) => match merge_histories(existing, &chat) {
+                Some(merged) => (merged, false),
+                None => {
+                    report.skipped.push(ImportIssue::new(&chat, "No new messages".into()));
+                    continue;
+                }
+            }
+        };
+
+        if created {
+            report.created.push((&chat).into());
+        } else {
+            report.updated.push((&chat).into());
+        }
+        match pending_index.get(&chat.uuid) {
+            Some(i) => pending[*i] = chat,
+            None => {
+                pending_index.insert(chat.uuid, pending.len());
+                pending.push(chat);
+            }
+        }
+    }
+
+    if !dry_run {
+        repo.save_all(&pending)?;
+    }
+    Ok(report)
+}
+
+/// Adds the messages of `imported` missing in `existing` at the same place of the tree.
+/// Messages are the same if they have the same ID or the same content below the same parent,
+/// so chats exported by older versions are merged as well. Diverging messages become new branches.
+/// The active branch of `existing` is kept.
+/// Returns [None] if `existing` already contains all messages.
+fn merge_histories(mut existing: Chat, imported: &Chat) -> Option<Chat> {
+    // IDs of the imported messages in `existing`
+    let mut ids: HashMap<Uuid, Uuid> = HashMap::new();
+    let mut changed = false;
+    for message in &imported.history {
+        let Some(id) = message.id() else {
+            continue;
+        };
+        let parent = message.parent().and_then(|parent| ids.get(&parent).copied());
+        let same = existing.history
+            .iter()
+            .find(|m| m.id() == Some(id) || (m.parent() == parent && same_message(m, message)))
+            .and_then(ChatMessage::id);
+        match same {
+            Some(same) => {
+                ids.insert(id, same);
+            },
+            None => {
+                existing.history.push(message.clone().with_parent(parent));
+                ids.insert(id, id);
+                changed = true;
+            }
+        }
+    }
+    if !changed {
+        return None;
+    }
+    // Chats without messages have no active branch yet
+    existing.current = existing.current.or(imported.current.and_then(|current| ids.get(&current).copied()));
+    for uuid in &imported.knowledge_bases {
+        if !existing.knowledge_bases.contains(uuid) {
+            existing.knowledge_bases.push(*uuid);
+        }
+    }
+    Some(existing)
+}
+
+fn same_message(a: &ChatMessage, b: &ChatMessage) -> bool {
+    a.role() == b.role()
+        && a.content() == b.content()
+        && a.images() == b.images()
+        && a.tool_name() == b.tool_name()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use crate::{
+        backend::chat::{Chat, ChatMessage, Role},
+        chat_format::ChatFile,
+        chat_repository::ChatRepository,
+        errors::Error,
+    };
+
+    use super::{import_file, MergeStrategy};
+
+    /// Chat alternating between user and assistant messages.
+    fn chat(uuid: Uuid, title: &str, contents: &[&str]) -> Chat {
+        let mut chat = Chat {
+            uuid,
+            title: title.into(),
+            history: Vec::new(),
+            current: None,
+            created_at: time::UtcDateTime::now(),
+            knowledge_bases: Vec::new()
+        };
+        for (i, content) in contents.iter().enumerate() {
+            let role = if i % 2 == 0 { Role::User } else { Role::Assistant };
+            chat.add_message(chat.current, ChatMessage::new(role, (*content).into()));
+        }
+        chat
+    }
+
+    fn export_file(chats: &[Chat]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("import-test-{}.json", Uuid::new_v4()));
+        std::fs::write(&path, serde_json::to_vec(&ChatFile::new(chats)).unwrap()).unwrap();
+        path
+    }
+
+    fn import(repo: &ChatRepository, chats: &[Chat], strategy: MergeStrategy) -> super::ImportReport {
+        let path = export_file(chats);
+        let report = import_file(repo, &path, None, None, strategy, false).unwrap();
+        std::fs::remove_file(path).unwrap();
+        report
+    }
+
+    fn contents(chat: &Chat) -> Vec<&str> {
+        chat.history.iter().map(ChatMessage::content).collect()
+    }
+
+    #[test]
+    fn conflicting_chats_are_resolved_by_strategy() {
+        let uuid = Uuid::new_v4();
+        let imported = chat(uuid, "Imported", &["Hello", "Hi"]);
+
+        let repo = ChatRepository::open_in_memory().unwrap();
+        repo.create(&chat(uuid, "Existing", &["Hello"])).unwrap();
+        let report = import(&repo, std::slice::from_ref(&imported), MergeStrategy::Skip);
+        assert_eq!((report.created.len(), report.updated.len(), report.skipped.len()), (0, 0, 1));
+        assert_eq!(repo.get(&uuid).unwrap().title, "Existing");
+
+        let report = import(&repo, std::slice::from_ref(&imported), MergeStrategy::Overwrite);
+        assert_eq!((report.created.len(), report.updated.len(), report.skipped.len()), (0, 1, 0));
+        assert_eq!(repo.get(&uuid).unwrap().title, "Imported");
+
+        let repo = ChatRepository::open_in_memory().unwrap();
+        repo.create(&chat(uuid, "Existing", &["Hello"])).unwrap();
+        let report = import(&repo, &[imported], MergeStrategy::KeepBoth);
+        assert_eq!((report.created.len(), report.updated.len()), (1, 0));
+        let copy = report.created[0].uuid;
+        assert_ne!(copy, uuid);
+        assert_eq!(repo.get(&uuid).unwrap().title, "Existing");
+        assert_eq!(repo.get(&copy).unwrap().title, "Imported");
+    }
+
+    #[test]
+    fn merging_adds_missing_messages_once() {
+        let uuid = Uuid::new_v4();
+        let repo = ChatRepository::open_in_memory().unwrap();
+        let existing = chat(uuid, "Existing", &["Hello", "Hi"]);
+        repo.create(&existing).unwrap();
+
+        // Same messages with other IDs, like exports of older versions
+        let imported = chat(uuid, "Imported", &["Hello", "Hi", "How are you?"]);
+        let report = import(&repo, std::slice::from_ref(&imported), MergeStrategy::Merge);
+        assert_eq!(report.updated.len(), 1);
+        let merged = repo.get(&uuid).unwrap();
+        assert_eq!(contents(&merged), ["Hello", "Hi", "How are you?"]);
+        assert_eq!(merged.history[2].parent(), existing.history[1].id());
+        assert_eq!(merged.title, "Existing");
+
+        let report = import(&repo, &[imported], MergeStrategy::Merge);
+        assert_eq!((report.updated.len(), report.skipped.len()), (0, 1));
+        assert_eq!(repo.get(&uuid).unwrap().history.len(), 3);
+    }
+
+    #[test]
+    fn diverging_messages_become_branches() {
+        let uuid = Uuid::new_v4();
+        let repo = ChatRepository::open_in_memory().unwrap();
+        let existing = chat(uuid, "Existing", &["Hello", "Hi"]);
+        repo.create(&existing).unwrap();
+
+        import(&repo, &[chat(uuid, "Imported", &["Hello", "Good day"])], MergeStrategy::Merge);
+        let merged = repo.get(&uuid).unwrap();
+        assert_eq!(contents(&merged), ["Hello", "Hi", "Good day"]);
+        assert_eq!(merged.history[1].parent(), merged.history[2].parent());
+        // The active branch is kept
+        assert_eq!(merged.current, existing.current);
+    }
+
+    #[test]
+    fn merging_needs_parents_before_children() {
+        let uuid = Uuid::new_v4();
+        let repo = ChatRepository::open_in_memory().unwrap();
+        repo.create(&chat(uuid, "Existing", &["Hello"])).unwrap();
+
+        let mut imported = chat(uuid, "Imported", &["Hello", "Hi"]);
+        imported.history.reverse();
+        let report = import(&repo, &[imported], MergeStrategy::Merge);
+        assert_eq!((report.updated.len(), report.rejected.len()), (0, 1));
+        assert_eq!(repo.get(&uuid).unwrap().history.len(), 1);
+    }
+
+    #[test]
+    fn repeated_chats_in_a_file_are_resolved_against_each_other() {
+        let uuid = Uuid::new_v4();
+        let first = chat(uuid, "First", &["Hello", "Hi"]);
+        let mut second = first.clone();
+        second.title = "Second".into();
+        second.add_message(second.current, ChatMessage::new(Role::User, "How are you?".into()));
+
+        let repo = ChatRepository::open_in_memory().unwrap();
+        let report = import(&repo, &[first.clone(), second.clone()], MergeStrategy::Merge);
+        assert_eq!((report.created.len(), report.updated.len()), (1, 1));
+        assert_eq!(contents(&repo.get(&uuid).unwrap()), ["Hello", "Hi", "How are you?"]);
+
+        let repo = ChatRepository::open_in_memory().unwrap();
+        let report = import(&repo, &[second, first], MergeStrategy::Overwrite);
+        assert_eq!((report.created.len(), report.updated.len()), (1, 1));
+        let chat = repo.get(&uuid).unwrap();
+        assert_eq!((chat.title.as_str(), chat.history.len()), ("First", 2));
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        let repo = ChatRepository::open_in_memory().unwrap();
+        let path = export_file(&[chat(Uuid::new_v4(), "Chat", &["Hello"])]);
+        let report = import_file(&repo, &path, None, None, MergeStrategy::Overwrite, true).unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.created.len(), 1);
+        assert!(repo.list().unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn imports_only_selected_chats() {
+        let repo = ChatRepository::open_in_memory().unwrap();
+        let (selected, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let path = export_file(&[chat(selected, "Selected", &["Hello"]), chat(other, "Other", &["Hello"])]);
+
+        let report = import_file(&repo, &path, None, Some(&[selected]), MergeStrategy::Overwrite, false).unwrap();
+        assert_eq!(report.created.len(), 1);
+        assert!(repo.exists(&selected).unwrap() && !repo.exists(&other).unwrap());
+
+        let missing = Uuid::new_v4();
+        let result = import_file(&repo, &path, None, Some(&[other, missing]), MergeStrategy::Overwrite, false);
+        assert!(matches!(result, Err(Error::ChatNotFound(uuid)) if uuid == missing.to_string()));
+        assert!(!repo.exists(&other).unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+}
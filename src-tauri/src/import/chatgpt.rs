@@ -0,0 +1,313 @@
+use std::{collections::HashMap, path::Path};
+
+use base64::Engine;
+use log::warn;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::backend::chat::{Chat, ChatMessage, Role};
+
+use super::{chat_uuid, timestamp_from_secs, title_or_default};
+
+/// A conversation of `conversations.json` in a ChatGPT data export.
+#[derive(Deserialize)]
+struct Conversation {
+    title: Option<String>,
+    create_time: Option<f64>,
+    /// All messages of the conversation as a tree.
+    /// Editing a message or regenerating a response creates a new branch.
+    mapping: HashMap<String, Node>,
+    /// Last message of the branch shown to the user
+    current_node: Option<String>,
+    conversation_id: Option<String>,
+    id: Option<String>
+}
+
+#[derive(Deserialize)]
+struct Node {
+    message: Option<Message>,
+    parent: Option<String>,
+    #[serde(default)]
+    children: Vec<String>
+}
+
+#[derive(Deserialize)]
+struct Message {
+    author: Author,
+    content: Option<Value>,
+    #[serde(default)]
+    metadata: Value
+}
+
+#[derive(Deserialize)]
+struct Author {
+    role: String,
+    name: Option<String>
+}
+
+/// Converts a ChatGPT conversation into a chat.
+/// Only the active branch is imported. Images are read from `dir`,
+/// the extracted export, and skipped if they're missing.
+pub fn convert(value: Value, dir: &Path) -> Result<Chat, String> {
+    let conversation: Conversation = serde_json::from_value(value).map_err(|e| e.to_string())?;
+
+    let mut history = Vec::new();
+    // Thoughts of reasoning models are separate messages
+    let mut thoughts: Option<String> = None;
+    for message in active_branch(&conversation) {
+        if message.metadata.get("is_visually_hidden_from_conversation").and_then(Value::as_bool) == Some(true) {
+            continue;
+        }
+        let Some(content) = &message.content else {
+            continue;
+        };
+        let content_type = content.get("content_type").and_then(Value::as_str).unwrap_or("text");
+        let (text, images) = match content_type {
+            "text" | "multimodal_text" => parts(content, dir),
+            "code" => (code_block(content), Vec::new()),
+            "execution_output" => (str_field(content, "text"), Vec::new()),
+            "thoughts" => {
+                let text = content
+                    .get("thoughts")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|thought| thought.get("content").and_then(Value::as_str))
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                thoughts = Some(text).filter(|text| !text.is_empty());
+                continue;
+            },
+            // Browsing results, quotes, custom instructions, ...
+            _ => continue
+        };
+        if text.trim().is_empty() && images.is_empty() {
+            continue;
+        }
+
+        let message = match message.author.role.as_str() {
+            "user" => ChatMessage::new(Role::User, text),
+            "assistant" => ChatMessage::with_thoughts(Role::Assistant, text, thoughts.take()),
+            "system" => ChatMessage::new(Role::System, text),
+            // The calls of ChatGPT's tools aren't exported, so their results, like the output
+            // of code or generated images, can only be shown as part of the answer.
+            "tool" => ChatMessage::new(Role::Assistant, text),
+            _ => continue
+        };
+        history.push(message.with_images(images));
+    }
+
+    Ok(Chat {
+        uuid: chat_uuid(conversation.conversation_id.as_deref().or(conversation.id.as_deref())),
+        title: title_or_default(conversation.title.as_deref()),
+        history,
+        current: None,
+        created_at: timestamp_from_secs(conversation.create_time),
+        knowledge_bases: Vec::new()
+    })
+}
+
+/// Messages from the root to the current node.
+/// Without a current node, the latest branches are followed.
+fn active_branch(conversation: &Conversation) -> Vec<&Message> {
+    let mapping = &conversation.mapping;
+    let follow_parents = conversation.current_node.is_some();
+    let mut id = match &conversation.current_node {
+        Some(current) => Some(current),
+        None => mapping.iter().find(|(_, node)| node.parent.is_none()).map(|(id, _)| id)
+    };
+
+    let mut nodes = Vec::new();
+    while let Some(node) = id.and_then(|id| mapping.get(id)) {
+        nodes.push(node);
+        // Malformed trees could contain cycles
+        if nodes.len() > mapping.len() {
+            break;
+        }
+        id = if follow_parents { node.parent.as_ref() } else { node.children.last() };
+    }
+    if follow_parents {
+        nodes.reverse();
+    }
+    nodes.into_iter().filter_map(|node| node.message.as_ref()).collect()
+}
+
+/// Text and images of a text message.
+fn parts(content: &Value, dir: &Path) -> (String, Vec<String>) {
+    let mut texts = Vec::new();
+    let mut images = Vec::new();
+    for part in content.get("parts").and_then(Value::as_array).into_iter().flatten() {
+        match part {
+            Value::String(text) => texts.push(text.as_str()),
+            part if part.get("content_type").and_then(Value::as_str) == Some("image_asset_pointer") => {
+                let pointer = part.get("asset_pointer").and_then(Value::as_str).unwrap_or_default();
+                match read_asset(pointer, dir) {
+                    Some(image) => images.push(image),
+                    None => warn!("Image {pointer} not found in the export")
+                }
+            },
+            _ => {}
+        }
+    }
+    (texts.join("\n"), images)
+}
+
+/// Reads an image of the export encoded as base64.
+/// Pointers like `file-service://file-abc` refer to files named `file-abc-<name>`.
+fn read_asset(pointer: &str, dir: &Path) -> Option<String> {
+    let file_id = pointer.rsplit("://").next().filter(|id| !id.is_empty())?;
+    let path = std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .find(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            // Other IDs can start with this ID, e.g. `file-abc` and `file-abcd`
+            name.strip_prefix(file_id).is_some_and(|rest| rest.is_empty() || rest.starts_with(['-', '.']))
+        })?
+        .path();
+    let data = std::fs::read(path).ok()?;
+    Some(base64::engine::general_purpose::STANDARD.encode(data))
+}
+
+/// Code written by the model for its tools.
+fn code_block(content: &Value) -> String {
+    let language = content.get("language").and_then(Value::as_str).filter(|l| *l != "unknown");
+    format!("```{}\n{}\n```", language.unwrap_or_default(), str_field(content, "text"))
+}
+
+fn str_field(value: &Value, key: &str) -> String {
+    value.get(key).and_then(Value::as_str).unwrap_or_default().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::{json, Value};
+
+    use crate::backend::chat::{ChatMessage, Role};
+
+    use super::convert;
+
+    fn node(role: &str, text: &str, parent: Option<&str>, children: &[&str]) -> Value {
+        json!({
+            "message": {
+                "author": { "role": role },
+                "content": { "content_type": "text", "parts": [text] }
+            },
+            "parent": parent,
+            "children": children
+        })
+    }
+
+    /// An edited question with an answer to each version.
+    fn conversation(current_node: Option<&str>) -> Value {
+        json!({
+            "title": "Edited",
+            "conversation_id": "67e0c2a4-6b1c-8004-a5b5-7d1b5e6f3e0d",
+            "current_node": current_node,
+            "mapping": {
+                "root": { "message": null, "parent": null, "children": ["q1", "q2"] },
+                "q1": node("user", "Question", Some("root"), &["a1"]),
+                "a1": node("assistant", "Answer", Some("q1"), &[]),
+                "q2": node("user", "Edited question", Some("root"), &["a2"]),
+                "a2": node("assistant", "Another answer", Some("q2"), &[])
+            }
+        })
+    }
+
+    fn contents(history: &[ChatMessage]) -> Vec<&str> {
+        history.iter().map(ChatMessage::content).collect()
+    }
+
+    #[test]
+    fn imports_the_branch_of_the_current_node() {
+        let chat = convert(conversation(Some("a1")), Path::new("")).unwrap();
+        assert_eq!(contents(&chat.history), ["Question", "Answer"]);
+        assert_eq!(chat.uuid.to_string(), "67e0c2a4-6b1c-8004-a5b5-7d1b5e6f3e0d");
+    }
+
+    #[test]
+    fn imports_the_latest_branch_without_current_node() {
+        let chat = convert(conversation(None), Path::new("")).unwrap();
+        assert_eq!(contents(&chat.history), ["Edited question", "Another answer"]);
+    }
+
+    #[test]
+    fn stops_at_cycles() {
+        let mapping = json!({
+            "q": node("user", "Question", Some("a"), &["a"]),
+            "a": node("assistant", "Answer", Some("q"), &["q"])
+        });
+        let chat = convert(json!({ "mapping": mapping, "current_node": "a" }), Path::new("")).unwrap();
+        assert!(chat.history.len() <= 3);
+        assert_eq!(chat.history.last().unwrap().content(), "Answer");
+    }
+
+    #[test]
+    fn adds_thoughts_to_the_next_answer() {
+        let mut conversation = conversation(Some("a1"));
+        let mapping = &mut conversation["mapping"];
+        mapping["t1"] = json!({
+            "message": {
+                "author": { "role": "assistant" },
+                "content": {
+                    "content_type": "thoughts",
+                    "thoughts": [{ "summary": "Reading", "content": "First" }, { "content": "Second" }]
+                }
+            },
+            "parent": "q1",
+            "children": ["a1"]
+        });
+        mapping["a1"]["parent"] = json!("t1");
+
+        let chat = convert(conversation, Path::new("")).unwrap();
+        assert_eq!(contents(&chat.history), ["Question", "Answer"]);
+        assert_eq!(chat.history[0].thoughts(), None);
+        assert_eq!(chat.history[1].thoughts(), Some("First\n\nSecond"));
+    }
+
+    #[test]
+    fn imports_tool_output_as_answer() {
+        let mut conversation = conversation(Some("o1"));
+        let mapping = &mut conversation["mapping"];
+        mapping["o1"] = json!({
+            "message": {
+                "author": { "role": "tool", "name": "python" },
+                "content": { "content_type": "execution_output", "text": "42" }
+            },
+            "parent": "a1",
+            "children": []
+        });
+
+        let chat = convert(conversation, Path::new("")).unwrap();
+        let output = chat.history.last().unwrap();
+        assert_eq!((output.role(), output.content()), (&Role::Assistant, "42"));
+        assert!(chat.history.iter().all(|message| message.tool_call_id().is_none()));
+    }
+
+    #[test]
+    fn reads_images_by_exact_file_id() {
+        let dir = std::env::temp_dir().join(format!("chatgpt-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("file-abcd-other.png"), "other").unwrap();
+        std::fs::write(dir.join("file-abc-image.png"), "image").unwrap();
+
+        let mut conversation = conversation(Some("q1"));
+        conversation["mapping"]["q1"]["message"]["content"] = json!({
+            "content_type": "multimodal_text",
+            "parts": [
+                { "content_type": "image_asset_pointer", "asset_pointer": "file-service://file-abc" },
+                { "content_type": "image_asset_pointer", "asset_pointer": "file-service://file-missing" },
+                "What is this?"
+            ]
+        });
+        let chat = convert(conversation, &dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(chat.history[0].content(), "What is this?");
+        // "image" encoded as base64
+        assert_eq!(chat.history[0].images(), ["aW1hZ2U="]);
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::backend::chat::{Chat, ChatMessage, Role};
+
+use super::{chat_uuid, timestamp_from_secs, title_or_default};
+
+/// A chat of an Open WebUI export (`chat-export-*.json`).
+#[derive(Deserialize)]
+struct ExportedChat {
+    id: Option<String>,
+    title: Option<String>,
+    chat: ChatData,
+    /// Seconds since the epoch
+    created_at: Option<f64>
+}
+
+#[derive(Deserialize)]
+struct ChatData {
+    title: Option<String>,
+    /// Tree of all messages including edits and regenerated responses
+    history: Option<History>,
+    /// Messages of the current branch, which older versions only store
+    #[serde(default)]
+    messages: Vec<Message>,
+    /// Milliseconds since the epoch
+    timestamp: Option<f64>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct History {
+    messages: HashMap<String, Message>,
+    current_id: Option<String>
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Message {
+    parent_id: Option<String>,
+    role: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    files: Vec<Value>
+}
+
+/// Converts an Open WebUI chat into a chat.
+/// Only the current branch is imported.
+pub fn convert(value: Value) -> Result<Chat, String> {
+    let exported: ExportedChat = serde_json::from_value(value).map_err(|e| e.to_string())?;
+
+    let mut history = Vec::new();
+    for message in current_branch(&exported.chat) {
+        let role = match message.role.as_str() {
+            "user" => Role::User,
+            "assistant" => Role::Assistant,
+            "system" => Role::System,
+            _ => continue
+        };
+        let (content, thoughts) = split_reasoning(&message.content);
+        let images: Vec<String> = message.files
+            .iter()
+            .filter(|file| file.get("type").and_then(Value::as_str) == Some("image"))
+            .filter_map(|file| file.get("url").and_then(Value::as_str))
+            .filter_map(|url| url.split_once(";base64,").map(|(_, data)| data.to_owned()))
+            .collect();
+        if content.trim().is_empty() && images.is_empty() {
+            continue;
+        }
+        history.push(ChatMessage::with_thoughts(role, content, thoughts).with_images(images));
+    }
+
+    let created_at = exported.created_at.or(exported.chat.timestamp.map(|ms| ms / 1000.0));
+    Ok(Chat {
+        uuid: chat_uuid(exported.id.as_deref()),
+        title: title_or_default(exported.title.as_deref().or(exported.chat.title.as_deref())),
+        history,
+        current: None,
+        created_at: timestamp_from_secs(created_at),
+        knowledge_bases: Vec::new()
+    })
+}
+
+/// Messages from the root to the current message.
+fn current_branch(chat: &ChatData) -> Vec<Message> {
+    let Some(history) = chat.history.as_ref().filter(|h| !h.messages.is_empty()) else {
+        return chat.messages.clone();
+    };
+    let mut branch = Vec::new();
+    let mut id = history.current_id.as_ref();
+    while let Some(message) = id.and_then(|id| history.messages.get(id)) {
+        branch.push(message.clone());
+        // Malformed trees could contain cycles
+        if branch.len() > history.messages.len() {
+            break;
+        }
+        id = message.parent_id.as_ref();
+    }
+    branch.reverse();
+    branch
+}
+
+/// Separates the reasoning of a model from its answer.
+/// Open WebUI embeds it into the content as a `<details type="reasoning">` block
+/// with every line quoted, older versions as `<think>` tags.
+fn split_reasoning(content: &str) -> (String, Option<String>) {
+    if let Some((before, rest)) = content.split_once("<details type=\"reasoning\"") {
+        if let Some((block, after)) = rest.split_once("</details>") {
+            // Skip the remaining attributes of the tag and the summary
+            let block = block.split_once('>').map_or("", |(_, block)| block);
+            let block = block.split_once("</summary>").map_or(block, |(_, block)| block);
+            let thoughts = block
+                .lines()
+                .map(|line| line.strip_prefix('>').map_or(line, |line| line.strip_prefix(' ').unwrap_or(line)))
+                .collect::<Vec<_>>()
+                .join("\n");
+            return (format!("{before}{after}").trim().to_owned(), Some(thoughts.trim().to_owned()));
+        }
+    }
+    if let Some((before, rest)) = content.split_once("<think>") {
+        if let Some((thoughts, after)) = rest.split_once("</think>") {
+            return (format!("{before}{after}").trim().to_owned(), Some(thoughts.trim().to_owned()));
+        }
+    }
+    (content.to_owned(), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::backend::chat::ChatMessage;
+
+    use super::{convert, split_reasoning};
+
+    fn message(role: &str, content: &str, parent: Option<&str>) -> Value {
+        json!({ "parentId": parent, "role": role, "content": content })
+    }
+
+    fn contents(history: &[ChatMessage]) -> Vec<&str> {
+        history.iter().map(ChatMessage::content).collect()
+    }
+
+    #[test]
+    fn imports_the_current_branch() {
+        let exported = json!({
+            "id": "0b4e4c52-4d28-4d7d-9d2a-7cfa3f0d6a1e",
+            "title": "Regenerated",
+            "created_at": 1_700_000_000,
+            "chat": {
+                "history": {
+                    "currentId": "a2",
+                    "messages": {
+                        "q": message("user", "Question", None),
+                        "a1": message("assistant", "Answer", Some("q")),
+                        "a2": message("assistant", "<think>Hmm</think>Better answer", Some("q"))
+                    }
+                },
+                "messages": [message("user", "Ignored", None)]
+            }
+        });
+        let chat = convert(exported).unwrap();
+        assert_eq!(chat.title, "Regenerated");
+        assert_eq!(contents(&chat.history), ["Question", "Better answer"]);
+        assert_eq!(chat.history[1].thoughts(), Some("Hmm"));
+        assert_eq!(chat.created_at.unix_timestamp(), 1_700_000_000);
+    }
+
+    #[test]
+    fn imports_messages_of_older_versions() {
+        let exported = json!({
+            "chat": {
+                "title": "Old",
+                "messages": [message("user", "Question", None), message("assistant", "Answer", None)],
+                "timestamp": 1_700_000_000_000_i64
+            }
+        });
+        let chat = convert(exported).unwrap();
+        assert_eq!(chat.title, "Old");
+        assert_eq!(contents(&chat.history), ["Question", "Answer"]);
+        assert_eq!(chat.created_at.unix_timestamp(), 1_700_000_000);
+    }
+
+    #[test]
+    fn stops_at_cycles() {
+        let exported = json!({
+            "chat": {
+                "history": {
+                    "currentId": "a",
+                    "messages": {
+                        "q": message("user", "Question", Some("a")),
+                        "a": message("assistant", "Answer", Some("q"))
+                    }
+                }
+            }
+        });
+        let chat = convert(exported).unwrap();
+        assert!(chat.history.len() <= 3);
+        assert_eq!(chat.history.last().unwrap().content(), "Answer");
+    }
+
+    #[test]
+    fn splits_reasoning_blocks() {
+        let content = "<details type=\"reasoning\" done=\"true\" duration=\"2\">\n\
+            <summary>Thought for 2 seconds</summary>\n\
+            > First line\n\
+            >\n\
+            > Second line\n\
+            </details>\n\
+            The answer";
+        let (answer, thoughts) = split_reasoning(content);
+        assert_eq!(answer, "The answer");
+        assert_eq!(thoughts.as_deref(), Some("First line\n\nSecond line"));
+    }
+
+    #[test]
+    fn splits_think_tags() {
+        let (answer, thoughts) = split_reasoning("<think>\nLet me see\n</think>\n\nThe answer");
+        assert_eq!(answer, "The answer");
+        assert_eq!(thoughts.as_deref(), Some("Let me see"));
+
+        let (answer, thoughts) = split_reasoning("<think>Unfinished");
+        assert_eq!((answer.as_str(), thoughts), ("<think>Unfinished", None));
+    }
+}
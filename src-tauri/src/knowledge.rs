@@ -0,0 +1,121 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use log::{error, info};
+use tauri::{AppHandle, Manager, Wry};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::{
+    errors::Error,
+    knowledge::index::{KnowledgeBase, KnowledgeBaseInfo},
+};
+
+pub(crate) mod chunker;
+pub(crate) mod index;
+
+pub type SharedKnowledgeBase = Arc<RwLock<KnowledgeBase>>;
+pub type SharedKnowledgeStore = Arc<KnowledgeStore>;
+
+/// Directory of the indices inside the app data directory,
+/// which also contains `settings.json`.
+const KNOWLEDGE_DIR: &str = "knowledge";
+
+/// All knowledge bases.
+/// Each one is stored in its own file named after its UUID.
+pub struct KnowledgeStore {
+    dir: PathBuf,
+    bases: RwLock<HashMap<Uuid, SharedKnowledgeBase>>
+}
+
+impl KnowledgeStore {
+    /// Loads all knowledge bases stored in `dir`.
+    /// Unreadable indices are skipped.
+    pub fn load(dir: PathBuf) -> Self {
+        let mut bases = HashMap::new();
+        if let Ok(entries) = std::fs::read_dir(&dir) {
+            for path in entries.flatten().map(|e| e.path()) {
+                if path.extension().is_none_or(|ext| ext != "json") {
+                    continue;
+                }
+                let base = std::fs::read(&path)
+                    .map_err(Error::from)
+                    .and_then(|data| serde_json::from_slice::<KnowledgeBase>(&data).map_err(Error::from));
+                match base {
+                    Ok(base) => {
+                        bases.insert(base.info().uuid, Arc::new(RwLock::new(base)));
+                    },
+                    Err(e) => error!("Cannot load knowledge base {:?}: {:?}", path, e)
+                }
+            }
+        }
+        info!("Loaded {} knowledge bases", bases.len());
+
+        Self {
+            dir,
+            bases: RwLock::new(bases)
+        }
+    }
+
+    pub async fn list(&self) -> Vec<KnowledgeBaseInfo> {
+        let mut infos = Vec::new();
+        for base in self.bases.read().await.values() {
+            infos.push(base.read().await.info().clone());
+        }
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+
+    pub async fn get(&self, uuid: &Uuid) -> Result<SharedKnowledgeBase, Error> {
+        self.bases
+            .read()
+            .await
+            .get(uuid)
+            .cloned()
+            .ok_or(Error::KnowledgeBaseNotFound(uuid.to_string()))
+    }
+
+    /// Adds and saves a new knowledge base.
+    pub async fn insert(&self, base: KnowledgeBase) -> Result<SharedKnowledgeBase, Error> {
+        self.save(&base).await?;
+        let uuid = base.info().uuid;
+        let base = Arc::new(RwLock::new(base));
+        self.bases.write().await.insert(uuid, base.clone());
+        Ok(base)
+    }
+
+    pub async fn save(&self, base: &KnowledgeBase) -> Result<(), Error> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let data = serde_json::to_vec(base)?;
+        // Write to a temporary file first, so a crash doesn't corrupt the index
+        let path = self.path(&base.info().uuid);
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, data).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, uuid: &Uuid) -> Result<(), Error> {
+        self.bases
+            .write()
+            .await
+            .remove(uuid)
+            .ok_or(Error::KnowledgeBaseNotFound(uuid.to_string()))?;
+        match tokio::fs::remove_file(self.path(uuid)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(())
+        }
+    }
+
+    fn path(&self, uuid: &Uuid) -> PathBuf {
+        self.dir.join(format!("{uuid}.json"))
+    }
+}
+
+pub fn build_knowledge_store(app: &AppHandle<Wry>) -> SharedKnowledgeStore {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map(|dir| dir.join(KNOWLEDGE_DIR))
+        .unwrap_or(PathBuf::from(KNOWLEDGE_DIR));
+    Arc::new(KnowledgeStore::load(dir))
+}
@@ -0,0 +1,131 @@
+/// A part of a document, which is embedded on its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub text: String,
+    /// First line of the chunk, starting at 1
+    pub start_line: usize,
+    /// Last line of the chunk (inclusive)
+    pub end_line: usize
+}
+
+/// Splits documents into chunks of whole lines.
+/// Chunks preferably end at paragraph breaks (blank lines), so
+/// Markdown sections and functions in source code stay together.
+/// Consecutive chunks overlap by a few lines to keep some context.
+pub struct Chunker {
+    /// Maximum number of characters of a chunk.
+    /// Only exceeded by single lines longer than that.
+    max_chars: usize,
+    overlap_lines: usize
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Self {
+            max_chars: 1500,
+            overlap_lines: 2
+        }
+    }
+}
+
+impl Chunker {
+    pub fn chunk(&self, text: &str) -> Vec<Chunk> {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut chunks = Vec::new();
+
+        let mut start = 0;
+        while start < lines.len() {
+            if lines[start].trim().is_empty() {
+                start += 1;
+                continue;
+            }
+
+            let mut end = start;
+            let mut len = 0;
+            let mut paragraph_end = None;
+            while end < lines.len() {
+                let line_len = lines[end].chars().count() + 1;
+                if len + line_len > self.max_chars && end > start {
+                    break;
+                }
+                // Don't end up with tiny chunks
+                if lines[end].trim().is_empty() && len >= self.max_chars / 2 {
+                    paragraph_end = Some(end);
+                }
+                len += line_len;
+                end += 1;
+            }
+            if end < lines.len() {
+                end = paragraph_end.unwrap_or(end);
+            }
+
+            chunks.push(Chunk {
+                text: lines[start..end].join("\n").trim_end().to_owned(),
+                start_line: start + 1,
+                end_line: end
+            });
+            if end >= lines.len() {
+                break;
+            }
+            start = end.saturating_sub(self.overlap_lines).max(start + 1);
+        }
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chunk, Chunker};
+
+    fn chunker(max_chars: usize, overlap_lines: usize) -> Chunker {
+        Chunker { max_chars, overlap_lines }
+    }
+
+    #[test]
+    fn keeps_short_text_in_one_chunk() {
+        let chunks = Chunker::default().chunk("# Title\n\nSome text.\n");
+        assert_eq!(chunks, vec![Chunk { text: "# Title\n\nSome text.".into(), start_line: 1, end_line: 3 }]);
+    }
+
+    #[test]
+    fn skips_empty_text() {
+        assert!(Chunker::default().chunk("").is_empty());
+        assert!(Chunker::default().chunk("\n  \n\n").is_empty());
+    }
+
+    #[test]
+    fn splits_long_text_with_overlap() {
+        let text = (1..=10).map(|i| format!("line {i:02}")).collect::<Vec<_>>().join("\n");
+        // Each line has 7 characters plus the line break
+        let chunks = chunker(32, 1).chunk(&text);
+        let ranges: Vec<(usize, usize)> = chunks.iter().map(|c| (c.start_line, c.end_line)).collect();
+        assert_eq!(ranges, vec![(1, 4), (4, 7), (7, 10)]);
+        assert_eq!(chunks[1].text, "line 04\nline 05\nline 06\nline 07");
+    }
+
+    #[test]
+    fn prefers_paragraph_breaks() {
+        let text = "aaaa\nbbbb\n\ncccc\ndddd\neeee";
+        let chunks = chunker(20, 0).chunk(text);
+        assert_eq!(chunks[0].text, "aaaa\nbbbb");
+        assert_eq!(chunks[0].end_line, 2);
+        assert_eq!(chunks[1].start_line, 4);
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        // 5 characters but 10 bytes per line
+        let text = "äöüßé\näöüßé\näöüßé";
+        let chunks = chunker(18, 0).chunk(text);
+        assert_eq!(chunks.len(), 1);
+    }
+
+    #[test]
+    fn keeps_overlong_lines() {
+        let line = "x".repeat(50);
+        let chunks = chunker(20, 2).chunk(&format!("{line}\n{line}"));
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].text, line);
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (2, 2));
+    }
+}
@@ -0,0 +1,412 @@
+use std::{collections::{HashMap, HashSet}, path::{Path, PathBuf}, time::{Duration, Instant, UNIX_EPOCH}};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use time::UtcDateTime;
+use uuid::Uuid;
+
+use crate::{
+    backend::{chat::{parse_utc_datetime, serialize_utc_datetime}, llm::SharedModel},
+    errors::{self, Error},
+    knowledge::chunker::Chunker,
+};
+
+/// Number of chunks embedded with a single request.
+const EMBEDDING_BATCH_SIZE: usize = 32;
+
+/// Larger files are most likely not written by hand and are skipped.
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Extensions of the files which are indexed.
+const INDEXED_EXTENSIONS: &[&str] = &[
+    "md", "markdown", "txt", "rst", "adoc", "org",
+    "rs", "ts", "js", "svelte", "vue", "py", "java", "kt", "c", "h", "cpp", "hpp", "cs",
+    "go", "rb", "php", "swift", "sh", "lua", "sql", "html", "css",
+    "toml", "yaml", "yml", "json", "xml",
+];
+
+/// Minimum time between two checks of a folder for changed files,
+/// see [KnowledgeBase::needs_scan].
+const RESCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Directories which are never indexed.
+const IGNORED_DIRS: &[&str] = &["node_modules", "target", "build", "dist", "__pycache__"];
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct KnowledgeBaseInfo {
+    pub uuid: Uuid,
+    pub name: String,
+    /// Indexed folder
+    pub folder: PathBuf,
+    /// Backend of the embedding model
+    pub backend_name: String,
+    pub embedding_model: String,
+    /// Dimension of the embeddings, 0 if nothing has been indexed yet
+    pub dimension: usize,
+    pub file_count: usize,
+    pub chunk_count: usize,
+    #[serde(deserialize_with = "parse_utc_datetime", serialize_with = "serialize_utc_datetime")]
+    pub updated_at: UtcDateTime
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexedChunk {
+    text: String,
+    start_line: usize,
+    end_line: usize,
+    embedding: Vec<f32>
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexedFile {
+    /// Modification time in milliseconds since the Unix epoch
+    modified: u64,
+    size: u64,
+    chunks: Vec<IndexedChunk>
+}
+
+/// Outcome of updating the index of a [KnowledgeBase].
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexReport {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    /// Files which could not be read, e.g. as they are no UTF-8
+    pub failed: Vec<String>
+}
+
+impl IndexReport {
+    /// Whether the index has been changed.
+    pub fn changed(&self) -> bool {
+        self.added + self.updated + self.removed > 0 || !self.failed.is_empty()
+    }
+}
+
+/// A chunk found by [KnowledgeBase::search].
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RetrievedChunk {
+    /// Path of the file relative to the folder of the knowledge base
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub text: String,
+    /// Cosine similarity to the query
+    pub score: f32
+}
+
+/// Vector index of the text files of a folder.
+/// Files are identified by their path relative to the folder.
+#[derive(Serialize, Deserialize)]
+pub struct KnowledgeBase {
+    info: KnowledgeBaseInfo,
+    files: HashMap<String, IndexedFile>,
+    /// When the folder has been compared with the index the last time
+    #[serde(skip)]
+    last_scan: Option<Instant>
+}
+
+/// A file found in the folder of a knowledge base.
+pub struct FoundFile {
+    relative_path: String,
+    path: PathBuf,
+    modified: u64,
+    size: u64
+}
+
+impl KnowledgeBase {
+    pub fn new(name: String, folder: PathBuf, backend_name: String, embedding_model: String) -> Self {
+        Self {
+            info: KnowledgeBaseInfo {
+                uuid: Uuid::new_v4(),
+                name,
+                folder,
+                backend_name,
+                embedding_model,
+                dimension: 0,
+                file_count: 0,
+                chunk_count: 0,
+                updated_at: UtcDateTime::now()
+            },
+            files: HashMap::new(),
+            last_scan: None
+        }
+    }
+
+    pub fn info(&self) -> &KnowledgeBaseInfo {
+        &self.info
+    }
+
+    /// Fails if embeddings with `dimension` can't be compared with the indexed ones,
+    /// e.g. since the embedding model has been replaced. The index has to be rebuilt then.
+    pub fn check_dimension(&self, dimension: usize) -> Result<(), Error> {
+        if self.info.dimension != 0 && dimension != self.info.dimension {
+            return Err(Error::IndexOutdated(self.info.name.clone()));
+        }
+        Ok(())
+    }
+
+    /// Removes everything from the index.
+    pub fn clear(&mut self) {
+        self.files.clear();
+        self.info.dimension = 0;
+        self.update_info();
+    }
+
+    /// Brings the index up to date with the folder.
+    /// Only new and modified files are embedded again.
+    /// On failure, the files indexed so far are kept.
+    pub async fn update(&mut self, model: &SharedModel) -> Result<IndexReport, Error> {
+        let found = self.scan().await?;
+        self.update_files(found, model).await
+    }
+
+    /// Finds all files of the folder without reading them.
+    pub async fn scan(&self) -> Result<Vec<FoundFile>, Error> {
+        let folder = self.info.folder.clone();
+        tokio::task::spawn_blocking(move || find_files(&folder))
+            .await
+            .map_err(errors::internal)?
+    }
+
+    /// Whether the folder has not been checked for changes recently.
+    pub fn needs_scan(&self) -> bool {
+        self.last_scan.is_none_or(|scanned| scanned.elapsed() >= RESCAN_INTERVAL)
+    }
+
+    /// Remembers that the folder has just been checked for changes.
+    pub fn mark_scanned(&mut self) {
+        self.last_scan = Some(Instant::now());
+    }
+
+    /// Whether the `found` files differ from the indexed ones
+    /// by their paths, modification times or sizes.
+    pub fn has_changes(&self, found: &[FoundFile]) -> bool {
+        found.len() != self.files.len() || found.iter().any(|file| {
+            self.files
+                .get(&file.relative_path)
+                .is_none_or(|f| f.modified != file.modified || f.size != file.size)
+        })
+    }
+
+    /// Brings the index up to date with the `found` files of [KnowledgeBase::scan].
+    pub async fn update_files(&mut self, found: Vec<FoundFile>, model: &SharedModel) -> Result<IndexReport, Error> {
+        self.mark_scanned();
+        let mut report = IndexReport::default();
+
+        let found_paths: HashSet<&str> = found.iter().map(|f| f.relative_path.as_str()).collect();
+        let before = self.files.len();
+        self.files.retain(|path, _| found_paths.contains(path.as_str()));
+        report.removed = before - self.files.len();
+
+        let chunker = Chunker::default();
+        let result = self.index_files(found, &chunker, model, &mut report).await;
+        self.update_info();
+        result?;
+
+        info!("Updated knowledge base '{}': {:?}", self.info.name, report);
+        Ok(report)
+    }
+
+    async fn index_files(
+        &mut self,
+        found: Vec<FoundFile>,
+        chunker: &Chunker,
+        model: &SharedModel,
+        report: &mut IndexReport
+    ) -> Result<(), Error> {
+        for file in found {
+            let existing = self.files.get(&file.relative_path);
+            if existing.is_some_and(|f| f.modified == file.modified && f.size == file.size) {
+                report.unchanged += 1;
+                continue;
+            }
+            let is_new = existing.is_none();
+
+            let content = match tokio::fs::read_to_string(&file.path).await {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!("Cannot index {:?}: {}", file.path, e);
+                    // Kept without chunks, so it is only read again after being modified
+                    self.files.insert(file.relative_path.clone(), IndexedFile {
+                        modified: file.modified,
+                        size: file.size,
+                        chunks: Vec::new()
+                    });
+                    report.failed.push(file.relative_path);
+                    continue;
+                }
+            };
+
+            let chunks = chunker.chunk(&content);
+            let mut indexed = Vec::with_capacity(chunks.len());
+            for batch in chunks.chunks(EMBEDDING_BATCH_SIZE) {
+                // The path gives the model some context of the chunk
+                let input: Vec<String> = batch
+                    .iter()
+                    .map(|chunk| format!("{}\n{}", file.relative_path, chunk.text))
+                    .collect();
+                let embeddings = model.read().await.embed(&input).await?;
+                self.check_dimension(embeddings.dimension)?;
+                self.info.dimension = embeddings.dimension;
+
+                indexed.extend(batch.iter().zip(embeddings.embeddings).map(|(chunk, embedding)| IndexedChunk {
+                    text: chunk.text.clone(),
+                    start_line: chunk.start_line,
+                    end_line: chunk.end_line,
+                    embedding
+                }));
+            }
+
+            self.files.insert(file.relative_path, IndexedFile {
+                modified: file.modified,
+                size: file.size,
+                chunks: indexed
+            });
+            if is_new {
+                report.added += 1;
+            } else {
+                report.updated += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn update_info(&mut self) {
+        self.info.file_count = self.files.len();
+        self.info.chunk_count = self.files.values().map(|f| f.chunks.len()).sum();
+        self.info.updated_at = UtcDateTime::now();
+    }
+
+    /// Finds the `top_k` chunks most similar to the embedded query.
+    pub fn search(&self, query: &[f32], top_k: usize) -> Vec<RetrievedChunk> {
+        let mut results: Vec<RetrievedChunk> = self.files
+            .iter()
+            .flat_map(|(path, file)| file.chunks.iter().map(move |chunk| RetrievedChunk {
+                path: path.clone(),
+                start_line: chunk.start_line,
+                end_line: chunk.end_line,
+                text: String::new(),
+                score: cosine_similarity(query, &chunk.embedding)
+            }))
+            .collect();
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        results.truncate(top_k);
+
+        // Only copy the text of the chunks we actually return
+        for result in &mut results {
+            if let Some(chunk) = self.files[&result.path].chunks.iter().find(|c| c.start_line == result.start_line) {
+                result.text = chunk.text.clone();
+            }
+        }
+        results
+    }
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let (mut dot, mut norm_a, mut norm_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+/// Recursively collects all indexable files of `folder`.
+/// Hidden files and directories are skipped.
+fn find_files(folder: &Path) -> Result<Vec<FoundFile>, Error> {
+    let mut found = Vec::new();
+    let mut dirs = vec![folder.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+
+            let path = entry.path();
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                if !IGNORED_DIRS.contains(&name.as_str()) {
+                    dirs.push(path);
+                }
+                continue;
+            }
+
+            let indexed = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| INDEXED_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+            if !metadata.is_file() || !indexed || metadata.len() > MAX_FILE_SIZE {
+                continue;
+            }
+
+            let Ok(relative_path) = path.strip_prefix(folder) else {
+                continue;
+            };
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default();
+            found.push(FoundFile {
+                relative_path: relative_path.to_string_lossy().replace('\\', "/"),
+                path,
+                modified,
+                size: metadata.len()
+            });
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::errors::Error;
+
+    use super::{cosine_similarity, KnowledgeBase};
+
+    #[test]
+    fn similarity_of_parallel_vectors_is_one() {
+        assert!((cosine_similarity(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn similarity_of_orthogonal_and_opposite_vectors() {
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert!((cosine_similarity(&[1.0, 1.0], &[-1.0, -1.0]) + 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn similarity_of_invalid_vectors_is_zero() {
+        assert_eq!(cosine_similarity(&[1.0, 2.0], &[1.0, 2.0, 3.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 2.0]), 0.0);
+    }
+
+    #[test]
+    fn embeddings_of_other_dimensions_need_a_rebuild() {
+        let mut base = KnowledgeBase::new("Notes".into(), PathBuf::from("/notes"), "Ollama".into(), "nomic-embed-text".into());
+        assert!(base.check_dimension(768).is_ok());
+
+        base.info.dimension = 768;
+        assert!(base.check_dimension(768).is_ok());
+        assert!(matches!(base.check_dimension(1024), Err(Error::IndexOutdated(name)) if name == "Notes"));
+
+        base.clear();
+        assert!(base.check_dimension(1024).is_ok());
+    }
+}
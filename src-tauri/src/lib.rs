@@ -2,19 +2,30 @@ use std::error::Error;
 use tauri::{App, AppHandle, Manager, Wry};
 
 use crate::{
-    backend::{build_backend_store, BackendStore},
+    backend::{build_backend_store, tools::{build_tool_registry, SharedToolRegistry}, BackendStore},
+    chat_repository::{build_chat_repository, SharedChatRepository},
+    knowledge::{build_knowledge_store, SharedKnowledgeStore},
     settings::{build_settings, Settings},
 };
 
 mod backend;
+mod chat_format;
+mod chat_repository;
 mod commands;
 mod errors;
+mod export;
+mod import;
+mod knowledge;
 mod settings;
 
 pub fn setup(app: &mut App<Wry>) -> Result<(), Box<dyn Error>> {
     let settings = build_settings(app.app_handle());
     app.manage(build_backend_store(&*settings.blocking_read()));
     app.manage(settings);
+    let knowledge = build_knowledge_store(app.app_handle());
+    app.manage(build_tool_registry(knowledge.clone()));
+    app.manage(knowledge);
+    app.manage(build_chat_repository(app.app_handle())?);
     Ok(())
 }
 
@@ -22,6 +33,9 @@ pub fn setup(app: &mut App<Wry>) -> Result<(), Box<dyn Error>> {
 pub fn cleanup_appstate(app: &AppHandle) {
     let _ = app.unmanage::<BackendStore>();
     let _ = app.unmanage::<Settings>();
+    let _ = app.unmanage::<SharedToolRegistry>();
+    let _ = app.unmanage::<SharedKnowledgeStore>();
+    let _ = app.unmanage::<SharedChatRepository>();
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
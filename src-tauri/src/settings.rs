@@ -1,5 +1,6 @@
 use std::{path::{Path, PathBuf}, sync::Arc};
 
+use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Wry};
 use tauri_plugin_store::{Store, StoreExt};
 use tokio::sync::RwLock;
@@ -9,6 +10,17 @@ pub(crate) type AppSettings = RwLock<Settings>;
 
 const OLLAMA_URL_KEY: &'static str = "ollamaUrl";
 const OLLAMA_MODELS_PATH_KEY: &'static str = "ollamaModelsPath";
+const OPENAI_URL_KEY: &'static str = "openAiUrl";
+const OPENAI_API_KEY_KEY: &'static str = "openAiApiKey";
+const TITLE_MODEL_KEY: &'static str = "titleModel";
+
+/// Model generating the titles of chats, usually a small one.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TitleModel {
+    pub backend_name: String,
+    pub model_name: String
+}
 
 pub struct Settings {
     store: Arc<Store<Wry>>
@@ -32,6 +44,30 @@ impl Settings {
         self.save();
     }
 
+    pub fn store_openai_url(&self, url: &str) {
+        self.store.set(OPENAI_URL_KEY, url);
+        self.save()
+    }
+
+    pub fn store_openai_api_key(&self, api_key: Option<&str>) {
+        match api_key {
+            Some(key) => self.store.set(OPENAI_API_KEY_KEY, key),
+            None => { let _ = self.store.delete(OPENAI_API_KEY_KEY); }
+        }
+        self.save()
+    }
+
+    pub fn store_title_model(&self, model: Option<&TitleModel>) {
+        match model {
+            Some(model) => match serde_json::to_value(model) {
+                Ok(value) => self.store.set(TITLE_MODEL_KEY, value),
+                Err(e) => eprintln!("Cannot store the title model: {e}")
+            },
+            None => { let _ = self.store.delete(TITLE_MODEL_KEY); }
+        }
+        self.save()
+    }
+
     fn save(&self) {
         let _ = self.store.save().inspect_err(|e| {
             eprintln!("Cannot save settings: {e}");
@@ -50,6 +86,24 @@ impl Settings {
             .and_then(|path| path.as_str().and_then(|v| Some(v.to_owned())))
             .and_then(|path| Some(PathBuf::from(path)))
     }
+
+    pub fn openai_url(&self) -> Url {
+        self.store.get(OPENAI_URL_KEY)
+            .and_then(|v| v.as_str().and_then(|v| Some(v.to_owned())))
+            .and_then(|url| Url::parse(&url).ok())
+            .unwrap_or(Url::parse("http://localhost:8080/v1/").unwrap())
+    }
+
+    pub fn title_model(&self) -> Option<TitleModel> {
+        self.store.get(TITLE_MODEL_KEY)
+            .and_then(|v| serde_json::from_value(v).ok())
+    }
+
+    pub fn openai_api_key(&self) -> Option<String> {
+        self.store.get(OPENAI_API_KEY_KEY)
+            .and_then(|v| v.as_str().and_then(|v| Some(v.to_owned())))
+            .filter(|key| !key.is_empty())
+    }
 }
 
 pub(crate) fn build_settings(app: &AppHandle<Wry>) -> AppSettings
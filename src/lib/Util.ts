@@ -1,4 +1,5 @@
 import { showError, showInfo, showWarning, type Notification } from "./Snackbar.svelte";
+import type { BackendError } from "./core/Chat";
 
 const UNIT_SUFFIX = ["Bytes", "KB", "MB", "GB"];
 
@@ -15,6 +16,42 @@ export function formatByteSize(size: number): string {
     return `${size}.${decimals} ${UNIT_SUFFIX[prefixCount]}`;
 }
 
+/**
+ * Returns a readable description of an error returned by the Rust backend.
+ */
+export function describeBackendError(e: BackendError): string {
+    switch(e.kind) {
+        case "modelNotFound":
+            return `Model '${e.message.model}' not found in ${e.message.backend}`;
+        case "backendBoot":
+            return `Could not start ${e.message.backend}: ${e.message.reason}`;
+        case "http":
+            return e.message.status_msg;
+        case "outOfMemory":
+            return `Not enough memory to run the model (${e.message})`;
+        case "contextTooLong":
+            return `The chat is too long for the context of the model (${e.message})`;
+        case "schemaViolation":
+            return `The response does not match the requested format: ${e.message.join(", ")}`;
+        case "indexOutdated":
+            return `The knowledge base '${e.message}' has been indexed with another embedding model, please rebuild it`;
+        case "unsupportedFileVersion":
+            return `The chats have been exported by a newer version of Whisper2 (format version ${e.message})`;
+        case "upload":
+            return `Could not upload '${e.message.file}': ${e.message.reason}`;
+        case "database":
+            return `Could not access the stored chats: ${e.message}`;
+        case "toolStepLimit":
+            return `The model still called tools after ${e.message} steps`;
+        default:
+            return String(e.message);
+    }
+}
+
+function isBackendError(e: any): e is BackendError {
+    return typeof e === "object" && e !== null && typeof e.kind === "string";
+}
+
 export interface ErrorOptions {
     userMsg: string,
     level?: Notification["level"]
@@ -41,6 +78,8 @@ export function handleError(e: any, options?: ErrorOptions) {
         showFn(pre + e.message);
     } else if (typeof e === "string") {
         showFn(pre + e);
+    } else if (isBackendError(e)) {
+        showFn(pre + describeBackendError(e));
     } else {
         showFn("Unknown error");
     }
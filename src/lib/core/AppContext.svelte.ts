@@ -1,6 +1,5 @@
-import type { Chat, ChatMessage } from "./Chat";
+import { activePath, type Chat, type ChatMessage, type ChatSearchResult, type ChatSummary, type ExportFormat, type ExportReport, type ImportFormat, type ImportReport, type MergeStrategy, type StoredChat, type TitleModel } from "./Chat";
 import type { Model } from "./LLMBackend";
-import { load, type Store } from '@tauri-apps/plugin-store';
 import Settings from "./Settings.svelte";
 import OllamaBackend from "./backends/Ollama.svelte";
 import { invoke } from "@tauri-apps/api/core";
@@ -17,8 +16,6 @@ export default class AppContext {
         return AppContext.instance;
     }
 
-    private static CHAT_STORE_PATH: string = "chats.json";
-
     constructor() {
         this._settings = new Settings();
     }
@@ -28,9 +25,6 @@ export default class AppContext {
     private ollamaBackend: OllamaBackend = new OllamaBackend();
     private _flatModels: Model[] = $derived(Object.values(this.ollamaBackend.models).flat());
 
-    // Store for saving and loading chats from disk
-    // Initialized at startup
-    private _chatStore!: Store;
     private _settings: Settings;
 
     private _chats: Chat[] = $state([]);
@@ -66,7 +60,6 @@ export default class AppContext {
 
         try {
             await this._settings.init();
-            this._chatStore = await load(AppContext.CHAT_STORE_PATH);
             await this.ollamaBackend.init();
             await Promise.all([
                 this.loadChats(),
@@ -84,25 +77,7 @@ export default class AppContext {
      */
     async saveChats() {
         for(const chat of this._chats) {
-            await this.saveChat(chat);
-        }
-    }
-
-    /**
-     * Saves the given chat to disk.
-     * @param chat The chat to save
-     */
-    async saveChat(chat: Chat) {
-        let snapshot: Partial<Chat> = this.convertChatToSerializable(chat);
-        delete snapshot.uuid;
-        this._chatStore.set(chat.uuid, snapshot);
-    }
-
-    private convertChatToSerializable(chat: Chat) {
-        if(chat instanceof ReactiveChat) {
-            return (chat as ReactiveChat).toPoco();
-        } else {
-            return $state.snapshot(chat) as Partial<Chat>;
+            await chat.save();
         }
     }
 
@@ -112,44 +87,101 @@ export default class AppContext {
      * @param chat The chat to delete
      */
     async deleteChat(chat: Chat) {
-        await this._chatStore.delete(chat.uuid);
         const idx = this._chats.findIndex(c => c.uuid === chat.uuid);
         if(idx < 0) return;
+        if(chat instanceof ReactiveChat && chat.persisted) {
+            await invoke("delete_chat", { uuid: chat.uuid });
+        }
         this._chats.splice(idx, 1);
     }
 
     /**
-     * Load all chats from disk into `this.chats`.
+     * Load the summaries of all chats into `this.chats`.
+     * The history of a chat is loaded by `Chat.load`.
      */
     async loadChats() {
-        this._chats = [];
-        for(const chatId of await this._chatStore.keys()) {
-            let chat = await this._chatStore.get<Chat>(chatId);
-            if(chat) {
-                chat.uuid = chatId;
-                chat.createdAt = new Date(chat.createdAt);
-                this._chats.push(new ReactiveChat(this, chat));
-            }
-        }
-        this._chats.sort((a,b) => b.createdAt.getTime() - a.createdAt.getTime());
+        const summaries: ChatSummary[] = await invoke("list_chats");
+        this._chats = summaries.map(s => ReactiveChat.fromSummary(this, s));
     }
 
-    async saveChatsToDisk(): Promise<void> {
-        await this._chatStore.save();
-        await invoke("save_chats", {
-            storeName: AppContext.CHAT_STORE_PATH
-        });
+    /**
+     * Full-text search over the titles and messages of all stored chats.
+     * The last word of `query` may be incomplete.
+     */
+    async searchChats(query: string, limit?: number): Promise<ChatSearchResult[]> {
+        return await invoke("search_chats", { query, limit });
     }
 
-    async importChatsFromDisk(): Promise<void> {
-        await invoke("import_chats", {
-            storeName: AppContext.CHAT_STORE_PATH
-        });
+    /**
+     * Asks the user for a file and exports the given chats or all chats into it.
+     * @returns null if the user aborted
+     */
+    async saveChatsToDisk(format: ExportFormat = "json", uuids?: string[]): Promise<ExportReport | null> {
+        return await invoke("save_chats", { format, uuids });
+    }
+
+    /**
+     * Exports the given chats or all chats to `path` without asking the user.
+     * With `dryRun`, only the report is returned.
+     */
+    async exportChatsToPath(path: string, options: {format?: ExportFormat, uuids?: string[], dryRun?: boolean} = {}): Promise<ExportReport> {
+        return await invoke("export_chats_to_path", { path, ...options });
+    }
+
+    /**
+     * Renders the given chats as a single Markdown or HTML document,
+     * e.g. to paste it into a ticket.
+     */
+    async renderChats(uuids: string[], format: ExportFormat): Promise<string> {
+        return await invoke("render_chats", { uuids, format });
+    }
+
+    /**
+     * Asks the user for a file and imports all chats of it.
+     * The format of the file is detected if not given.
+     * @returns null if the user aborted
+     */
+    async importChatsFromDisk(strategy: MergeStrategy = "overwrite", format?: ImportFormat): Promise<ImportReport | null> {
+        const report: ImportReport | null = await invoke("import_chats", { format, strategy });
         await this.loadChats();
+        return report;
+    }
+
+    /**
+     * Imports the given chats or all chats of the file at `path` without asking the user.
+     * With `dryRun`, only the report is returned.
+     */
+    async importChatsFromPath(
+        path: string,
+        options: {format?: ImportFormat, uuids?: string[], strategy?: MergeStrategy, dryRun?: boolean} = {}
+    ): Promise<ImportReport> {
+        const report: ImportReport = await invoke("import_chats_from_path", { path, ...options });
+        if(!report.dryRun) {
+            await this.loadChats();
+        }
+        return report;
+    }
+
+    /**
+     * Model naming new chats. If null, the model of the chat is used.
+     */
+    async getTitleModel(): Promise<TitleModel | null> {
+        return await invoke("get_title_model");
+    }
+
+    async setTitleModel(model: TitleModel | null): Promise<void> {
+        await invoke("set_title_model", { model });
+    }
+
+    /**
+     * JSON Schema of chats exported as "json".
+     */
+    async getChatExportSchema(): Promise<object> {
+        return await invoke("get_chat_export_schema");
     }
 
     async deleteAllChats(): Promise<void> {
-        await this._chatStore.clear();
+        await invoke("delete_all_chats");
         await this.loadChats();
     }
 
@@ -180,26 +212,133 @@ export default class AppContext {
 class ReactiveChat implements Chat {
     public title: string = $state("New Chat");
     public history: ChatMessage[] = $state([]);
+    public knowledgeBases: string[] = $state([]);
 
     private ctx: AppContext;
     private _uuid: string;
     private _createdAt: Date;
+    // Messages of all branches, only known after loading
+    private tree: ChatMessage[] = [];
 
-    constructor(ctx: AppContext, chat?: Chat) {
+    // State on disk, used to only write the changes
+    private _persisted = false;
+    private persistedTitle = "";
+    private persistedMessages = 0;
+    private loaded = true;
+
+    constructor(ctx: AppContext) {
         this.ctx = ctx;
-        if(!chat){
-            this._uuid = crypto.randomUUID();
-            this._createdAt = new Date();
+        this._uuid = crypto.randomUUID();
+        this._createdAt = new Date();
+    }
+
+    static fromSummary(ctx: AppContext, summary: ChatSummary): ReactiveChat {
+        const chat = new ReactiveChat(ctx);
+        chat._uuid = summary.uuid;
+        chat._createdAt = new Date(summary.createdAt);
+        chat.title = summary.title;
+        chat._persisted = true;
+        chat.persistedTitle = summary.title;
+        chat.persistedMessages = summary.messageCount;
+        chat.loaded = false;
+        return chat;
+    }
+
+    async load() {
+        if(this.loaded) return;
+        const chat: StoredChat = await invoke("get_chat", { uuid: this._uuid });
+        this.setTree(chat);
+        this.knowledgeBases = chat.knowledgeBases ?? [];
+        this.loaded = true;
+    }
+
+    private setTree(chat: StoredChat) {
+        this.tree = chat.history;
+        this.history = activePath(chat.history, chat.current);
+        this.persistedMessages = this.history.length;
+    }
+
+    /**
+     * Writes the changes since the last save.
+     * Messages can only be appended to the active branch.
+     */
+    async save() {
+        if(!this.loaded) return;
+        if(!this._persisted) {
+            const chat: StoredChat = await invoke("create_chat", { chat: this.toPoco() });
+            // Keep the reactive messages, they only lack their IDs
+            chat.history.forEach((stored, i) => this.withId(i, stored));
+            this.tree = chat.history;
+            this._persisted = true;
         } else {
-            this._uuid = chat.uuid;
-            this._createdAt = chat.createdAt;
-            this.title = chat.title;
-            this.history = chat.history;
+            for(const message of $state.snapshot(this.history).slice(this.persistedMessages)) {
+                const stored: ChatMessage = await invoke("append_chat_message", { uuid: this._uuid, message });
+                this.withId(this.persistedMessages, stored);
+                this.tree.push(stored);
+                this.persistedMessages++;
+            }
+            if(this.title !== this.persistedTitle) {
+                await invoke("rename_chat", { uuid: this._uuid, title: this.title });
+            }
         }
+        this.persistedTitle = this.title;
+        this.persistedMessages = this.history.length;
     }
 
-    async save() {
-        await this.ctx.saveChat(this);
+    private withId(index: number, stored: ChatMessage) {
+        this.history[index].id = stored.id;
+        this.history[index].parent = stored.parent;
+    }
+
+    /**
+     * Other versions of `message`, e.g. regenerated responses, including itself.
+     */
+    siblings(message: ChatMessage): ChatMessage[] {
+        return this.tree.filter(m => m.parent === message.parent);
+    }
+
+    /**
+     * Shows the branch containing the stored message `messageId`.
+     */
+    async switchBranch(messageId: string) {
+        await this.save();
+        const chat: StoredChat = await invoke("switch_chat_branch", { uuid: this._uuid, messageId });
+        this.setTree(chat);
+    }
+
+    /**
+     * Adds `message` below the stored message `parent` as a new branch, which becomes the active one.
+     * Used for edited prompts and regenerated responses.
+     */
+    async fork(parent: string | undefined, message: ChatMessage) {
+        await this.save();
+        const stored: ChatMessage = await invoke("fork_chat", { uuid: this._uuid, parent, message });
+        this.tree.push(stored);
+        this.history = activePath(this.tree, stored.id);
+        this.persistedMessages = this.history.length;
+    }
+
+    /**
+     * Names the chat after its first exchange.
+     * Without a configured title model, `model` is used.
+     * The title is derived from the first prompt if no model is available.
+     */
+    async generateTitle(model?: Model) {
+        await this.save();
+        const title: string = await invoke("generate_chat_title", {
+            uuid: this._uuid,
+            backendName: model?.backend.name,
+            modelName: model?.name
+        });
+        this.title = title;
+        this.persistedTitle = title;
+    }
+
+    async setKnowledgeBases(knowledgeBases: string[]) {
+        this.knowledgeBases = knowledgeBases;
+        if(this._persisted) {
+            await invoke("set_chat_knowledge_bases", { uuid: this._uuid, knowledgeBases });
+        }
     }
 
     async delete() {
@@ -214,10 +353,15 @@ class ReactiveChat implements Chat {
         return this._createdAt;
     }
 
-    toPoco(): Omit<Chat, "save"|"delete"> {
+    get persisted(): boolean {
+        return this._persisted;
+    }
+
+    toPoco(): Omit<Chat, "save"|"delete"|"load"|"generateTitle"> {
         return {
             title: $state.snapshot(this.title),
             history: $state.snapshot(this.history),
+            knowledgeBases: $state.snapshot(this.knowledgeBases),
             uuid: this._uuid,
             createdAt: this._createdAt
         }
@@ -1,28 +1,225 @@
+import type { Model } from "./LLMBackend";
+
 export type Role = "system"|"user"|"assistant"|"tool";
 export type Base64 = string;
 
 export interface ChatMessage {
+    // Assigned when the message is stored
+    id?: string;
+    // Message answered by this one, missing for the first message
+    parent?: string;
     role: Role;
     content: string;
     images?: Base64[];
     // Internal thoughts of the model
-    thinking?: string
+    thinking?: string;
+    // Tools the assistant wants to call
+    tool_calls?: ToolCall[];
+    // Called tool of a "tool" message
+    tool_name?: string;
+    tool_call_id?: string;
+}
+
+export interface ToolCall {
+    id?: string;
+    function: {
+        name: string;
+        arguments: any;
+    };
 }
 
 export interface ChatRequest {
     prompt: ChatMessage;
 }
 
+/**
+ * Error returned by the Rust backend.
+ * `message` depends on the `kind` of the error.
+ */
+export interface BackendError {
+    kind: "io"|"http"|"backendNotFound"|"backendBoot"|"modelNotFound"|"knowledgeBaseNotFound"|"indexOutdated"|"chatNotFound"|"invalidChat"|"unsupportedFileVersion"|"invalidModelfile"|"upload"|"database"|"outOfMemory"
+        |"contextTooLong"|"invalidResponse"|"schemaViolation"|"toolStepLimit"|"generation"|"internal";
+    message: any;
+}
+
 export interface ChatResponse {
     done: boolean;
     message: ChatMessage;
+    // Set if the generation failed
+    error?: BackendError;
+    // Only part of the last response
+    stats?: GenerationStats;
+    // Only part of the first response if the history has been shortened
+    context?: ContextReport;
+}
+
+/**
+ * What to do with a history exceeding the context window of a model.
+ * "pinSystem" drops the oldest turns but keeps system messages,
+ * "summarize" replaces them with a summary written by the model,
+ * "rollingSummary" stores the summary with the chat and extends it later.
+ */
+export type ContextPolicy = "off"|"dropOldest"|"pinSystem"|"summarize"|"rollingSummary";
+
+/**
+ * What has been removed from a history to fit the context window.
+ * Token counts are estimated.
+ */
+export interface ContextReport {
+    policy: ContextPolicy;
+    contextLength: number;
+    estimatedTokens: number;
+    sentTokens: number;
+    droppedMessages: number;
+    summary?: string;
+}
+
+/**
+ * Statistics of a finished generation.
+ * Durations are in nanoseconds.
+ */
+export interface GenerationStats {
+    total_duration?: number;
+    load_duration?: number;
+    prompt_eval_count?: number;
+    prompt_eval_duration?: number;
+    eval_count?: number;
+    eval_duration?: number;
+    prompt_tokens_per_second?: number;
+    tokens_per_second?: number;
 }
 
 export interface Chat {
     uuid: string;
     title: string;
+    // Messages of the active branch
     history: ChatMessage[];
     createdAt: Date;
+    // UUIDs of the attached knowledge bases
+    knowledgeBases: string[];
+    // Loads the history if only the summary has been loaded
+    load: () => Promise<void>;
     save: () => Promise<void>;
     delete: () => Promise<void>;
+    // Names the chat, see `generate_chat_title`
+    generateTitle: (model?: Model) => Promise<void>;
+}
+
+/**
+ * Model naming new chats, usually a small one.
+ */
+export interface TitleModel {
+    backendName: string;
+    modelName: string;
+}
+
+/**
+ * A chat as stored by the Rust backend.
+ * Its history contains the messages of all branches, parents before their children.
+ */
+export interface StoredChat {
+    uuid: string;
+    title: string;
+    history: ChatMessage[];
+    // Last message of the active branch
+    current?: string;
+    createdAt: string;
+    knowledgeBases?: string[];
+}
+
+/**
+ * Messages from the first one to `current`.
+ */
+export function activePath(history: ChatMessage[], current?: string): ChatMessage[] {
+    const byId = new Map(history.map(message => [message.id, message]));
+    const path: ChatMessage[] = [];
+    let message = current ? byId.get(current) : undefined;
+    while(message && path.length <= history.length) {
+        path.push(message);
+        message = message.parent ? byId.get(message.parent) : undefined;
+    }
+    return path.reverse();
+}
+
+/**
+ * Overview of a stored chat without its history.
+ */
+export interface ChatSummary {
+    uuid: string;
+    title: string;
+    createdAt: string;
+    messageCount: number;
+}
+
+/**
+ * File formats of exported chats.
+ * Only "json" can be imported again.
+ */
+export type ExportFormat = "json"|"markdown"|"html";
+
+export interface ExportReport {
+    path: string;
+    format: ExportFormat;
+    chats: ChatSummary[];
+    // Size of the file in bytes
+    bytes: number;
+    // Nothing has been written if set
+    dryRun: boolean;
+}
+
+/**
+ * File formats chats can be imported from:
+ * our own JSON export, `conversations.json` of a ChatGPT data export
+ * or a chat export of Open WebUI.
+ */
+export type ImportFormat = "json"|"chatGpt"|"openWebUi";
+
+/**
+ * How to import a chat whose UUID already exists:
+ * keep the existing chat, replace it, import the chat with a new UUID
+ * or append the messages missing in the existing chat.
+ */
+export type MergeStrategy = "skip"|"overwrite"|"keepBoth"|"merge";
+
+/**
+ * A chat which has not been imported.
+ */
+export interface ImportIssue {
+    // null if the chat couldn't be read at all
+    uuid: string | null;
+    title: string | null;
+    reason: string;
+}
+
+export interface ImportReport {
+    path: string;
+    format: ImportFormat;
+    // Chats which didn't exist before, "keepBoth" chats with their new UUID
+    created: ChatSummary[];
+    // Existing chats which have been replaced or merged
+    updated: ChatSummary[];
+    // Existing chats which have been kept
+    skipped: ImportIssue[];
+    // Chats which are unreadable or invalid
+    rejected: ImportIssue[];
+    // Nothing has been written if set
+    dryRun: boolean;
+}
+
+/**
+ * Part of the snippet of a search result.
+ * Parts matching the query are highlighted.
+ */
+export interface SnippetPart {
+    text: string;
+    highlight: boolean;
+}
+
+export interface ChatSearchResult {
+    uuid: string;
+    title: string;
+    createdAt: string;
+    // Position of the matching message, null if the title matched
+    position: number | null;
+    snippet: SnippetPart[];
 }
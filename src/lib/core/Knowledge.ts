@@ -0,0 +1,73 @@
+import { invoke } from "@tauri-apps/api/core";
+
+/**
+ * Vector index of the text files of a local folder.
+ * Relevant parts of the files are provided to the model
+ * when the knowledge base is attached to a chat.
+ */
+export interface KnowledgeBaseInfo {
+    uuid: string;
+    name: string;
+    folder: string;
+    backendName: string;
+    embeddingModel: string;
+    dimension: number;
+    fileCount: number;
+    chunkCount: number;
+    updatedAt: string;
+}
+
+export interface IndexReport {
+    added: number;
+    updated: number;
+    removed: number;
+    unchanged: number;
+    // Files which could not be read
+    failed: string[];
+}
+
+export interface RetrievedChunk {
+    // Relative to the folder of the knowledge base
+    path: string;
+    startLine: number;
+    endLine: number;
+    text: string;
+    score: number;
+}
+
+export function listKnowledgeBases(): Promise<KnowledgeBaseInfo[]> {
+    return invoke("list_knowledge_bases");
+}
+
+/**
+ * Creates a knowledge base and indexes the folder.
+ * @param name Display name
+ * @param folder Absolute path of the folder
+ * @param backendName Backend of the embedding model
+ * @param modelName Embedding model, see `Backend.getEmbeddingModels`
+ */
+export function createKnowledgeBase(name: string, folder: string, backendName: string, modelName: string): Promise<KnowledgeBaseInfo> {
+    return invoke("create_knowledge_base", { name, folder, backendName, modelName });
+}
+
+/**
+ * Indexes new and modified files and removes deleted ones.
+ */
+export function updateKnowledgeBase(uuid: string): Promise<IndexReport> {
+    return invoke("update_knowledge_base", { uuid });
+}
+
+/**
+ * Indexes all files again.
+ */
+export function rebuildKnowledgeBase(uuid: string): Promise<IndexReport> {
+    return invoke("rebuild_knowledge_base", { uuid });
+}
+
+export function queryKnowledgeBase(uuid: string, query: string, topK?: number): Promise<RetrievedChunk[]> {
+    return invoke("query_knowledge_base", { uuid, query, topK });
+}
+
+export function deleteKnowledgeBase(uuid: string): Promise<void> {
+    return invoke("delete_knowledge_base", { uuid });
+}
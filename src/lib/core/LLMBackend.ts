@@ -1,23 +1,48 @@
-import type { ChatMessage, ChatResponse } from "./Chat";
+import type { ChatMessage, ChatResponse, ContextPolicy } from "./Chat";
 
 export interface Backend {
     readonly name: string;
     readonly models: Model[];
 
     updateModels(): Promise<Model[]>;
+    /**
+     * Models which can only compute embeddings.
+     * These are not part of `models`.
+     * Call `updateModels` before to get the latest models.
+     */
+    getEmbeddingModels(): Promise<Model[]>;
     running(): Promise<boolean>;
 
     boot(): Promise<void>;
     shutdown(): Promise<void>;
 }
 
-export type Capability = "completion"|"vision"|"tools";
+export type Capability = "completion"|"vision"|"tools"|"thinking"|"embedding";
 
-export interface Model {
+/**
+ * General information about a model.
+ * Optional fields are only set if the backend knows them.
+ */
+export interface ModelInfo {
     name: string;
     id: string;
     size: number;
     capabilities: Capability[];
+    // Maximum context length in tokens if the backend tells
+    contextLength?: number;
+    parameterCount?: number;
+    // e.g. "Q4_K_M"
+    quantization?: string;
+    // e.g. "llama"
+    family?: string;
+    // Prompt template in the syntax of the backend
+    template?: string;
+    license?: string;
+    // RFC 3339
+    modifiedAt?: string;
+}
+
+export interface Model extends ModelInfo {
     backend: Backend;
 
     /**
@@ -45,6 +70,11 @@ export interface Model {
      */
     unload(): Promise<void>;
 
+    /**
+     * Fetches everything the backend knows about the model.
+     */
+    getCard(): Promise<ModelCard>;
+
     /**
      * Starts a chat completion returning an iterable generator of tokens.
      * @param content The prompt
@@ -53,6 +83,25 @@ export interface Model {
      */
     prompt(content: ChatMessage, history?: ChatMessage[], options?: PromptOptions): AsyncIterable<ChatResponse>;
 
+    /**
+     * Generates another response to the prompt answered by a stored message.
+     * The history and knowledge bases of the stored chat are used.
+     * Add the response with `fork` below the prompt passed to `onStart`,
+     * so it becomes a sibling of the old one.
+     * @param chatUuid The stored chat
+     * @param messageId The response to replace
+     * @param onStart Receives the ID of the answered prompt
+     * @param options Knowledge bases are ignored
+     */
+    regenerate(chatUuid: string, messageId: string, onStart: (parent: string) => void, options?: PromptOptions): AsyncIterable<ChatResponse>;
+
+    /**
+     * Computes an embedding for every input.
+     * Requires the "embedding" capability.
+     * @param input Texts to embed
+     */
+    embed(input: string[]): Promise<Embeddings>;
+
     /**
      * Checks whether this model can be deleted.
      * If `true`, then the `delete` method is defined.
@@ -60,6 +109,24 @@ export interface Model {
     isDeletable(): this is DeletableModel;
 }
 
+/**
+ * The information of a model including how it has been built.
+ */
+export interface ModelCard extends ModelInfo {
+    // e.g. an Ollama Modelfile
+    modelfile?: string;
+    // System prompt built into the model
+    system?: string;
+    // Default parameters, one "name value" per line
+    parameters?: string;
+}
+
+export interface Embeddings {
+    // One embedding per input in the same order
+    embeddings: number[][];
+    dimension: number;
+}
+
 export const DeletableTag = Symbol("Deleteable");
 export interface DeletableModel extends Model {
     [DeletableTag]: true;
@@ -73,40 +140,57 @@ export interface DeletableModel extends Model {
 export interface PromptOptions {
     think?: boolean;
     abort?: AbortSignal;
+    generation?: GenerationOptions;
+    // Names of the tools the model may call, see `getAvailableTools`
+    tools?: string[];
+    // Maximum number of completions when calling tools
+    maxToolSteps?: number;
+    // UUIDs of knowledge bases to retrieve context from
+    knowledgeBases?: string[];
+    // Stored chat of the prompt, which keeps the summary of the "rollingSummary" context policy
+    chatUuid?: string;
+}
+
+/**
+ * A tool the model may call.
+ * `parameters` is the JSON Schema of its arguments.
+ */
+export interface ToolDefinition {
+    name: string;
+    description: string;
+    parameters: object;
 }
 
 /**
- * Generates a title for a chat history.
- * The `history` needs to have at least two entries!
- * @throws Error if the `history` has less than two entries.
- * @param model The model to use
- * @param history The chat history to generate a title for
- * @returns A title
+ * Options for a single chat completion.
+ * Unset options use the defaults of the backend or model.
  */
-export async function generateTitle(model: Model, history: ChatMessage[]): Promise<string> {
-    if(history.length < 2) {
-        throw new Error("Chat history needs at least two entries");
-    }
-
-    let title = "";
-    const instruction: ChatMessage[] = [
-        {
-            role: "system",
-            content:
-            `You generate a brief title for the given user prompt and chat history.
-            The title should not be longer than five words and not empty.
-            The title must not contain any Markdown or HTML.
-            The title must only contain plain text!
-            You must only output the title and nothing more!`
-        },
-        ...history
-    ];
-    for await(const chunk of model.prompt(instruction[0], instruction)) {
-        title += chunk.message.content;
-    }
-    return title.length > 0 ? title : "Chat";
+export interface GenerationOptions {
+    temperature?: number;
+    topK?: number;
+    topP?: number;
+    minP?: number;
+    repeatPenalty?: number;
+    seed?: number;
+    // Size of the context window in tokens
+    contextLength?: number;
+    // Maximum number of tokens to generate
+    maxTokens?: number;
+    stop?: string[];
+    // e.g. "10m", only supported by Ollama
+    keepAlive?: string;
+    // Constrains the response to JSON
+    format?: ResponseFormat;
+    // How to shorten histories exceeding the context window, defaults to "pinSystem"
+    contextPolicy?: ContextPolicy;
 }
 
+/**
+ * Any JSON or JSON matching a JSON Schema.
+ * The response gets validated after the generation.
+ */
+export type ResponseFormat = {type: "json"} | {type: "jsonSchema", schema: object};
+
 export function prependAssistantContext(history: ChatMessage[]): ChatMessage[] {
     const assistant: ChatMessage = {
         role: "system",
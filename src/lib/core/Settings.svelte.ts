@@ -45,4 +45,5 @@ export default class Settings {
 
     public static AUTO_SCROLL: string = "autoScroll";
     public static SELECTED_MODEL: string = "selectedModel";
+    public static CONTEXT_POLICY: string = "contextPolicy";
 }
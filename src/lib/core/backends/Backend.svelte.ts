@@ -1,7 +1,14 @@
 import { Channel, invoke } from "@tauri-apps/api/core";
-import { DeletableTag, type Backend, type Capability, type DeletableModel, type Model, type PromptOptions } from "$lib/core/LLMBackend";
+import { DeletableTag, type Backend, type Capability, type DeletableModel, type Embeddings, type Model, type ModelCard, type PromptOptions, type ToolDefinition } from "$lib/core/LLMBackend";
 import type { ChatMessage, ChatResponse } from "$lib/core/Chat";
-import { handleError } from "$lib/Util";
+import { describeBackendError, handleError } from "$lib/Util";
+
+/**
+ * Gets all tools which can be passed to `PromptOptions.tools`.
+ */
+export async function getAvailableTools(): Promise<ToolDefinition[]> {
+    return invoke("get_available_tools");
+}
 
 export default abstract class BackendImpl implements Backend {
     abstract readonly name: string;
@@ -27,6 +34,13 @@ export default abstract class BackendImpl implements Backend {
         return models;
     }
 
+    async getEmbeddingModels(): Promise<Model[]> {
+        let res: Model[] = await invoke("get_embedding_models_for_backend", {
+            backendName: this.name,
+        });
+        return res.map(m => this.buildModel(m));
+    }
+
     buildModel(m: Model): Model {
         return new ModelImpl({
             id: m.name,
@@ -87,6 +101,13 @@ export class ModelImpl implements Model {
     readonly id!: string;
     readonly size!: number;
     readonly capabilities!: Capability[];
+    readonly contextLength?: number;
+    readonly parameterCount?: number;
+    readonly quantization?: string;
+    readonly family?: string;
+    readonly template?: string;
+    readonly license?: string;
+    readonly modifiedAt?: string;
     readonly backend!: Backend;
 
     // Ressource identifiers of ongoing chat completions
@@ -144,6 +165,13 @@ export class ModelImpl implements Model {
         });
     }
 
+    async getCard(): Promise<ModelCard> {
+        return invoke("get_model_card", {
+            backendName: this.backend.name,
+            modelName: this.name
+        });
+    }
+
     public async stopAllPrompts() {
         for (const rid of this.promptGenIds) {
             await this.stopPrompt(rid);
@@ -155,26 +183,69 @@ export class ModelImpl implements Model {
         this.promptGenIds.delete(rid);
     }
 
+    embed(input: string[]): Promise<Embeddings> {
+        return invoke("embed", {
+            backendName: this.backend.name,
+            modelName: this.name,
+            input
+        });
+    }
+
     async* prompt(content: ChatMessage, history?: ChatMessage[], options?: PromptOptions): AsyncIterable<ChatResponse> {
         history ??= [];
+        yield* this.stream(options, responseChannel => invoke<number>("prompt_model", {
+            backendName: this.backend.name,
+            modelName: this.name,
+            content,
+            history,
+            think: options?.think ?? false,
+            options: options?.generation,
+            tools: options?.tools,
+            maxToolSteps: options?.maxToolSteps,
+            knowledgeBases: options?.knowledgeBases,
+            chatUuid: options?.chatUuid,
+            responseChannel
+        }));
+    }
+
+    async* regenerate(chatUuid: string, messageId: string, onStart: (parent: string) => void, options?: PromptOptions): AsyncIterable<ChatResponse> {
+        yield* this.stream(options, async responseChannel => {
+            const reply: { rid: number, parent: string } = await invoke("regenerate_chat_message", {
+                backendName: this.backend.name,
+                modelName: this.name,
+                uuid: chatUuid,
+                messageId,
+                think: options?.think ?? false,
+                options: options?.generation,
+                tools: options?.tools,
+                maxToolSteps: options?.maxToolSteps,
+                responseChannel
+            });
+            onStart(reply.parent);
+            return reply.rid;
+        });
+    }
+
+    /**
+     * Streams the responses of a prompt started by `start`,
+     * which returns the resource ID of the prompt.
+     */
+    private async* stream(options: PromptOptions | undefined, start: (responseChannel: Channel<ChatResponse>) => Promise<number>): AsyncIterable<ChatResponse> {
         let rid = -1;
         const stream = new ReadableStream({
             start: async ctrl => {
                 const responseChannel = new Channel<ChatResponse>((chunk) => {
-                    if(chunk.done) {
+                    if(chunk.error) {
+                        ctrl.error(new Error(describeBackendError(chunk.error)));
+                    } else if(chunk.done) {
+                        // The last chunk carries the generation statistics
+                        ctrl.enqueue(chunk);
                         ctrl.close();
                     } else {
                         ctrl.enqueue(chunk);
                     }
                 });
-                rid = await invoke("prompt_model", {
-                    backendName: this.backend.name,
-                    modelName: this.name,
-                    content,
-                    history,
-                    think: options?.think ?? false,
-                    responseChannel
-                });
+                rid = await start(responseChannel);
                 this.promptGenIds.add(rid);
             },
             cancel: async _ => {
@@ -2,6 +2,7 @@ import { SvelteURL } from "svelte/reactivity";
 import BackendImpl, { ModelImpl } from "$lib/core/backends/Backend.svelte";
 import { Channel, invoke } from "@tauri-apps/api/core";
 import { DeletableTag, type DeletableModel, type Model } from "../LLMBackend";
+import type { Role } from "../Chat";
 
 export interface OllamaPullProgress {
     status: string,
@@ -11,6 +12,24 @@ export interface OllamaPullProgress {
     completed?: number
 }
 
+/**
+ * Definition of a model derived from another one.
+ * See https://github.com/ollama/ollama/blob/main/docs/modelfile.md
+ */
+export interface Modelfile {
+    // Base model e.g. llama3.2:3b
+    from: string;
+    system?: string;
+    template?: string;
+    // Parameters in order, e.g. ["stop", "<|eot_id|>"] may occur multiple times
+    parameters?: [string, number|boolean|string][];
+    // Absolute paths of LoRA adapters, uploaded when creating the model
+    adapters?: string[];
+    licenses?: string[];
+    messages?: {role: Role, content: string}[];
+    requires?: string;
+}
+
 export default class OllamaBackend extends BackendImpl {
     // This must be the same string used in the backend
     readonly name: string = "Ollama";
@@ -70,6 +89,69 @@ export default class OllamaBackend extends BackendImpl {
      * @param cb Called for events reporting the download progress
      */
     async pullModel(tag: string, cb: (ev: OllamaPullProgress) => void): Promise<void> {
+        return this.followProgress(cb, progressChannel => invoke("ollama_pull_model", {
+            tag,
+            progressChannel
+        }));
+    }
+
+    /**
+     * Creates a model from a Modelfile, e.g. an existing model with another system prompt.
+     * Adapters are uploaded first, reported like by `importGguf`.
+     * This methods waits until the model has been created
+     * and throws in case of an error.
+     * Call `updateModels` afterwards to use the model.
+     * @param name Name of the new model
+     * @param modelfile Definition of the model
+     * @param cb Called for events reporting the progress
+     * @param quantize Quantization of FP16 or FP32 weights, e.g. q4_K_M
+     */
+    async createModel(name: string, modelfile: Modelfile, cb: (ev: OllamaPullProgress) => void, quantize?: string): Promise<void> {
+        return this.followProgress(cb, progressChannel => invoke("ollama_create_model", {
+            name,
+            modelfile,
+            quantize,
+            progressChannel
+        }));
+    }
+
+    /**
+     * Imports a local GGUF file as a model.
+     * The file is hashed and uploaded to Ollama first, reported
+     * with the statuses "hashing" and "uploading".
+     * This methods waits until the model has been created
+     * and throws in case of an error.
+     * @param name Name of the new model
+     * @param path Path of the GGUF file
+     * @param cb Called for events reporting the progress
+     * @param quantize Quantization of FP16 or FP32 weights, e.g. q4_K_M
+     */
+    async importGguf(name: string, path: string, cb: (ev: OllamaPullProgress) => void, quantize?: string): Promise<void> {
+        return this.followProgress(cb, progressChannel => invoke("ollama_import_gguf", {
+            name,
+            path,
+            quantize,
+            progressChannel
+        }));
+    }
+
+    async parseModelfile(text: string): Promise<Modelfile> {
+        return invoke("ollama_parse_modelfile", { text });
+    }
+
+    async renderModelfile(modelfile: Modelfile): Promise<string> {
+        return invoke("ollama_render_modelfile", { modelfile });
+    }
+
+    /**
+     * Resolves after an operation reporting its progress to a channel succeeded.
+     * @param cb Called for every progress
+     * @param start Starts the operation
+     */
+    private async followProgress(
+        cb: (ev: OllamaPullProgress) => void,
+        start: (progressChannel: Channel<OllamaPullProgress>) => Promise<void>
+    ): Promise<void> {
         return new Promise(async (resolve, reject) => {
             let succeeded = false;
             const progressChannel = new Channel<OllamaPullProgress>(ev => {
@@ -81,10 +163,11 @@ export default class OllamaBackend extends BackendImpl {
                     reject(ev.error ?? "Unknown");
                 }
             });
-            await invoke("ollama_pull_model", {
-                tag,
-                progressChannel: progressChannel
-            });
+            try {
+                await start(progressChannel);
+            } catch(e) {
+                reject(e);
+            }
         });
     }
 }